@@ -1,6 +1,27 @@
+pub mod conditional;
+pub mod diagnostics;
+pub mod extensions;
+pub mod extract;
+pub mod http_date;
+pub mod introspect;
+pub mod json;
+pub mod live_routes;
+pub mod middleware;
+pub mod mime;
+pub mod multipart;
+pub mod openapi;
+pub mod range;
 pub mod request;
 pub mod response;
+#[cfg(test)]
+mod route_bench;
+pub mod route_group;
+pub mod route_names;
+pub mod route_radix;
 pub mod route_table;
 pub mod route_trie;
 pub mod router;
 pub mod routing;
+pub mod safe_path;
+pub mod shared_str;
+pub mod urlencoded;
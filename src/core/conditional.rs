@@ -0,0 +1,358 @@
+//! Validators and conditional requests, RFC 9110 sections 8.8 and 13.
+
+use crate::core::http_date;
+use crate::core::middleware::Next;
+use crate::core::request::Request;
+use crate::core::response::{Response, StatusCode};
+use crate::core::routing::Identifiers;
+use std::fmt;
+use std::fs::Metadata;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// An entity tag, the quotes are not part of `tag`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ETag {
+    pub weak: bool,
+    pub tag: String,
+}
+
+impl ETag {
+    pub fn strong(tag: impl Into<String>) -> ETag {
+        return ETag {
+            weak: false,
+            tag: tag.into(),
+        };
+    }
+
+    pub fn weak(tag: impl Into<String>) -> ETag {
+        return ETag {
+            weak: true,
+            tag: tag.into(),
+        };
+    }
+
+    /// Strong tag from the size and modification time, changes whenever a write is noticed by
+    /// the file system without reading the file.
+    pub fn from_metadata(len: u64, modified: Option<SystemTime>) -> ETag {
+        let nanos = modified
+            .and_then(|m| return m.duration_since(UNIX_EPOCH).ok())
+            .map(|d| return d.as_nanos())
+            .unwrap_or(0);
+        return ETag::strong(format!("{:x}-{:x}", len, nanos));
+    }
+
+    /// Strong tag from a hash of the content itself.
+    pub fn from_content(content: &[u8]) -> ETag {
+        return ETag::strong(format!("{:016x}", fnv1a(content)));
+    }
+
+    /// Reads a single tag such as `"abc"` or `W/"abc"`.
+    pub fn parse(s: &str) -> Option<ETag> {
+        let (tags, rest) = parse_list(s.trim())?;
+        if !rest.trim().is_empty() {
+            return None;
+        }
+        let [tag] = &tags[..] else {
+            return None;
+        };
+        return Some(tag.clone());
+    }
+
+    pub fn strong_eq(&self, other: &ETag) -> bool {
+        return !self.weak && !other.weak && self.tag == other.tag;
+    }
+
+    pub fn weak_eq(&self, other: &ETag) -> bool {
+        return self.tag == other.tag;
+    }
+}
+
+impl fmt::Display for ETag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.weak {
+            return write!(f, "W/\"{}\"", self.tag);
+        }
+        return write!(f, "\"{}\"", self.tag);
+    }
+}
+
+// stable across builds, unlike the hasher of the standard library
+fn fnv1a(content: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in content {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    return hash;
+}
+
+// tags of an `If-Match` or `If-None-Match` list and whatever did not parse
+fn parse_list(s: &str) -> Option<(Vec<ETag>, &str)> {
+    let mut tags = Vec::new();
+    let mut rest = s;
+    loop {
+        rest = rest.trim_start_matches(|c: char| return c == ',' || c.is_ascii_whitespace());
+        if rest.is_empty() {
+            return Some((tags, rest));
+        }
+        let (weak, quoted) = match rest.strip_prefix("W/") {
+            Some(quoted) => (true, quoted),
+            None => (false, rest),
+        };
+        let (tag, after) = quoted.strip_prefix('"')?.split_once('"')?;
+        tags.push(ETag {
+            weak,
+            tag: tag.to_string(),
+        });
+        rest = after;
+    }
+}
+
+enum Condition {
+    Any,
+    Tags(Vec<ETag>),
+}
+
+fn condition(header: &str) -> Option<Condition> {
+    if header.trim() == "*" {
+        return Some(Condition::Any);
+    }
+    let (tags, _) = parse_list(header)?;
+    return Some(Condition::Tags(tags));
+}
+
+/// What a representation can be recognized by, for answering conditional requests about it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Validators {
+    pub etag: Option<ETag>,
+    pub last_modified: Option<SystemTime>,
+    // a request about a resource that does not exist yet, `If-None-Match: *` creates only
+    pub exists: bool,
+}
+
+impl Validators {
+    /// A resource with no current representation.
+    pub fn missing() -> Validators {
+        return Validators::default();
+    }
+
+    pub fn for_file(metadata: &Metadata) -> Validators {
+        let modified = metadata.modified().ok();
+        return Validators {
+            etag: Some(ETag::from_metadata(metadata.len(), modified)),
+            last_modified: modified,
+            exists: true,
+        };
+    }
+
+    pub fn for_content(content: &[u8]) -> Validators {
+        return Validators {
+            etag: Some(ETag::from_content(content)),
+            last_modified: None,
+            exists: true,
+        };
+    }
+
+    /// The answer to the preconditions of `req` when they stop it from being handled as usual,
+    /// 304 for a cache that is already up to date and 412 for a failed precondition.
+    ///
+    /// Preconditions are evaluated in the order of RFC 9110 section 13.2.2. A date header is only
+    /// looked at when the matching entity tag header is absent, and is ignored if unreadable.
+    pub fn evaluate(&self, req: &Request) -> Option<Response> {
+        let safe = req.method.is_get();
+        if let Some(if_match) = req.header("If-Match").and_then(condition) {
+            let matched = match if_match {
+                Condition::Any => self.exists,
+                Condition::Tags(tags) => self.matches(&tags, ETag::strong_eq),
+            };
+            if !matched {
+                return Some(self.precondition_failed());
+            }
+        } else if let Some(since) = req.header("If-Unmodified-Since").and_then(http_date::parse) {
+            if self.modified_after(since) {
+                return Some(self.precondition_failed());
+            }
+        }
+
+        if let Some(if_none_match) = req.header("If-None-Match").and_then(condition) {
+            let matched = match if_none_match {
+                Condition::Any => self.exists,
+                Condition::Tags(tags) => self.matches(&tags, ETag::weak_eq),
+            };
+            if matched {
+                return Some(self.failed_none_match(safe));
+            }
+        } else if let Some(since) = req.header("If-Modified-Since").and_then(http_date::parse) {
+            if safe && self.exists && self.last_modified.is_some() && !self.modified_after(since) {
+                return Some(self.not_modified());
+            }
+        }
+        return None;
+    }
+
+    /// Sets `ETag` and `Last-Modified` on a response for this representation.
+    pub fn apply(&self, response: &mut Response) {
+        if let Some(etag) = &self.etag {
+            response.set_header("ETag", etag.to_string());
+        }
+        if let Some(modified) = self.last_modified {
+            response.set_header("Last-Modified", http_date::format(modified));
+        }
+    }
+
+    fn matches(&self, tags: &[ETag], eq: fn(&ETag, &ETag) -> bool) -> bool {
+        let Some(etag) = &self.etag else {
+            return false;
+        };
+        return self.exists && tags.iter().any(|tag| return eq(tag, etag));
+    }
+
+    fn modified_after(&self, since: SystemTime) -> bool {
+        return self
+            .last_modified
+            .is_some_and(|modified| return http_date::truncate(modified) > since);
+    }
+
+    fn failed_none_match(&self, safe: bool) -> Response {
+        if safe {
+            return self.not_modified();
+        }
+        return self.precondition_failed();
+    }
+
+    fn not_modified(&self) -> Response {
+        let mut response = Response::new(StatusCode::NOT_MODIFIED);
+        self.apply(&mut response);
+        return response;
+    }
+
+    fn precondition_failed(&self) -> Response {
+        return Response::new(StatusCode::PRECONDITION_FAILED);
+    }
+}
+
+/// Layer for handlers that build their responses in memory. Successful `GET` and `HEAD` responses get a
+/// content hash `ETag` unless they set one, and a `304` when the client already has them.
+///
+/// The handler still runs for every request, this saves the transfer and not the work.
+pub fn conditional_get<Context>(
+    req: &Request,
+    path_vals: &Identifiers,
+    ctx: &Context,
+    next: Next<Context>,
+) -> Response {
+    let mut response = next.run(req, path_vals, ctx);
+    if !req.method.is_get() || response.status != StatusCode::OK {
+        return response;
+    }
+    let etag = response.header("ETag").and_then(ETag::parse);
+    let last_modified = response.header("Last-Modified").and_then(http_date::parse);
+    let validators = Validators {
+        etag: Some(etag.unwrap_or_else(|| return ETag::from_content(&response.body))),
+        last_modified,
+        exists: true,
+    };
+    if let Some(answer) = validators.evaluate(req) {
+        return answer;
+    }
+    validators.apply(&mut response);
+    return response;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::conditional::{conditional_get, ETag, Validators};
+    use crate::core::http_date;
+    use crate::core::middleware::Layered;
+    use crate::core::request::Request;
+    use crate::core::response::{Response, StatusCode};
+    use crate::core::routing::{Captures, Handler, Identifiers};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn conditional_etags() {
+        assert_eq!(ETag::parse("\"abc\""), Some(ETag::strong("abc")));
+        assert_eq!(ETag::parse(" W/\"a,b\" "), Some(ETag::weak("a,b")));
+        assert_eq!(ETag::parse("abc"), None);
+        assert_eq!(ETag::parse("\"a\", \"b\""), None);
+        assert_eq!(ETag::weak("x").to_string(), "W/\"x\"");
+
+        assert!(ETag::strong("x").strong_eq(&ETag::strong("x")));
+        assert!(!ETag::weak("x").strong_eq(&ETag::strong("x")));
+        assert!(ETag::weak("x").weak_eq(&ETag::strong("x")));
+        assert_eq!(ETag::from_content(b"abc"), ETag::from_content(b"abc"));
+        assert_ne!(ETag::from_content(b"abc"), ETag::from_content(b"abd"));
+    }
+
+    fn status(validators: &Validators, method: &str, headers: &str) -> Option<StatusCode> {
+        let raw = format!("{} /r HTTP/1.1\r\n{}\r\n", method, headers);
+        let request = Request::from(raw.as_bytes()).unwrap();
+        return validators.evaluate(&request).map(|r| return r.status);
+    }
+
+    #[test]
+    fn conditional_evaluate() {
+        let modified = UNIX_EPOCH + Duration::from_secs(784111777);
+        let current = Validators {
+            etag: Some(ETag::strong("v2")),
+            last_modified: Some(modified + Duration::from_millis(300)),
+            exists: true,
+        };
+        let date = http_date::format(modified);
+        let earlier = http_date::format(modified - Duration::from_secs(60));
+        let not_modified = Some(StatusCode::NOT_MODIFIED);
+        let failed = Some(StatusCode::PRECONDITION_FAILED);
+
+        assert_eq!(status(&current, "GET", ""), None);
+        let header = "If-None-Match: \"v1\", W/\"v2\"\r\n";
+        assert_eq!(status(&current, "GET", header), not_modified);
+        assert_eq!(status(&current, "POST", header), failed);
+        assert_eq!(status(&current, "GET", "If-None-Match: \"v1\"\r\n"), None);
+        let since = format!("If-Modified-Since: {}\r\n", date);
+        assert_eq!(status(&current, "GET", &since), not_modified);
+        let since = format!("If-Modified-Since: {}\r\n", earlier);
+        assert_eq!(status(&current, "GET", &since), None);
+        // the entity tag wins over the date
+        let both = format!("If-None-Match: \"v1\"\r\nIf-Modified-Since: {}\r\n", date);
+        assert_eq!(status(&current, "GET", &both), None);
+
+        assert_eq!(status(&current, "POST", "If-Match: \"v2\"\r\n"), None);
+        assert_eq!(status(&current, "POST", "If-Match: W/\"v2\"\r\n"), failed);
+        assert_eq!(status(&current, "POST", "If-Match: *\r\n"), None);
+        let unmodified = format!("If-Unmodified-Since: {}\r\n", earlier);
+        assert_eq!(status(&current, "POST", &unmodified), failed);
+        let unmodified = format!("If-Unmodified-Since: {}\r\n", date);
+        assert_eq!(status(&current, "POST", &unmodified), None);
+        assert_eq!(
+            status(&current, "POST", "If-Unmodified-Since: soon\r\n"),
+            None
+        );
+
+        let missing = Validators::missing();
+        assert_eq!(status(&missing, "POST", "If-None-Match: *\r\n"), None);
+        assert_eq!(status(&current, "POST", "If-None-Match: *\r\n"), failed);
+        assert_eq!(status(&missing, "POST", "If-Match: *\r\n"), failed);
+    }
+
+    #[test]
+    fn conditional_get_layer() {
+        let page = |_req: &Request, _ids: &Identifiers, _ctx: &()| return "page";
+        let handler = Layered::new(page).layer(conditional_get);
+        let call = |headers: &str| -> Response {
+            let raw = format!("GET / HTTP/1.1\r\n{}\r\n", headers);
+            let request = Request::from(raw.as_bytes()).unwrap();
+            let ids = Identifiers::new(Captures::new());
+            return handler.call(&request, &ids, &());
+        };
+
+        let first = call("");
+        assert_eq!(first.status, StatusCode::OK);
+        let etag = first.header("ETag").unwrap().to_string();
+        assert_eq!(etag, ETag::from_content(b"page").to_string());
+
+        let again = call(&format!("If-None-Match: {}\r\n", etag));
+        assert_eq!(again.status, StatusCode::NOT_MODIFIED);
+        assert!(again.body.is_empty());
+        assert_eq!(again.header("ETag"), Some(etag.as_str()));
+    }
+}
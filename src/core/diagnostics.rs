@@ -0,0 +1,258 @@
+use crate::core::routing::{Route, Routeable, Segments};
+use crate::core::shared_str::SharedStr;
+use itertools::Itertools;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ConflictKind {
+    /// Both routes match exactly the same paths, so one of them can never match.
+    Duplicate,
+    /// Some paths described by the route never reach it since a literal of another route wins first.
+    Shadowed,
+    /// Both routes match some path and neither is more specific, precedence is left to segment order.
+    Ambiguous,
+}
+
+#[derive(Debug)]
+pub struct Conflict {
+    pub kind: ConflictKind,
+    pub route: SharedStr,
+    // the route that takes precedence over `route`
+    pub other: SharedStr,
+    // a request path that runs into the conflict
+    pub example: String,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            ConflictKind::Duplicate => {
+                return write!(
+                    f,
+                    "duplicate: {} matches the same paths as {}",
+                    self.route, self.other
+                );
+            }
+            ConflictKind::Shadowed => {
+                return write!(
+                    f,
+                    "shadowed: {} never receives {} because {} takes precedence",
+                    self.route, self.example, self.other
+                );
+            }
+            ConflictKind::Ambiguous => {
+                return write!(
+                    f,
+                    "ambiguous: {} matches both {} and {}, {} takes precedence",
+                    self.example, self.route, self.other, self.other
+                );
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RouteReport {
+    // in the order the routes are tried
+    pub routes: Vec<SharedStr>,
+    pub conflicts: Vec<Conflict>,
+}
+
+impl RouteReport {
+    pub fn is_clean(&self) -> bool {
+        return self.conflicts.is_empty();
+    }
+}
+
+impl fmt::Display for RouteReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Registered routes ({}):", self.routes.len())?;
+        for route in &self.routes {
+            writeln!(f, "    {}", route)?;
+        }
+        if self.is_clean() {
+            return write!(f, "No route conflicts found.");
+        }
+        write!(f, "Route conflicts ({}):", self.conflicts.len())?;
+        for conflict in &self.conflicts {
+            write!(f, "\n    {}", conflict)?;
+        }
+        return Ok(());
+    }
+}
+
+// compares two routes where `first` is tried before `second`
+fn compare<Context>(
+    first: &Route<Context>,
+    second: &Route<Context>,
+) -> Option<(ConflictKind, String)> {
+    let same_len = first.path_segments.len() == second.path_segments.len();
+    let mut first_specific = false;
+    let mut second_specific = false;
+    let mut example = Vec::new();
+
+    for (i, (a, b)) in first
+        .path_segments
+        .iter()
+        .zip(second.path_segments.iter())
+        .enumerate()
+    {
+        match (a, b) {
+            (Segments::Literal(x), Segments::Literal(y)) => {
+                if !first.policy.literal_matches(x, y) {
+                    return None;
+                }
+                example.push(a.to_string());
+            }
+            (Segments::Literal(_), Segments::Capture(_)) => {
+                example.push(a.to_string());
+                if !same_len {
+                    // no path matches both, but the literal may still pull paths of `second` away
+                    example.extend(
+                        second.path_segments[i + 1..]
+                            .iter()
+                            .map(|s| return s.to_string()),
+                    );
+                    return Some((ConflictKind::Shadowed, example.join("/")));
+                }
+                first_specific = true;
+            }
+            (Segments::Capture(_), Segments::Literal(_)) => {
+                if !same_len {
+                    return None;
+                }
+                second_specific = true;
+                example.push(b.to_string());
+            }
+            (Segments::Capture(_), Segments::Capture(_)) => {
+                example.push(a.to_string());
+            }
+        }
+    }
+
+    if !same_len {
+        return None;
+    }
+    let example = example.join("/");
+    match (first_specific, second_specific) {
+        (false, false) => return Some((ConflictKind::Duplicate, example)),
+        (true, true) => return Some((ConflictKind::Ambiguous, example)),
+        (true, false) => return Some((ConflictKind::Shadowed, example)),
+        (false, true) => return None,
+    }
+}
+
+/// Checks every pair of registered routes against each other.
+///
+/// Shadowing is only reported when the routing structure actually fails to dispatch the example
+/// path, so the result depends on the `Routeable` implementation and not just the patterns.
+pub fn validate<Context, T: Routeable<Context>>(routes: &T) -> RouteReport {
+    let ordered = routes.routes().into_iter().sorted().collect_vec();
+    let mut conflicts = Vec::new();
+
+    for (i, first) in ordered.iter().enumerate() {
+        for second in &ordered[i + 1..] {
+            let Some((kind, example)) = compare(first, second) else {
+                continue;
+            };
+            if kind == ConflictKind::Shadowed {
+                // the more specific route is meant to win, only a dead end is a problem
+                if second.matches(&example).is_none() || routes.match_route(&example).is_some() {
+                    continue;
+                }
+            }
+            conflicts.push(Conflict {
+                kind,
+                route: second.path.clone(),
+                other: first.path.clone(),
+                example,
+            });
+        }
+    }
+
+    return RouteReport {
+        routes: ordered.iter().map(|r| return r.path.clone()).collect_vec(),
+        conflicts,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::diagnostics::{validate, ConflictKind};
+    use crate::core::request::Request;
+    use crate::core::route_table::RouteTable;
+    use crate::core::route_trie::RouteTrie;
+    use crate::core::routing::{Identifiers, Routeable};
+    use anyhow::Result;
+
+    struct PlaceholderContext {}
+
+    fn thunk(_req: &Request, _paths: &Identifiers, _ctx: &PlaceholderContext) -> Result<Vec<u8>> {
+        return Ok(vec![1, 2, 3]);
+    }
+
+    fn build<T: Routeable<PlaceholderContext>>(paths: &[&'static str]) -> T {
+        let mut routes = T::new();
+        for path in paths {
+            routes.add_route(*path, thunk).unwrap();
+        }
+        return routes;
+    }
+
+    #[test]
+    fn report_clean() {
+        let trie: RouteTrie<_> = build(&["/", "/echo/{msg}", "/user-agent", "/files/{file}"]);
+        let report = validate(&trie);
+        assert!(report.is_clean());
+        assert_eq!(
+            report.routes,
+            vec!["/", "/echo/{msg}", "/files/{file}", "/user-agent"]
+        );
+    }
+
+    #[test]
+    fn report_specialization_is_not_a_conflict() {
+        let trie: RouteTrie<_> = build(&["/user/dashboard", "/user/{id}"]);
+        assert!(validate(&trie).is_clean());
+    }
+
+    #[test]
+    fn report_trie_shadowed() {
+        let trie: RouteTrie<_> = build(&["/user/dashboard", "/user/{id}/settings"]);
+        let report = validate(&trie);
+        assert_eq!(report.conflicts.len(), 1);
+        let conflict = &report.conflicts[0];
+        assert_eq!(conflict.kind, ConflictKind::Shadowed);
+        assert_eq!(conflict.route, "/user/{id}/settings");
+        assert_eq!(conflict.other, "/user/dashboard");
+        assert_eq!(conflict.example, "/user/dashboard/settings");
+    }
+
+    #[test]
+    fn report_table_not_shadowed() {
+        // the table tries every route so it does not get stuck on the literal
+        let table: RouteTable<_> = build(&["/user/dashboard", "/user/{id}/settings"]);
+        assert!(validate(&table).is_clean());
+    }
+
+    #[test]
+    fn report_ambiguous() {
+        let table: RouteTable<_> = build(&["/{a}/x", "/x/{b}"]);
+        let report = validate(&table);
+        assert_eq!(report.conflicts.len(), 1);
+        let conflict = &report.conflicts[0];
+        assert_eq!(conflict.kind, ConflictKind::Ambiguous);
+        assert_eq!(conflict.route, "/{a}/x");
+        assert_eq!(conflict.other, "/x/{b}");
+        assert_eq!(conflict.example, "/x/x");
+    }
+
+    #[test]
+    fn report_display() {
+        let trie: RouteTrie<_> = build(&["/user/dashboard", "/user/{id}/settings"]);
+        let report = validate(&trie).to_string();
+        assert!(report.contains("Registered routes (2):"));
+        assert!(report.contains("Route conflicts (1):"));
+        assert!(report.contains("shadowed: /user/{id}/settings never receives"));
+    }
+}
@@ -0,0 +1,127 @@
+use std::any::{type_name, Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+type Value = Arc<dyn Any + Send + Sync>;
+
+/// Values keyed by their type, for layers to hand data to handlers or to the layers outside them.
+///
+/// Values are shared, cloning the map (and the request or response holding it) does not copy
+/// them. A layer adds to the request by passing a modified clone on to `next.run`.
+#[derive(Clone, Default)]
+pub struct Extensions {
+    // the type name is only kept for `Debug`
+    values: HashMap<TypeId, (&'static str, Value)>,
+}
+
+impl Extensions {
+    pub fn new() -> Extensions {
+        return Extensions::default();
+    }
+
+    /// Stores `value`, handing back the value of the same type it replaces.
+    pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) -> Option<Arc<T>> {
+        let previous = self
+            .values
+            .insert(TypeId::of::<T>(), (type_name::<T>(), Arc::new(value)))?;
+        return downcast(previous.1);
+    }
+
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        let (_, value) = self.values.get(&TypeId::of::<T>())?;
+        return value.downcast_ref();
+    }
+
+    /// The stored value itself rather than a borrow, for keeping it past the map.
+    pub fn get_shared<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        let (_, value) = self.values.get(&TypeId::of::<T>())?;
+        return downcast(value.clone());
+    }
+
+    pub fn remove<T: Send + Sync + 'static>(&mut self) -> Option<Arc<T>> {
+        let (_, value) = self.values.remove(&TypeId::of::<T>())?;
+        return downcast(value);
+    }
+
+    pub fn contains<T: Send + Sync + 'static>(&self) -> bool {
+        return self.values.contains_key(&TypeId::of::<T>());
+    }
+
+    pub fn len(&self) -> usize {
+        return self.values.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.values.is_empty();
+    }
+}
+
+fn downcast<T: Send + Sync + 'static>(value: Value) -> Option<Arc<T>> {
+    return value.downcast().ok();
+}
+
+impl fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return f
+            .debug_set()
+            .entries(self.values.values().map(|(name, _)| return name))
+            .finish();
+    }
+}
+
+// values are not comparable, maps are equal when they share the very same values
+impl PartialEq for Extensions {
+    fn eq(&self, other: &Self) -> bool {
+        return self.values.len() == other.values.len()
+            && self.values.iter().all(|(key, (_, value))| {
+                return other
+                    .values
+                    .get(key)
+                    .is_some_and(|(_, theirs)| return Arc::ptr_eq(value, theirs));
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::extensions::Extensions;
+
+    #[derive(Debug, PartialEq)]
+    struct User(String);
+
+    #[test]
+    fn extensions_by_type() {
+        let mut extensions = Extensions::new();
+        assert!(extensions.insert(User(String::from("ada"))).is_none());
+        assert!(extensions.insert(7u32).is_none());
+        assert_eq!(extensions.get::<User>(), Some(&User(String::from("ada"))));
+        assert_eq!(extensions.get::<u32>(), Some(&7));
+        assert_eq!(extensions.get::<u64>(), None);
+
+        let replaced = extensions.insert(User(String::from("bob"))).unwrap();
+        assert_eq!(*replaced, User(String::from("ada")));
+        assert_eq!(extensions.len(), 2);
+        assert_eq!(*extensions.remove::<u32>().unwrap(), 7);
+        assert!(!extensions.contains::<u32>());
+        assert_eq!(
+            *extensions.get_shared::<User>().unwrap(),
+            User(String::from("bob"))
+        );
+    }
+
+    #[test]
+    fn extensions_clones_share_values() {
+        let mut extensions = Extensions::new();
+        extensions.insert(User(String::from("ada")));
+        let mut copy = extensions.clone();
+        assert_eq!(copy, extensions);
+
+        copy.insert(User(String::from("ada")));
+        assert_ne!(copy, extensions);
+        assert_eq!(
+            format!("{:?}", extensions),
+            format!("{{{:?}}}", std::any::type_name::<User>())
+        );
+    }
+}
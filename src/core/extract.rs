@@ -0,0 +1,641 @@
+//! Typed handler arguments pulled out of the request.
+//!
+//! A handler written as `fn(Path<(u32, String)>, Header<UserAgent>) -> impl IntoResponse` is turned
+//! into a regular `Handler` with `extract`. Arguments are extracted in order, the first one that
+//! fails answers the request with its `Rejection` and the handler does not run.
+
+use crate::core::request::Request;
+use crate::core::response::{IntoResponse, Response, StatusCode};
+use crate::core::routing::{Captures, Handler, Identifiers};
+use crate::core::urlencoded::{self, Params};
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Why an argument could not be extracted, answered instead of running the handler.
+#[derive(Debug)]
+pub struct Rejection {
+    pub status: StatusCode,
+    pub message: String,
+}
+
+impl Rejection {
+    pub fn bad_request(message: impl Into<String>) -> Rejection {
+        return Rejection {
+            status: StatusCode::BAD_REQUEST,
+            message: message.into(),
+        };
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Rejection {
+        return Rejection {
+            status: StatusCode::NOT_FOUND,
+            message: message.into(),
+        };
+    }
+
+    pub fn unsupported_media_type(message: impl Into<String>) -> Rejection {
+        return Rejection {
+            status: StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            message: message.into(),
+        };
+    }
+
+    // the handler asked for something the route can never provide
+    pub fn internal(message: impl Into<String>) -> Rejection {
+        return Rejection {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            message: message.into(),
+        };
+    }
+}
+
+impl IntoResponse for Rejection {
+    fn into_response(self) -> Response {
+        return (self.status, self.message).into_response();
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "{}: {}", self.status, self.message);
+    }
+}
+
+pub trait FromRequest<Context>: Sized {
+    fn from_request(
+        req: &Request,
+        path_vals: &Identifiers,
+        ctx: &Context,
+    ) -> Result<Self, Rejection>;
+}
+
+// an optional argument is missing rather than rejected
+impl<Context, T: FromRequest<Context>> FromRequest<Context> for Option<T> {
+    fn from_request(
+        req: &Request,
+        path_vals: &Identifiers,
+        ctx: &Context,
+    ) -> Result<Self, Rejection> {
+        return Ok(T::from_request(req, path_vals, ctx).ok());
+    }
+}
+
+/// The captures of the route, a single value, a tuple in route order, or a map by key.
+pub struct Path<T>(pub T);
+
+pub trait FromCaptures: Sized {
+    fn from_captures(captures: &Captures) -> Result<Self, Rejection>;
+}
+
+impl<Context, T: FromCaptures> FromRequest<Context> for Path<T> {
+    fn from_request(
+        _req: &Request,
+        path_vals: &Identifiers,
+        _ctx: &Context,
+    ) -> Result<Self, Rejection> {
+        return Ok(Path(T::from_captures(&path_vals.path_values)?));
+    }
+}
+
+// the path does not have the shape the handler reads, there is nothing to serve at it
+fn expect_captures(captures: &Captures, n: usize) -> Result<(), Rejection> {
+    if captures.len() != n {
+        return Err(Rejection::not_found(format!(
+            "Route captures {} value(s) but the handler expects {}",
+            captures.len(),
+            n
+        )));
+    }
+    return Ok(());
+}
+
+fn parse_capture<T>(key: &str, value: &str) -> Result<T, Rejection>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    let invalid = |e: &dyn fmt::Display| {
+        return Rejection::bad_request(format!("Invalid value for {{{}}}: {}", key, e));
+    };
+    let decoded = urlencoded::percent_decode(value).map_err(|e| return invalid(&e))?;
+    return decoded.parse().map_err(|e| return invalid(&e));
+}
+
+macro_rules! from_captures_value {
+    ($($t:ty),+) => {
+        $(
+            impl FromCaptures for $t {
+                fn from_captures(captures: &Captures) -> Result<Self, Rejection> {
+                    expect_captures(captures, 1)?;
+                    let (key, value) = captures.iter().next().copied().unwrap_or(("", ""));
+                    return parse_capture(key, value);
+                }
+            }
+        )+
+    };
+}
+
+from_captures_value!(String, bool, char, u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+macro_rules! from_captures_tuple {
+    ($n:expr; $($t:ident),+) => {
+        impl<$($t),+> FromCaptures for ($($t,)+)
+        where
+            $($t: FromStr, $t::Err: fmt::Display),+
+        {
+            fn from_captures(captures: &Captures) -> Result<Self, Rejection> {
+                expect_captures(captures, $n)?;
+                let mut entries = captures.iter();
+                return Ok(($({
+                    let (key, value) = entries.next().copied().unwrap_or(("", ""));
+                    parse_capture::<$t>(key, value)?
+                },)+));
+            }
+        }
+    };
+}
+
+from_captures_tuple!(1; T1);
+from_captures_tuple!(2; T1, T2);
+from_captures_tuple!(3; T1, T2, T3);
+from_captures_tuple!(4; T1, T2, T3, T4);
+
+impl FromCaptures for HashMap<String, String> {
+    fn from_captures(captures: &Captures) -> Result<Self, Rejection> {
+        return captures
+            .iter()
+            .map(|(key, value)| return Ok((key.to_string(), parse_capture(key, value)?)))
+            .collect();
+    }
+}
+
+/// The decoded query string, a missing query reads as an empty one.
+pub struct Query<T>(pub T);
+
+pub trait FromQuery: Sized {
+    fn from_query(pairs: Vec<(String, String)>) -> Result<Self, Rejection>;
+}
+
+impl<Context, T: FromQuery> FromRequest<Context> for Query<T> {
+    fn from_request(
+        req: &Request,
+        _path_vals: &Identifiers,
+        _ctx: &Context,
+    ) -> Result<Self, Rejection> {
+        let params = req
+            .query_params()
+            .map_err(|e| return Rejection::bad_request(format!("Invalid query: {}", e)))?;
+        return Ok(Query(T::from_query(params.into_pairs())?));
+    }
+}
+
+/// The decoded `application/x-www-form-urlencoded` body, read into `T` like a query. Bodies of
+/// another type are rejected with 415.
+pub struct Form<T>(pub T);
+
+impl<Context, T: FromQuery> FromRequest<Context> for Form<T> {
+    fn from_request(
+        req: &Request,
+        _path_vals: &Identifiers,
+        _ctx: &Context,
+    ) -> Result<Self, Rejection> {
+        let params = req.form().map_err(|e| {
+            return Rejection {
+                status: e.status(),
+                message: e.to_string(),
+            };
+        })?;
+        return Ok(Form(T::from_query(params.into_pairs())?));
+    }
+}
+
+// repeated keys keep the last value
+impl FromQuery for HashMap<String, String> {
+    fn from_query(pairs: Vec<(String, String)>) -> Result<Self, Rejection> {
+        return Ok(pairs.into_iter().collect());
+    }
+}
+
+impl FromQuery for Vec<(String, String)> {
+    fn from_query(pairs: Vec<(String, String)>) -> Result<Self, Rejection> {
+        return Ok(pairs);
+    }
+}
+
+// every value of a repeated key
+impl FromQuery for Params {
+    fn from_query(pairs: Vec<(String, String)>) -> Result<Self, Rejection> {
+        return Ok(Params::from(pairs));
+    }
+}
+
+/// A single header decoded into `H`, missing or malformed headers are rejected.
+pub struct Header<H>(pub H);
+
+pub trait TypedHeader: Sized {
+    const NAME: &'static str;
+    fn decode(value: &str) -> Result<Self, String>;
+}
+
+impl<Context, H: TypedHeader> FromRequest<Context> for Header<H> {
+    fn from_request(
+        req: &Request,
+        _path_vals: &Identifiers,
+        _ctx: &Context,
+    ) -> Result<Self, Rejection> {
+        let Some(value) = req.header(H::NAME) else {
+            return Err(Rejection::bad_request(format!(
+                "Missing header {}",
+                H::NAME
+            )));
+        };
+        let header = H::decode(value).map_err(|e| {
+            return Rejection::bad_request(format!("Invalid header {}: {}", H::NAME, e));
+        })?;
+        return Ok(Header(header));
+    }
+}
+
+pub struct UserAgent(pub String);
+
+impl TypedHeader for UserAgent {
+    const NAME: &'static str = "User-Agent";
+
+    fn decode(value: &str) -> Result<Self, String> {
+        return Ok(UserAgent(value.to_string()));
+    }
+}
+
+pub struct Host(pub String);
+
+impl TypedHeader for Host {
+    const NAME: &'static str = "Host";
+
+    fn decode(value: &str) -> Result<Self, String> {
+        return Ok(Host(value.to_string()));
+    }
+}
+
+pub struct ContentType(pub String);
+
+impl TypedHeader for ContentType {
+    const NAME: &'static str = "Content-Type";
+
+    fn decode(value: &str) -> Result<Self, String> {
+        return Ok(ContentType(value.to_string()));
+    }
+}
+
+pub struct ContentLength(pub usize);
+
+impl TypedHeader for ContentLength {
+    const NAME: &'static str = "Content-Length";
+
+    fn decode(value: &str) -> Result<Self, String> {
+        return value
+            .parse()
+            .map(ContentLength)
+            .map_err(|e| return format!("{}", e));
+    }
+}
+
+/// The request body, a request without one reads as empty.
+pub struct Body<T>(pub T);
+
+pub trait FromBody: Sized {
+    /// Whether a body of this media type can be read, a request without a type always can.
+    fn accepts(_media_type: &str) -> bool {
+        return true;
+    }
+    fn from_body(body: &[u8]) -> Result<Self, Rejection>;
+}
+
+impl<Context, T: FromBody> FromRequest<Context> for Body<T> {
+    fn from_request(
+        req: &Request,
+        _path_vals: &Identifiers,
+        _ctx: &Context,
+    ) -> Result<Self, Rejection> {
+        if let Some(content_type) = req.header("Content-Type") {
+            let media_type = content_type.split(';').next().unwrap_or("").trim();
+            if !T::accepts(media_type) {
+                return Err(Rejection::unsupported_media_type(format!(
+                    "Unsupported content type {}",
+                    content_type
+                )));
+            }
+        }
+        return Ok(Body(T::from_body(req.body.unwrap_or_default())?));
+    }
+}
+
+impl FromBody for Bytes {
+    fn from_body(body: &[u8]) -> Result<Self, Rejection> {
+        return Ok(Bytes::copy_from_slice(body));
+    }
+}
+
+impl FromBody for String {
+    fn accepts(media_type: &str) -> bool {
+        return media_type.starts_with("text/");
+    }
+
+    fn from_body(body: &[u8]) -> Result<Self, Rejection> {
+        let Ok(body) = std::str::from_utf8(body) else {
+            return Err(Rejection::bad_request("Body is not UTF-8"));
+        };
+        return Ok(body.to_string());
+    }
+}
+
+/// Part of the context, whatever `FromContext` makes of it.
+pub struct State<T>(pub T);
+
+pub trait FromContext<Context> {
+    fn from_context(ctx: &Context) -> Self;
+}
+
+impl<T: Clone> FromContext<T> for T {
+    fn from_context(ctx: &T) -> Self {
+        return ctx.clone();
+    }
+}
+
+impl<Context, T: FromContext<Context>> FromRequest<Context> for State<T> {
+    fn from_request(
+        _req: &Request,
+        _path_vals: &Identifiers,
+        ctx: &Context,
+    ) -> Result<Self, Rejection> {
+        return Ok(State(T::from_context(ctx)));
+    }
+}
+
+/// A value a layer stored in `Request::extensions`, the request is rejected when it is missing.
+pub struct Extension<T>(pub T);
+
+impl<Context, T: Clone + Send + Sync + 'static> FromRequest<Context> for Extension<T> {
+    fn from_request(
+        req: &Request,
+        _path_vals: &Identifiers,
+        _ctx: &Context,
+    ) -> Result<Self, Rejection> {
+        let Some(value) = req.extensions.get::<T>() else {
+            return Err(Rejection::internal(format!(
+                "Missing request extension {}",
+                std::any::type_name::<T>()
+            )));
+        };
+        return Ok(Extension(value.clone()));
+    }
+}
+
+/// State registered with `Router::manage`, shared by every request. Mutating it is up to the
+/// interior mutability of `T`.
+pub struct Managed<T>(pub Arc<T>);
+
+impl<T> Deref for Managed<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        return &self.0;
+    }
+}
+
+impl<Context, T: Send + Sync + 'static> FromRequest<Context> for Managed<T> {
+    fn from_request(
+        req: &Request,
+        path_vals: &Identifiers,
+        _ctx: &Context,
+    ) -> Result<Self, Rejection> {
+        // a layer's value of the same type hides the managed one
+        let value = req.extensions.get_shared::<T>();
+        let Some(value) = value.or_else(|| return path_vals.managed::<T>()) else {
+            return Err(Rejection::internal(format!(
+                "No managed state of type {}",
+                std::any::type_name::<T>()
+            )));
+        };
+        return Ok(Managed(value));
+    }
+}
+
+/// A function of extractors turned into a `Handler`, see `extract`.
+pub struct Extract<F, Args> {
+    f: F,
+    args: PhantomData<fn() -> Args>,
+}
+
+/// Wraps a function whose arguments all implement `FromRequest` so it can be registered as a
+/// handler, e.g. `app.handle("/echo/{msg}", extract(echo))`.
+pub fn extract<F, Args>(f: F) -> Extract<F, Args> {
+    return Extract {
+        f,
+        args: PhantomData,
+    };
+}
+
+macro_rules! extract_handler {
+    ($($t:ident),+) => {
+        impl<Context, F, R, $($t),+> Handler<Context> for Extract<F, ($($t,)+)>
+        where
+            F: Fn($($t),+) -> R + Send + Sync,
+            R: IntoResponse,
+            $($t: FromRequest<Context>),+
+        {
+            #[allow(non_snake_case)]
+            fn call(&self, req: &Request, path_vals: &Identifiers, ctx: &Context) -> Response {
+                $(
+                    let $t = match $t::from_request(req, path_vals, ctx) {
+                        Ok(value) => value,
+                        Err(rejection) => return rejection.into_response(),
+                    };
+                )+
+                return (self.f)($($t),+).into_response();
+            }
+        }
+    };
+}
+
+extract_handler!(T1);
+extract_handler!(T1, T2);
+extract_handler!(T1, T2, T3);
+extract_handler!(T1, T2, T3, T4);
+extract_handler!(T1, T2, T3, T4, T5);
+extract_handler!(T1, T2, T3, T4, T5, T6);
+
+#[cfg(test)]
+mod tests {
+    use crate::core::extract::{
+        extract, Body, Form, FromContext, FromQuery, Header, Path, Query, Rejection, State,
+        UserAgent,
+    };
+    use crate::core::request::Request;
+    use crate::core::response::{Response, StatusCode};
+    use crate::core::routing::{Handler, Route};
+    use crate::core::urlencoded::Params;
+    use anyhow::anyhow;
+    use bytes::Bytes;
+    use std::collections::HashMap;
+
+    struct PlaceholderContext {
+        greeting: String,
+    }
+
+    struct Greeting(String);
+
+    impl FromContext<PlaceholderContext> for Greeting {
+        fn from_context(ctx: &PlaceholderContext) -> Self {
+            return Greeting(ctx.greeting.clone());
+        }
+    }
+
+    // runs `handler` for `raw` as if it was registered at `path`
+    fn call(
+        path: &'static str,
+        handler: impl Handler<PlaceholderContext> + 'static,
+        raw: &str,
+    ) -> Response {
+        let route = Route::new(path, handler).unwrap();
+        let request = Request::from(raw.as_bytes()).unwrap();
+        let ids = route.matches(request.path).unwrap();
+        let ctx = PlaceholderContext {
+            greeting: String::from("hi"),
+        };
+        return route.handler.call(&request, &ids, &ctx);
+    }
+
+    fn body(response: &Response) -> &str {
+        return std::str::from_utf8(&response.body).unwrap();
+    }
+
+    fn user(Path((id, name)): Path<(u32, String)>) -> String {
+        return format!("{} {}", id, name);
+    }
+
+    #[test]
+    fn extract_path() {
+        let raw = "GET /user/7/ada%20l HTTP/1.1\r\n\r\n";
+        let response = call("/user/{id}/{name}", extract(user), raw);
+        assert_eq!(body(&response), "7 ada l");
+
+        let raw = "GET /user/x/ada HTTP/1.1\r\n\r\n";
+        let response = call("/user/{id}/{name}", extract(user), raw);
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert!(body(&response).contains("{id}"));
+
+        let response = call("/user/{id}", extract(user), "GET /user/7 HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+        assert!(body(&response).contains("expects 2"));
+
+        let raw = "GET /user/7/ada/extra HTTP/1.1\r\n\r\n";
+        let response = call("/user/{id}/{name}/{more}", extract(user), raw);
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+    }
+
+    struct Page(usize);
+
+    impl FromQuery for Page {
+        fn from_query(pairs: Vec<(String, String)>) -> Result<Self, Rejection> {
+            let Some((_, page)) = pairs.iter().find(|(k, _)| return k == "page") else {
+                return Ok(Page(1));
+            };
+            return page
+                .parse()
+                .map(Page)
+                .map_err(|_| return Rejection::bad_request("page must be a number"));
+        }
+    }
+
+    #[test]
+    fn extract_query() {
+        let search = |Query(q): Query<HashMap<String, String>>, Query(Page(page)): Query<Page>| {
+            return format!("{} {}", q["q"], page);
+        };
+        let raw = "GET /search?q=a+b&page=3 HTTP/1.1\r\n\r\n";
+        assert_eq!(body(&call("/search", extract(search), raw)), "a b 3");
+
+        let raw = "GET /search?q=a&page=x HTTP/1.1\r\n\r\n";
+        let response = call("/search", extract(search), raw);
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn extract_header() {
+        let agent = |Header(UserAgent(agent)): Header<UserAgent>| return agent;
+        let raw = "GET / HTTP/1.1\r\nuser-agent: curl/7.64.1\r\n\r\n";
+        assert_eq!(body(&call("/", extract(agent), raw)), "curl/7.64.1");
+
+        let response = call("/", extract(agent), "GET / HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(body(&response), "Missing header User-Agent");
+
+        let optional = |agent: Option<Header<UserAgent>>| {
+            return format!("{}", agent.is_some());
+        };
+        let response = call("/", extract(optional), "GET / HTTP/1.1\r\n\r\n");
+        assert_eq!(body(&response), "false");
+    }
+
+    #[test]
+    fn extract_body() {
+        let bytes = |Body(body): Body<Bytes>| return body.to_vec();
+        let text = |Body(body): Body<String>| return body;
+        let raw = "POST / HTTP/1.1\r\nContent-Type: application/octet-stream\r\nContent-Length: 3\r\n\r\nabc";
+        assert_eq!(body(&call("/", extract(bytes), raw)), "abc");
+
+        let response = call("/", extract(text), raw);
+        assert_eq!(response.status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let raw = "POST / HTTP/1.1\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: 3\r\n\r\nabc";
+        assert_eq!(body(&call("/", extract(text), raw)), "abc");
+        let response = call("/", extract(text), "POST / HTTP/1.1\r\n\r\n");
+        assert_eq!(body(&response), "");
+    }
+
+    #[test]
+    fn extract_form() {
+        let tags = |Form(form): Form<Params>, Form(Page(page)): Form<Page>| {
+            return format!(
+                "{} {}",
+                form.get_all("tag").collect::<Vec<_>>().join(","),
+                page
+            );
+        };
+        let raw = "POST / HTTP/1.1\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: 20\r\n\r\ntag=a+b&tag=c&page=2";
+        assert_eq!(body(&call("/", extract(tags), raw)), "a b,c 2");
+        let response = call("/", extract(tags), "POST / HTTP/1.1\r\n\r\n");
+        assert_eq!(body(&response), " 1");
+
+        let raw =
+            "POST / HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: 2\r\n\r\n{}";
+        let response = call("/", extract(tags), raw);
+        assert_eq!(response.status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let raw = "POST / HTTP/1.1\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: 6\r\n\r\npage=x";
+        let response = call("/", extract(tags), raw);
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn extract_state() {
+        let greet = |State(Greeting(greeting)): State<Greeting>, Path(name): Path<String>| {
+            return format!("{} {}", greeting, name);
+        };
+        let raw = "GET /hello/bob HTTP/1.1\r\n\r\n";
+        assert_eq!(body(&call("/hello/{name}", extract(greet), raw)), "hi bob");
+    }
+
+    #[test]
+    fn extract_handler_error_is_kept() {
+        let fails = |_: Path<String>| -> anyhow::Result<String> {
+            return Err(anyhow!("not found"));
+        };
+        let response = call("/{name}", extract(fails), "GET /x HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+    }
+}
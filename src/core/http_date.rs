@@ -0,0 +1,121 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// `time` as an IMF-fixdate, `Sun, 06 Nov 1994 08:49:37 GMT`, to the second.
+pub fn format(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| return d.as_secs())
+        .unwrap_or(0);
+    let days = (secs / 86400) as i64;
+    let (year, month, day) = civil_from_days(days);
+    let rem = secs % 86400;
+    return format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAYS[(days % 7) as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60
+    );
+}
+
+/// Reads an IMF-fixdate. The obsolete RFC 850 and asctime forms are not accepted, a date that
+/// cannot be read makes a conditional header be ignored as the spec asks.
+pub fn parse(date: &str) -> Option<SystemTime> {
+    let (_, rest) = date.split_once(", ")?;
+    let parts = rest.split(' ').collect::<Vec<_>>();
+    let [day, month, year, time, "GMT"] = parts[..] else {
+        return None;
+    };
+    let day = day
+        .parse::<u32>()
+        .ok()
+        .filter(|d| return (1..=31).contains(d))?;
+    let month = MONTHS.iter().position(|m| return *m == month)? as u32 + 1;
+    let year = year.parse::<i64>().ok().filter(|y| return *y >= 1970)?;
+    let hms = time
+        .split(':')
+        .map(|n| return n.parse::<u64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    let [h, m, s] = hms[..] else {
+        return None;
+    };
+    if h > 23 || m > 59 || s > 60 {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+    let secs = days as u64 * 86400 + h * 3600 + m * 60 + s;
+    return Some(UNIX_EPOCH + Duration::from_secs(secs));
+}
+
+/// Whether two times fall on the same second, which is all an HTTP date can tell apart.
+pub fn same_second(a: SystemTime, b: SystemTime) -> bool {
+    return truncate(a) == truncate(b);
+}
+
+pub fn truncate(time: SystemTime) -> SystemTime {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| return d.as_secs())
+        .unwrap_or(0);
+    return UNIX_EPOCH + Duration::from_secs(secs);
+}
+
+// days since 1970-01-01 to a proleptic Gregorian date, after Howard Hinnant's algorithm
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    return (year, month, day);
+}
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    return era * 146097 + doe - 719468;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::http_date::{format, parse, same_second};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn http_date_round_trip() {
+        let time = UNIX_EPOCH + Duration::from_secs(784111777);
+        assert_eq!(format(time), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(parse("Sun, 06 Nov 1994 08:49:37 GMT"), Some(time));
+        assert_eq!(format(UNIX_EPOCH), "Thu, 01 Jan 1970 00:00:00 GMT");
+
+        let leap = UNIX_EPOCH + Duration::from_secs(951782400);
+        assert_eq!(format(leap), "Tue, 29 Feb 2000 00:00:00 GMT");
+        assert_eq!(parse(&format(leap)), Some(leap));
+        assert!(same_second(leap, leap + Duration::from_millis(900)));
+    }
+
+    #[test]
+    fn http_date_rejects_other_forms() {
+        assert_eq!(parse("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+        assert_eq!(parse("Sun Nov  6 08:49:37 1994"), None);
+        assert_eq!(parse("Sun, 06 Nov 1994 25:49:37 GMT"), None);
+        assert_eq!(parse("Sun, 06 Foo 1994 08:49:37 GMT"), None);
+        assert_eq!(parse(""), None);
+    }
+}
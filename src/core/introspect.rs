@@ -0,0 +1,182 @@
+use crate::core::request::{HttpMethod, Request};
+use crate::core::response::Response;
+use crate::core::routing::{Handler, Identifiers, RouteHandler, Routeable, Segments};
+use crate::core::shared_str::SharedStr;
+use std::sync::Arc;
+
+/// What a route documents about itself, none of it changes how requests are matched or served.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RouteMeta {
+    pub summary: Option<String>,
+    // empty when the handler takes any method
+    pub methods: Vec<HttpMethod>,
+    // capture name to the values it accepts, as a pattern
+    pub constraints: Vec<(String, String)>,
+    pub request_content_type: Option<String>,
+    pub response_content_type: Option<String>,
+}
+
+/// A handler along with the documentation of its route, built with `describe`.
+pub struct Described<Context> {
+    meta: RouteMeta,
+    handler: RouteHandler<Context>,
+}
+
+/// Attaches documentation to a handler, it shows up in `Router::routes_info` and the OpenAPI
+/// document wherever the handler ends up registered.
+pub fn describe<Context>(handler: impl Handler<Context> + 'static) -> Described<Context> {
+    return Described {
+        meta: RouteMeta::default(),
+        handler: Arc::new(handler),
+    };
+}
+
+impl<Context> Described<Context> {
+    pub fn summary(mut self, summary: impl Into<String>) -> Self {
+        self.meta.summary = Some(summary.into());
+        return self;
+    }
+
+    /// A method the handler answers, requests with other methods still reach it.
+    pub fn method(mut self, method: HttpMethod) -> Self {
+        if !self.meta.methods.contains(&method) {
+            self.meta.methods.push(method);
+        }
+        return self;
+    }
+
+    /// Documents the values the capture `name` accepts, the router does not enforce it.
+    pub fn constraint(mut self, name: impl Into<String>, pattern: impl Into<String>) -> Self {
+        self.meta.constraints.push((name.into(), pattern.into()));
+        return self;
+    }
+
+    pub fn request_content_type(mut self, media_type: impl Into<String>) -> Self {
+        self.meta.request_content_type = Some(media_type.into());
+        return self;
+    }
+
+    pub fn response_content_type(mut self, media_type: impl Into<String>) -> Self {
+        self.meta.response_content_type = Some(media_type.into());
+        return self;
+    }
+}
+
+impl<Context> Handler<Context> for Described<Context> {
+    fn call(&self, req: &Request, path_vals: &Identifiers, ctx: &Context) -> Response {
+        return self.handler.call(req, path_vals, ctx);
+    }
+
+    fn meta(&self) -> Option<&RouteMeta> {
+        return Some(&self.meta);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaptureInfo {
+    pub name: SharedStr,
+    pub constraint: Option<String>,
+}
+
+/// A registered route as far as it can be told from the outside.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RouteInfo {
+    pub path: SharedStr,
+    // every method the request line accepts when the route does not document its own
+    pub methods: Vec<HttpMethod>,
+    pub captures: Vec<CaptureInfo>,
+    pub meta: Option<RouteMeta>,
+}
+
+/// Every route of `routes`, ordered by path.
+pub fn routes_info<Context, T: Routeable<Context>>(routes: &T) -> Vec<RouteInfo> {
+    let mut info = routes
+        .routes()
+        .into_iter()
+        .map(|route| {
+            let meta = route.handler.meta().cloned();
+            let constraint = |name: &str| {
+                let constraints = meta.as_ref().map(|m| return m.constraints.as_slice());
+                return constraints
+                    .unwrap_or_default()
+                    .iter()
+                    .find(|(capture, _)| return capture == name)
+                    .map(|(_, pattern)| return pattern.clone());
+            };
+            let captures = route
+                .path_segments
+                .iter()
+                .filter_map(|segment| match segment {
+                    Segments::Capture(name) => {
+                        return Some(CaptureInfo {
+                            name: name.clone(),
+                            constraint: constraint(name),
+                        })
+                    }
+                    Segments::Literal(_) => return None,
+                })
+                .collect();
+            let methods = match &meta {
+                Some(meta) if !meta.methods.is_empty() => meta.methods.clone(),
+                _ => HttpMethod::ALL.to_vec(),
+            };
+            return RouteInfo {
+                path: route.path.clone(),
+                methods,
+                captures,
+                meta,
+            };
+        })
+        .collect::<Vec<_>>();
+    info.sort_by(|a, b| return a.path.cmp(&b.path));
+    return info;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::introspect::{describe, routes_info, CaptureInfo};
+    use crate::core::request::{HttpMethod, Request};
+    use crate::core::response::StatusCode;
+    use crate::core::route_trie::RouteTrie;
+    use crate::core::routing::{Identifiers, Routeable};
+
+    struct PlaceholderContext {}
+
+    fn thunk(_req: &Request, _paths: &Identifiers, _ctx: &PlaceholderContext) -> StatusCode {
+        return StatusCode::OK;
+    }
+
+    #[test]
+    fn introspect_lists_routes() {
+        let mut routes = RouteTrie::<PlaceholderContext>::new();
+        routes.add_route("/", thunk).unwrap();
+        let files = describe(thunk)
+            .summary("Reads a file")
+            .method(HttpMethod::Get)
+            .method(HttpMethod::Get)
+            .constraint("file", "[a-z.]+")
+            .response_content_type("application/octet-stream");
+        routes.add_route("/files/{dir}/{file}", files).unwrap();
+
+        let info = routes_info(&routes);
+        assert_eq!(info.len(), 2);
+        assert_eq!(info[0].path, "/");
+        assert_eq!(info[0].methods, HttpMethod::ALL.to_vec());
+        assert!(info[0].meta.is_none());
+
+        assert_eq!(info[1].methods, vec![HttpMethod::Get]);
+        let captures = vec![
+            CaptureInfo {
+                name: "dir".into(),
+                constraint: None,
+            },
+            CaptureInfo {
+                name: "file".into(),
+                constraint: Some(String::from("[a-z.]+")),
+            },
+        ];
+        assert_eq!(info[1].captures, captures);
+        let meta = info[1].meta.as_ref().unwrap();
+        assert_eq!(meta.summary.as_deref(), Some("Reads a file"));
+    }
+}
@@ -0,0 +1,132 @@
+use anyhow::Result;
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Route set shared between the serving threads and whoever changes it at runtime.
+///
+/// Requests work on a snapshot, an `Arc` cloned out under a briefly held read lock, so a request
+/// in flight keeps the routes it started with. Updates are applied to a copy of the current set
+/// and published with a single pointer swap, readers never wait on an update being built.
+pub struct LiveRoutes<T> {
+    current: RwLock<Arc<T>>,
+    // serializes updates so two of them never start from the same snapshot
+    writer: Mutex<()>,
+}
+
+impl<T> LiveRoutes<T> {
+    pub fn new(routes: T) -> LiveRoutes<T> {
+        return LiveRoutes {
+            current: RwLock::new(Arc::new(routes)),
+            writer: Mutex::new(()),
+        };
+    }
+
+    pub fn snapshot(&self) -> Arc<T> {
+        // the lock only guards a pointer swap, a panic elsewhere cannot leave it half written
+        let current = self
+            .current
+            .read()
+            .unwrap_or_else(|e| return e.into_inner());
+        return current.clone();
+    }
+
+    /// Replaces the whole route set.
+    pub fn store(&self, routes: T) {
+        let _writer = self.writer.lock().unwrap_or_else(|e| return e.into_inner());
+        self.publish(Arc::new(routes));
+    }
+
+    /// Direct access while nothing else holds the routes, used to build them before serving.
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        let current = self
+            .current
+            .get_mut()
+            .unwrap_or_else(|e| return e.into_inner());
+        return Arc::get_mut(current);
+    }
+
+    fn publish(&self, routes: Arc<T>) {
+        let mut current = self
+            .current
+            .write()
+            .unwrap_or_else(|e| return e.into_inner());
+        *current = routes;
+    }
+}
+
+impl<T: Clone> LiveRoutes<T> {
+    /// Applies `f` to a copy of the current routes and publishes it, nothing changes if `f` fails.
+    pub fn update<F>(&self, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut T) -> Result<()>,
+    {
+        let _writer = self.writer.lock().unwrap_or_else(|e| return e.into_inner());
+        let mut next = T::clone(&self.snapshot());
+        f(&mut next)?;
+        self.publish(Arc::new(next));
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::live_routes::LiveRoutes;
+    use anyhow::anyhow;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn live_snapshot_is_stable() {
+        let live = LiveRoutes::new(vec![1]);
+        let before = live.snapshot();
+        live.update(|v| {
+            v.push(2);
+            return Ok(());
+        })
+        .unwrap();
+        assert_eq!(*before, vec![1]);
+        assert_eq!(*live.snapshot(), vec![1, 2]);
+    }
+
+    #[test]
+    fn live_failed_update_changes_nothing() {
+        let live = LiveRoutes::new(vec![1]);
+        let result = live.update(|v| {
+            v.push(2);
+            return Err(anyhow!("rejected"));
+        });
+        assert!(result.is_err());
+        assert_eq!(*live.snapshot(), vec![1]);
+    }
+
+    #[test]
+    fn live_get_mut_only_when_unshared() {
+        let mut live = LiveRoutes::new(vec![1]);
+        assert!(live.get_mut().is_some());
+        let snapshot = live.snapshot();
+        assert!(live.get_mut().is_none());
+        drop(snapshot);
+        live.get_mut().unwrap().push(2);
+        assert_eq!(*live.snapshot(), vec![1, 2]);
+    }
+
+    #[test]
+    fn live_concurrent_updates_are_not_lost() {
+        let live = Arc::new(LiveRoutes::new(Vec::new()));
+        let workers = (0..8)
+            .map(|i| {
+                let live = live.clone();
+                return thread::spawn(move || {
+                    live.update(|v| {
+                        v.push(i);
+                        return Ok(());
+                    })
+                    .unwrap();
+                });
+            })
+            .collect::<Vec<_>>();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert_eq!(live.snapshot().len(), 8);
+    }
+}
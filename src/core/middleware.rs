@@ -0,0 +1,153 @@
+use crate::core::introspect::RouteMeta;
+use crate::core::request::Request;
+use crate::core::response::{IntoResponse, Response};
+use crate::core::routing::{Handler, Identifiers, RouteHandler};
+use std::sync::Arc;
+
+/// Logic that runs around a handler. A layer either answers the request itself or hands it on
+/// with `next.run(..)`, and can change whatever response comes back from further in.
+pub trait Middleware<Context>: Send + Sync {
+    fn call(
+        &self,
+        req: &Request,
+        path_vals: &Identifiers,
+        ctx: &Context,
+        next: Next<Context>,
+    ) -> Response;
+}
+
+impl<Context, F, R> Middleware<Context> for F
+where
+    F: Fn(&Request, &Identifiers, &Context, Next<Context>) -> R + Send + Sync,
+    R: IntoResponse,
+{
+    fn call(
+        &self,
+        req: &Request,
+        path_vals: &Identifiers,
+        ctx: &Context,
+        next: Next<Context>,
+    ) -> Response {
+        return self(req, path_vals, ctx, next).into_response();
+    }
+}
+
+pub type Layer<Context> = Arc<dyn Middleware<Context>>;
+
+/// The rest of the chain from the point of view of one layer, ending in the handler.
+pub struct Next<'n, Context> {
+    layers: &'n [Layer<Context>],
+    handler: &'n dyn Handler<Context>,
+}
+
+impl<'n, Context> Next<'n, Context> {
+    pub fn new(layers: &'n [Layer<Context>], handler: &'n dyn Handler<Context>) -> Self {
+        return Next { layers, handler };
+    }
+
+    pub fn run(self, req: &Request, path_vals: &Identifiers, ctx: &Context) -> Response {
+        let Some((layer, rest)) = self.layers.split_first() else {
+            return self.handler.call(req, path_vals, ctx);
+        };
+        return layer.call(req, path_vals, ctx, Next::new(rest, self.handler));
+    }
+}
+
+/// A handler wrapped in layers, the first layer added is the outermost one.
+///
+/// Being a handler itself it can be registered anywhere a handler is expected, this is how layers
+/// are attached to a single route or to every route of a group.
+pub struct Layered<Context> {
+    layers: Vec<Layer<Context>>,
+    handler: RouteHandler<Context>,
+}
+
+impl<Context> Layered<Context> {
+    pub fn new(handler: impl Handler<Context> + 'static) -> Self {
+        return Layered::from_parts(Vec::new(), Arc::new(handler));
+    }
+
+    pub fn from_parts(layers: Vec<Layer<Context>>, handler: RouteHandler<Context>) -> Self {
+        return Layered { layers, handler };
+    }
+
+    pub fn layer(mut self, middleware: impl Middleware<Context> + 'static) -> Self {
+        self.layers.push(Arc::new(middleware));
+        return self;
+    }
+}
+
+impl<Context> Handler<Context> for Layered<Context> {
+    fn call(&self, req: &Request, path_vals: &Identifiers, ctx: &Context) -> Response {
+        return Next::new(&self.layers, self.handler.as_ref()).run(req, path_vals, ctx);
+    }
+
+    fn meta(&self) -> Option<&RouteMeta> {
+        return self.handler.meta();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::middleware::{Layered, Next};
+    use crate::core::request::Request;
+    use crate::core::response::{Response, StatusCode};
+    use crate::core::routing::{Captures, Handler, Identifiers};
+
+    struct PlaceholderContext {}
+
+    fn thunk(_req: &Request, _paths: &Identifiers, _ctx: &PlaceholderContext) -> &'static str {
+        return "handler";
+    }
+
+    fn call(handler: &Layered<PlaceholderContext>) -> Response {
+        let request = Request::from(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        let ids = Identifiers::new(Captures::new());
+        return handler.call(&request, &ids, &PlaceholderContext {});
+    }
+
+    fn wrap(
+        req: &Request,
+        ids: &Identifiers,
+        ctx: &PlaceholderContext,
+        next: Next<PlaceholderContext>,
+    ) -> Response {
+        let mut response = next.run(req, ids, ctx);
+        response.body.insert(0, b'(');
+        response.body.push(b')');
+        return response;
+    }
+
+    #[test]
+    fn middleware_without_layers() {
+        assert_eq!(call(&Layered::new(thunk)).body, b"handler".to_vec());
+    }
+
+    #[test]
+    fn middleware_runs_in_order() {
+        let tag = |req: &Request, ids: &Identifiers, ctx: &PlaceholderContext, next: Next<_>| {
+            let mut response = next.run(req, ids, ctx);
+            response.body.insert(0, b'<');
+            response.set_header("X-Tagged", "yes");
+            return response;
+        };
+        let response = call(&Layered::new(thunk).layer(wrap).layer(tag));
+        assert_eq!(response.body, b"(<handler)".to_vec());
+        assert_eq!(response.header("X-Tagged"), Some("yes"));
+    }
+
+    #[test]
+    fn middleware_short_circuits() {
+        let deny =
+            |_req: &Request, _ids: &Identifiers, _ctx: &PlaceholderContext, _next: Next<_>| {
+                return (StatusCode::FORBIDDEN, "denied");
+            };
+        let never =
+            |_req: &Request, _ids: &Identifiers, _ctx: &PlaceholderContext, _next: Next<_>| {
+                return StatusCode::INTERNAL_SERVER_ERROR;
+            };
+        let response = call(&Layered::new(thunk).layer(wrap).layer(deny).layer(never));
+        assert_eq!(response.status, StatusCode::FORBIDDEN);
+        assert_eq!(response.body, b"(denied)".to_vec());
+    }
+}
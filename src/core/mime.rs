@@ -0,0 +1,79 @@
+use std::path::Path;
+
+pub const OCTET_STREAM: &str = "application/octet-stream";
+
+/// Media type for a file extension, matched without regard to case.
+pub fn from_extension(extension: &str) -> Option<&'static str> {
+    let media_type = match extension.to_ascii_lowercase().as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "md" => "text/markdown; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "pdf" => "application/pdf",
+        "wasm" => "application/wasm",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "mp3" => "audio/mpeg",
+        "mp4" => "video/mp4",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        _ => return None,
+    };
+    return Some(media_type);
+}
+
+pub fn from_path(path: &Path) -> Option<&'static str> {
+    return from_extension(path.extension()?.to_str()?);
+}
+
+// leading bytes of formats that always start the same way
+const SIGNATURES: [(&[u8], &str); 8] = [
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"%PDF-", "application/pdf"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"\x1f\x8b", "application/gzip"),
+    (b"\0asm", "application/wasm"),
+];
+
+/// Media type told by the first bytes of the content, for files without a known extension.
+pub fn sniff(content: &[u8]) -> Option<&'static str> {
+    return SIGNATURES
+        .iter()
+        .find(|(signature, _)| return content.starts_with(signature))
+        .map(|(_, media_type)| return *media_type);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::mime::{from_extension, from_path, sniff};
+    use std::path::Path;
+
+    #[test]
+    fn mime_from_extension() {
+        assert_eq!(from_extension("HTML"), Some("text/html; charset=utf-8"));
+        assert_eq!(from_path(Path::new("a/b.tar.gz")), Some("application/gzip"));
+        assert_eq!(from_path(Path::new("Makefile")), None);
+        assert_eq!(from_extension("unknown"), None);
+    }
+
+    #[test]
+    fn mime_sniff() {
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n...."), Some("image/png"));
+        assert_eq!(sniff(b"%PDF-1.7"), Some("application/pdf"));
+        assert_eq!(sniff(b"plain text"), None);
+        assert_eq!(sniff(b""), None);
+    }
+}
@@ -0,0 +1,470 @@
+//! `multipart/form-data`, RFC 7578, as sent by browsers for forms with files.
+//!
+//! Parts are read from any `Read` one at a time, only the part being read is held in memory and
+//! limits stop a body from taking more than it is allowed to.
+
+use crate::core::response::{IntoResponse, Response, StatusCode};
+use crate::core::urlencoded::percent_decode;
+use std::io::{self, Read};
+
+// what is read from the source at once
+const READ_CHUNK: usize = 8192;
+// the headers of one part, they are short unless something is off
+const MAX_PART_HEAD: usize = 8192;
+
+/// How much a multipart body may hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub part_size: usize,
+    pub total_size: usize,
+    pub parts: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        return Limits {
+            part_size: 8 * 1024 * 1024,
+            total_size: 16 * 1024 * 1024,
+            parts: 32,
+        };
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MultipartError {
+    #[error("Content type has no multipart boundary")]
+    NoBoundary,
+    #[error("Malformed multipart body, {0}")]
+    Malformed(&'static str),
+    #[error("Part {0} is larger than allowed")]
+    PartTooLarge(usize),
+    #[error("Multipart body is larger than allowed")]
+    TooLarge,
+    #[error("Multipart body has more parts than allowed")]
+    TooManyParts,
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl MultipartError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            MultipartError::PartTooLarge(_)
+            | MultipartError::TooLarge
+            | MultipartError::TooManyParts => return StatusCode::PAYLOAD_TOO_LARGE,
+            MultipartError::Io(_) => return StatusCode::INTERNAL_SERVER_ERROR,
+            _ => return StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl IntoResponse for MultipartError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        return (status, self.to_string()).into_response();
+    }
+}
+
+/// One part of a form, a field or a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Part {
+    pub headers: Vec<(String, String)>,
+    // from `Content-Disposition`
+    pub name: Option<String>,
+    pub filename: Option<String>,
+    pub data: Vec<u8>,
+}
+
+impl Part {
+    /// Header lookup ignoring the case of the name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        return self
+            .headers
+            .iter()
+            .find(|(key, _)| return key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| return value.as_str());
+    }
+
+    /// Defaults to `text/plain` as the spec says.
+    pub fn content_type(&self) -> &str {
+        return self.header("Content-Type").unwrap_or("text/plain");
+    }
+
+    pub fn is_file(&self) -> bool {
+        return self.filename.is_some();
+    }
+}
+
+/// The boundary of a `multipart/form-data` content type.
+pub fn boundary(content_type: &str) -> Option<&str> {
+    let mut params = content_type.split(';');
+    let media_type = params.next()?.trim();
+    if !media_type.eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    let boundary = params.find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        if !key.trim().eq_ignore_ascii_case("boundary") {
+            return None;
+        }
+        return Some(value.trim().trim_matches('"'));
+    })?;
+    // RFC 2046 section 5.1.1
+    if boundary.is_empty() || boundary.len() > 70 {
+        return None;
+    }
+    return Some(boundary);
+}
+
+/// Reads the parts of a multipart body as they come in from `source`.
+pub struct Multipart<R> {
+    source: R,
+    // `CRLF--boundary`, the first one may come without the line break
+    delimiter: Vec<u8>,
+    limits: Limits,
+    // read from the source and not yet handed out
+    buffer: Vec<u8>,
+    read: usize,
+    parts: usize,
+    eof: bool,
+    done: bool,
+}
+
+impl<R: Read> Multipart<R> {
+    pub fn new(source: R, boundary: &str, limits: Limits) -> Multipart<R> {
+        return Multipart {
+            source,
+            delimiter: format!("\r\n--{}", boundary).into_bytes(),
+            limits,
+            // as if the body started on a new line, which makes the first delimiter like the others
+            buffer: b"\r\n".to_vec(),
+            read: 0,
+            parts: 0,
+            eof: false,
+            done: false,
+        };
+    }
+
+    /// The next part, `None` after the last one.
+    pub fn next_part(&mut self) -> Result<Option<Part>, MultipartError> {
+        if self.done {
+            return Ok(None);
+        }
+        if self.parts == 0 {
+            // anything before the first delimiter is a preamble to ignore
+            self.skip_to_delimiter()?;
+        }
+        // the delimiter is followed by `--` after the last part, or by the end of its line
+        self.fill_to(2)?;
+        if self.buffer.starts_with(b"--") {
+            self.done = true;
+            return Ok(None);
+        }
+        let line_end = self.find_filled(b"\r\n", MAX_PART_HEAD)?;
+        let Some(line_end) = line_end else {
+            return Err(MultipartError::Malformed("delimiter line does not end"));
+        };
+        if !self.buffer[..line_end]
+            .iter()
+            .all(|b| return *b == b' ' || *b == b'\t')
+        {
+            return Err(MultipartError::Malformed("text after a delimiter"));
+        }
+        self.buffer.drain(..line_end + 2);
+
+        self.parts += 1;
+        if self.parts > self.limits.parts {
+            return Err(MultipartError::TooManyParts);
+        }
+        let mut part = self.read_head()?;
+        part.data = self.read_data()?;
+        return Ok(Some(part));
+    }
+
+    /// Every part left, for bodies small enough to hold at once.
+    pub fn parts(mut self) -> Result<Vec<Part>, MultipartError> {
+        let mut parts = Vec::new();
+        while let Some(part) = self.next_part()? {
+            parts.push(part);
+        }
+        return Ok(parts);
+    }
+
+    fn skip_to_delimiter(&mut self) -> Result<(), MultipartError> {
+        loop {
+            if let Some(at) = find(&self.buffer, &self.delimiter) {
+                self.buffer.drain(..at + self.delimiter.len());
+                return Ok(());
+            }
+            let keep = self.delimiter.len().min(self.buffer.len());
+            self.buffer.drain(..self.buffer.len() - keep);
+            if !self.fill()? {
+                return Err(MultipartError::Malformed("no delimiter"));
+            }
+        }
+    }
+
+    fn read_head(&mut self) -> Result<Part, MultipartError> {
+        let mut part = Part {
+            headers: Vec::new(),
+            name: None,
+            filename: None,
+            data: Vec::new(),
+        };
+        // a part without headers starts with the empty line right away
+        let end = if self.fill_to(2)? && self.buffer.starts_with(b"\r\n") {
+            0
+        } else {
+            let Some(end) = self.find_filled(b"\r\n\r\n", MAX_PART_HEAD)? else {
+                return Err(MultipartError::Malformed("part headers do not end"));
+            };
+            end + 2
+        };
+        let head = self.buffer.drain(..end + 2).collect::<Vec<_>>();
+        let Ok(head) = std::str::from_utf8(&head) else {
+            return Err(MultipartError::Malformed("part headers are not UTF-8"));
+        };
+        for line in head.split("\r\n").filter(|line| return !line.is_empty()) {
+            let Some((name, value)) = line.split_once(':') else {
+                return Err(MultipartError::Malformed("part header without a colon"));
+            };
+            part.headers
+                .push((name.trim().to_string(), value.trim().to_string()));
+        }
+        if let Some(disposition) = part.header("Content-Disposition") {
+            let params = disposition_params(disposition);
+            let param = |key: &str| {
+                return params
+                    .iter()
+                    .find(|(k, _)| return k.eq_ignore_ascii_case(key))
+                    .map(|(_, v)| return v.clone());
+            };
+            part.name = param("name");
+            part.filename = param("filename*")
+                .and_then(|value| return extended_value(&value))
+                .or_else(|| return param("filename"));
+        }
+        return Ok(part);
+    }
+
+    fn read_data(&mut self) -> Result<Vec<u8>, MultipartError> {
+        let mut data = Vec::new();
+        loop {
+            if let Some(at) = find(&self.buffer, &self.delimiter) {
+                data.extend(self.buffer.drain(..at));
+                self.buffer.drain(..self.delimiter.len());
+                return self.check_part(data);
+            }
+            // all but what could be the start of a delimiter is data
+            let keep = (self.delimiter.len() - 1).min(self.buffer.len());
+            data.extend(self.buffer.drain(..self.buffer.len() - keep));
+            data = self.check_part(data)?;
+            if !self.fill()? {
+                return Err(MultipartError::Malformed("part does not end"));
+            }
+        }
+    }
+
+    fn check_part(&self, data: Vec<u8>) -> Result<Vec<u8>, MultipartError> {
+        if data.len() > self.limits.part_size {
+            return Err(MultipartError::PartTooLarge(self.parts));
+        }
+        return Ok(data);
+    }
+
+    // position of `needle` once it is in the buffer, reading no further than `max` bytes for it
+    fn find_filled(&mut self, needle: &[u8], max: usize) -> Result<Option<usize>, MultipartError> {
+        loop {
+            if let Some(at) = find(&self.buffer, needle) {
+                return Ok(Some(at));
+            }
+            if self.buffer.len() > max || !self.fill()? {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn fill_to(&mut self, len: usize) -> Result<bool, MultipartError> {
+        while self.buffer.len() < len {
+            if !self.fill()? {
+                return Ok(false);
+            }
+        }
+        return Ok(true);
+    }
+
+    // `false` once the source has nothing more
+    fn fill(&mut self) -> Result<bool, MultipartError> {
+        if self.eof {
+            return Ok(false);
+        }
+        let mut chunk = [0; READ_CHUNK];
+        let n = self.source.read(&mut chunk)?;
+        if n == 0 {
+            self.eof = true;
+            return Ok(false);
+        }
+        self.read += n;
+        if self.read > self.limits.total_size {
+            return Err(MultipartError::TooLarge);
+        }
+        self.buffer.extend_from_slice(&chunk[..n]);
+        return Ok(true);
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    return haystack
+        .windows(needle.len())
+        .position(|window| return window == needle);
+}
+
+// `form-data; name="a"; filename="b \"c\".txt"`, values unquoted
+fn disposition_params(disposition: &str) -> Vec<(String, String)> {
+    let mut params = Vec::new();
+    let mut rest = disposition
+        .split_once(';')
+        .map(|(_, rest)| return rest)
+        .unwrap_or("");
+    loop {
+        rest = rest.trim_start_matches(|c: char| return c == ';' || c.is_whitespace());
+        let Some((key, after)) = rest.split_once('=') else {
+            return params;
+        };
+        let after = after.trim_start();
+        let (value, next) = match after.strip_prefix('"') {
+            Some(quoted) => unquote(quoted),
+            None => {
+                let end = after.find(';').unwrap_or(after.len());
+                (after[..end].trim().to_string(), &after[end..])
+            }
+        };
+        params.push((key.trim().to_string(), value));
+        rest = next;
+    }
+}
+
+// the value of a quoted string and what follows its closing quote. Only quotes are taken as
+// escaped, some clients send Windows paths with their backslashes as they are, RFC 7578 4.2
+fn unquote(quoted: &str) -> (String, &str) {
+    let mut value = String::new();
+    let mut chars = quoted.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return (value, &quoted[i + 1..]),
+            '\\' if chars.peek().is_some_and(|(_, next)| return *next == '"') => {
+                value.push('"');
+                chars.next();
+            }
+            c => value.push(c),
+        }
+    }
+    return (value, "");
+}
+
+// `UTF-8''na%C3%AFve.txt`, RFC 8187
+fn extended_value(value: &str) -> Option<String> {
+    let (charset, rest) = value.split_once('\'')?;
+    let (_language, encoded) = rest.split_once('\'')?;
+    if !charset.eq_ignore_ascii_case("UTF-8") {
+        return None;
+    }
+    return percent_decode(encoded).ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::multipart::{boundary, Limits, Multipart, MultipartError};
+    use std::io::Read;
+
+    const BODY: &str = concat!(
+        "preamble\r\n",
+        "--XyZ\r\n",
+        "Content-Disposition: form-data; name=\"title\"\r\n",
+        "\r\n",
+        "two\r\nlines\r\n",
+        "--XyZ\r\n",
+        "Content-Disposition: form-data; name=\"file\"; filename=\"a \\\"b\\\".txt\"\r\n",
+        "Content-Type: text/plain\r\n",
+        "\r\n",
+        "--XY not yet\r\n",
+        "--XyZ\r\n",
+        "Content-Disposition: form-data; name=\"file\"; filename*=UTF-8''na%C3%AFve.bin\r\n",
+        "\r\n",
+        "\r\n",
+        "--XyZ--\r\n",
+        "epilogue"
+    );
+
+    // hands out one byte per read, so every boundary falls between reads at some point
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let Some((first, rest)) = self.0.split_first() else {
+                return Ok(0);
+            };
+            buf[0] = *first;
+            self.0 = rest;
+            return Ok(1);
+        }
+    }
+
+    #[test]
+    fn multipart_boundary() {
+        let content_type = "multipart/form-data; boundary=\"XyZ\"";
+        assert_eq!(boundary(content_type), Some("XyZ"));
+        assert_eq!(boundary("Multipart/Form-Data;boundary=a"), Some("a"));
+        assert_eq!(boundary("multipart/mixed; boundary=a"), None);
+        assert_eq!(boundary("multipart/form-data"), None);
+    }
+
+    #[test]
+    fn multipart_parts() {
+        for parts in [
+            Multipart::new(BODY.as_bytes(), "XyZ", Limits::default()).parts(),
+            Multipart::new(Trickle(BODY.as_bytes()), "XyZ", Limits::default()).parts(),
+        ] {
+            let parts = parts.unwrap();
+            assert_eq!(parts.len(), 3);
+            assert_eq!(parts[0].name.as_deref(), Some("title"));
+            assert_eq!(parts[0].filename, None);
+            assert_eq!(parts[0].data, b"two\r\nlines".to_vec());
+            assert_eq!(parts[1].filename.as_deref(), Some("a \"b\".txt"));
+            assert_eq!(parts[1].content_type(), "text/plain");
+            assert_eq!(parts[1].data, b"--XY not yet".to_vec());
+            assert_eq!(parts[2].filename.as_deref(), Some("na\u{ef}ve.bin"));
+            assert!(parts[2].data.is_empty());
+        }
+    }
+
+    #[test]
+    fn multipart_limits_and_errors() {
+        let limits = Limits {
+            part_size: 8,
+            ..Limits::default()
+        };
+        let parts = Multipart::new(BODY.as_bytes(), "XyZ", limits).parts();
+        assert!(matches!(parts, Err(MultipartError::PartTooLarge(1))));
+
+        let limits = Limits {
+            total_size: 64,
+            ..Limits::default()
+        };
+        let parts = Multipart::new(BODY.as_bytes(), "XyZ", limits).parts();
+        assert!(matches!(parts, Err(MultipartError::TooLarge)));
+
+        let limits = Limits {
+            parts: 2,
+            ..Limits::default()
+        };
+        let parts = Multipart::new(BODY.as_bytes(), "XyZ", limits).parts();
+        assert!(matches!(parts, Err(MultipartError::TooManyParts)));
+
+        let cut = &BODY[..BODY.find("lines").unwrap()];
+        let parts = Multipart::new(cut.as_bytes(), "XyZ", Limits::default()).parts();
+        assert!(matches!(parts, Err(MultipartError::Malformed(_))));
+        let parts = Multipart::new(&b"no parts"[..], "XyZ", Limits::default()).parts();
+        assert!(matches!(parts, Err(MultipartError::Malformed(_))));
+    }
+}
@@ -0,0 +1,164 @@
+use crate::core::introspect::{routes_info, RouteInfo};
+use crate::core::json::string;
+use crate::core::live_routes::LiveRoutes;
+use crate::core::request::{HttpMethod, Request};
+use crate::core::response::{Response, StatusCode};
+use crate::core::routing::{Handler, Identifiers, Routeable};
+use std::fmt::Write;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+/// OpenAPI 3.1 document describing `routes`, as JSON.
+pub fn document(title: &str, version: &str, routes: &[RouteInfo]) -> String {
+    let paths = routes
+        .iter()
+        .map(|route| return format!("{}:{}", string(&route.path), path_item(route)))
+        .collect::<Vec<_>>();
+    return format!(
+        "{{\"openapi\":\"3.1.0\",\"info\":{{\"title\":{},\"version\":{}}},\"paths\":{{{}}}}}",
+        string(title),
+        string(version),
+        paths.join(",")
+    );
+}
+
+fn path_item(route: &RouteInfo) -> String {
+    let operations = route
+        .methods
+        .iter()
+        .map(|method| return format!("\"{}\":{}", method_key(*method), operation(route, *method)))
+        .collect::<Vec<_>>();
+    return format!("{{{}}}", operations.join(","));
+}
+
+fn method_key(method: HttpMethod) -> &'static str {
+    match method {
+        HttpMethod::Get => return "get",
+        HttpMethod::Post => return "post",
+        HttpMethod::Put => return "put",
+        HttpMethod::Delete => return "delete",
+        HttpMethod::Head => return "head",
+        HttpMethod::Patch => return "patch",
+    }
+}
+
+fn operation(route: &RouteInfo, method: HttpMethod) -> String {
+    let meta = route.meta.clone().unwrap_or_default();
+    let mut fields = Vec::new();
+    if let Some(summary) = &meta.summary {
+        fields.push(format!("\"summary\":{}", string(summary)));
+    }
+    if !route.captures.is_empty() {
+        let parameters = route
+            .captures
+            .iter()
+            .map(|capture| {
+                let mut schema = String::from("\"type\":\"string\"");
+                if let Some(pattern) = &capture.constraint {
+                    write!(schema, ",\"pattern\":{}", string(pattern)).unwrap();
+                }
+                return format!(
+                    "{{\"name\":{},\"in\":\"path\",\"required\":true,\"schema\":{{{}}}}}",
+                    string(&capture.name),
+                    schema
+                );
+            })
+            .collect::<Vec<_>>();
+        fields.push(format!("\"parameters\":[{}]", parameters.join(",")));
+    }
+    if matches!(
+        method,
+        HttpMethod::Post | HttpMethod::Put | HttpMethod::Patch
+    ) {
+        if let Some(media_type) = &meta.request_content_type {
+            fields.push(format!(
+                "\"requestBody\":{{\"content\":{{{}:{{}}}}}}",
+                string(media_type)
+            ));
+        }
+    }
+    let content = match &meta.response_content_type {
+        Some(media_type) => format!(",\"content\":{{{}:{{}}}}", string(media_type)),
+        None => String::new(),
+    };
+    fields.push(format!(
+        "\"responses\":{{\"200\":{{\"description\":{}{}}}}}",
+        string(StatusCode::OK.reason()),
+        content
+    ));
+    return format!("{{{}}}", fields.join(","));
+}
+
+/// Serves the OpenAPI document of the routes as they are when the request comes in.
+pub struct OpenApi<Context, T> {
+    title: String,
+    version: String,
+    routes: Arc<LiveRoutes<T>>,
+    context: PhantomData<fn(&Context)>,
+}
+
+impl<Context, T> OpenApi<Context, T> {
+    pub fn new(title: &str, version: &str, routes: Arc<LiveRoutes<T>>) -> Self {
+        return OpenApi {
+            title: title.to_string(),
+            version: version.to_string(),
+            routes,
+            context: PhantomData,
+        };
+    }
+}
+
+impl<Context, T: Routeable<Context> + Send + Sync> Handler<Context> for OpenApi<Context, T> {
+    fn call(&self, _req: &Request, _path_vals: &Identifiers, _ctx: &Context) -> Response {
+        let routes = routes_info(self.routes.snapshot().as_ref());
+        let mut response = Response::new(StatusCode::OK);
+        response.set_header("Content-Type", "application/json");
+        response.body = document(&self.title, &self.version, &routes).into_bytes();
+        return response;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::introspect::{describe, routes_info};
+    use crate::core::openapi::document;
+    use crate::core::request::{HttpMethod, Request};
+    use crate::core::response::StatusCode;
+    use crate::core::route_trie::RouteTrie;
+    use crate::core::routing::{Identifiers, Routeable};
+    use pretty_assertions::assert_eq;
+
+    struct PlaceholderContext {}
+
+    fn thunk(_req: &Request, _paths: &Identifiers, _ctx: &PlaceholderContext) -> StatusCode {
+        return StatusCode::OK;
+    }
+
+    #[test]
+    fn openapi_document() {
+        let mut routes = RouteTrie::<PlaceholderContext>::new();
+        let files = describe(thunk)
+            .summary("Stores a \"file\"")
+            .method(HttpMethod::Post)
+            .constraint("file", "[a-z]+")
+            .request_content_type("text/plain");
+        routes.add_route("/files/{file}", files).unwrap();
+        routes.add_route("/", thunk).unwrap();
+
+        let expected = concat!(
+            r#"{"openapi":"3.1.0","info":{"title":"demo","version":"1.0"},"paths":{"#,
+            r#""/":{"get":{"responses":{"200":{"description":"OK"}}},"#,
+            r#""post":{"responses":{"200":{"description":"OK"}}},"#,
+            r#""put":{"responses":{"200":{"description":"OK"}}},"#,
+            r#""delete":{"responses":{"200":{"description":"OK"}}},"#,
+            r#""head":{"responses":{"200":{"description":"OK"}}},"#,
+            r#""patch":{"responses":{"200":{"description":"OK"}}}},"#,
+            r#""/files/{file}":{"post":{"summary":"Stores a \"file\"","#,
+            r#""parameters":[{"name":"file","in":"path","required":true,"#,
+            r#""schema":{"type":"string","pattern":"[a-z]+"}}],"#,
+            r#""requestBody":{"content":{"text/plain":{}}},"#,
+            r#""responses":{"200":{"description":"OK"}}}}}}"#
+        );
+        assert_eq!(document("demo", "1.0", &routes_info(&routes)), expected);
+    }
+}
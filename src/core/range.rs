@@ -0,0 +1,385 @@
+//! `Range` requests, RFC 9110 section 14.
+//!
+//! Only the `bytes` unit exists. A header that cannot be read is ignored and the whole
+//! representation is sent, only ranges that are well formed but all start past the end are
+//! answered with 416.
+//!
+//! Responses are built in memory, what is sent is read from the source in full first. To keep
+//! that bounded no more than `MAX_BUFFERED` bytes are read for one response: a single range is
+//! shortened to that, as its `Content-Range` tells, and a larger selection is refused with 413
+//! so the client asks for it in ranges.
+
+use crate::core::http_date;
+use crate::core::request::Request;
+use crate::core::response::{IntoResponse, Response, StatusCode};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::time::SystemTime;
+
+/// More ranges than this in one request are not worth the many small reads, the whole
+/// representation is sent instead.
+pub const MAX_RANGES: usize = 16;
+
+/// The most that is read from a source for one response.
+pub const MAX_BUFFERED: u64 = 16 * 1024 * 1024;
+
+/// Both ends included, as written in the header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn len(&self) -> u64 {
+        if self.is_empty() {
+            return 0;
+        }
+        return self.end - self.start + 1;
+    }
+
+    // both ends are included, only a range that ends before it starts holds nothing
+    pub fn is_empty(&self) -> bool {
+        return self.end < self.start;
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Ranges {
+    Full,
+    Partial(Vec<ByteRange>),
+    Unsatisfiable,
+}
+
+/// Ranges of a `Range` header against a representation of `len` bytes, in request order.
+pub fn parse(header: &str, len: u64) -> Ranges {
+    let Some((unit, specs)) = header.trim().split_once('=') else {
+        return Ranges::Full;
+    };
+    if !unit.eq_ignore_ascii_case("bytes") {
+        return Ranges::Full;
+    }
+    let mut ranges = Vec::new();
+    let mut any = false;
+    for spec in specs.split(',').map(str::trim) {
+        if spec.is_empty() {
+            continue;
+        }
+        any = true;
+        let Some((first, last)) = spec.split_once('-') else {
+            return Ranges::Full;
+        };
+        let range = match (first, last) {
+            ("", suffix) => {
+                let Ok(suffix) = suffix.parse::<u64>() else {
+                    return Ranges::Full;
+                };
+                if suffix == 0 || len == 0 {
+                    continue;
+                }
+                ByteRange {
+                    start: len.saturating_sub(suffix),
+                    end: len - 1,
+                }
+            }
+            (first, last) => {
+                let Ok(start) = first.parse::<u64>() else {
+                    return Ranges::Full;
+                };
+                let end = if last.is_empty() {
+                    u64::MAX
+                } else {
+                    let Ok(end) = last.parse::<u64>() else {
+                        return Ranges::Full;
+                    };
+                    end
+                };
+                if end < start {
+                    return Ranges::Full;
+                }
+                if start >= len {
+                    continue;
+                }
+                ByteRange {
+                    start,
+                    end: end.min(len - 1),
+                }
+            }
+        };
+        ranges.push(range);
+    }
+    if !any || ranges.len() > MAX_RANGES {
+        return Ranges::Full;
+    }
+    if ranges.is_empty() {
+        return Ranges::Unsatisfiable;
+    }
+    return Ranges::Partial(ranges);
+}
+
+/// Whether the representation is still the one `If-Range` names, so its ranges can be sent.
+/// Entity tags are compared strongly, dates have to match the modification time exactly.
+pub fn if_range_holds(
+    if_range: &str,
+    etag: Option<&str>,
+    last_modified: Option<SystemTime>,
+) -> bool {
+    let if_range = if_range.trim();
+    if if_range.starts_with('"') || if_range.starts_with("W/") {
+        return etag.is_some_and(|etag| {
+            return !etag.starts_with("W/") && !if_range.starts_with("W/") && etag == if_range;
+        });
+    }
+    let Some(date) = http_date::parse(if_range) else {
+        return false;
+    };
+    return last_modified.is_some_and(|modified| return http_date::same_second(modified, date));
+}
+
+/// The response to `req` for a representation read from `source`, whole or only the ranges the
+/// request asks for. Only the requested bytes are read, and no more than `MAX_BUFFERED` of them.
+pub fn serve(
+    req: &Request,
+    source: &mut (impl Read + Seek),
+    len: u64,
+    content_type: &str,
+    etag: Option<&str>,
+    last_modified: Option<SystemTime>,
+) -> io::Result<Response> {
+    let ranges = match req.header("Range") {
+        Some(header) if req.method.is_get() => {
+            let current = req.header("If-Range").is_none_or(|if_range| {
+                return if_range_holds(if_range, etag, last_modified);
+            });
+            if current {
+                parse(header, len)
+            } else {
+                Ranges::Full
+            }
+        }
+        _ => Ranges::Full,
+    };
+    let ranges = match ranges {
+        Ranges::Partial(mut ranges) if ranges.len() == 1 => {
+            let range = &mut ranges[0];
+            range.end = range.end.min(range.start + MAX_BUFFERED - 1);
+            Ranges::Partial(ranges)
+        }
+        ranges => ranges,
+    };
+    let selected = match &ranges {
+        Ranges::Full => len,
+        Ranges::Partial(ranges) => ranges.iter().map(ByteRange::len).sum(),
+        Ranges::Unsatisfiable => 0,
+    };
+    let mut response = match ranges {
+        _ if selected > MAX_BUFFERED => {
+            let message = format!("More than {} bytes, ask for ranges", MAX_BUFFERED);
+            (StatusCode::PAYLOAD_TOO_LARGE, message).into_response()
+        }
+        Ranges::Full => {
+            let mut response = Response::new(StatusCode::OK);
+            response.set_header("Content-Type", content_type);
+            source.seek(SeekFrom::Start(0))?;
+            source.read_to_end(&mut response.body)?;
+            response
+        }
+        Ranges::Partial(ranges) => partial(source, len, &ranges, content_type)?,
+        Ranges::Unsatisfiable => unsatisfiable(len),
+    };
+    response.set_header("Accept-Ranges", "bytes");
+    return Ok(response);
+}
+
+/// A 206 with the ranges of `source`, as a `multipart/byteranges` body for more than one.
+pub fn partial(
+    source: &mut (impl Read + Seek),
+    len: u64,
+    ranges: &[ByteRange],
+    content_type: &str,
+) -> io::Result<Response> {
+    let mut response = Response::new(StatusCode::PARTIAL_CONTENT);
+    if let [range] = ranges {
+        response.set_header("Content-Type", content_type);
+        response.set_header("Content-Range", content_range(range, len));
+        response.body = read_range(source, range)?;
+        return Ok(response);
+    }
+
+    let boundary = boundary();
+    for range in ranges {
+        let head = format!(
+            "--{}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+            boundary,
+            content_type,
+            content_range(range, len)
+        );
+        response.body.extend(head.as_bytes());
+        response.body.extend(read_range(source, range)?);
+        response.body.extend(b"\r\n");
+    }
+    response
+        .body
+        .extend(format!("--{}--\r\n", boundary).as_bytes());
+    response.set_header(
+        "Content-Type",
+        format!("multipart/byteranges; boundary={}", boundary),
+    );
+    return Ok(response);
+}
+
+pub fn unsatisfiable(len: u64) -> Response {
+    let mut response = Response::new(StatusCode::RANGE_NOT_SATISFIABLE);
+    response.set_header("Content-Range", format!("bytes */{}", len));
+    return response;
+}
+
+fn content_range(range: &ByteRange, len: u64) -> String {
+    return format!("bytes {}-{}/{}", range.start, range.end, len);
+}
+
+fn read_range(source: &mut (impl Read + Seek), range: &ByteRange) -> io::Result<Vec<u8>> {
+    source.seek(SeekFrom::Start(range.start))?;
+    let mut buffer = Vec::new();
+    source.take(range.len()).read_to_end(&mut buffer)?;
+    return Ok(buffer);
+}
+
+// random per response, a part that happens to contain it would end the part early
+fn boundary() -> String {
+    let random = RandomState::new().build_hasher().finish();
+    return format!("byteranges_{:016x}", random);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::http_date;
+    use crate::core::range::{
+        if_range_holds, parse, serve, ByteRange, Ranges, MAX_BUFFERED, MAX_RANGES,
+    };
+    use crate::core::request::Request;
+    use crate::core::response::{Response, StatusCode};
+    use std::io::Cursor;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn range(start: u64, end: u64) -> ByteRange {
+        return ByteRange { start, end };
+    }
+
+    #[test]
+    fn range_parse() {
+        assert_eq!(parse("bytes=0-4", 10), Ranges::Partial(vec![range(0, 4)]));
+        assert_eq!(parse("bytes=5-", 10), Ranges::Partial(vec![range(5, 9)]));
+        assert_eq!(parse("bytes=-3", 10), Ranges::Partial(vec![range(7, 9)]));
+        assert_eq!(parse("bytes=-30", 10), Ranges::Partial(vec![range(0, 9)]));
+        assert_eq!(parse("bytes=8-20", 10), Ranges::Partial(vec![range(8, 9)]));
+        assert_eq!(
+            parse("bytes=0-0, 20-30, -1", 10),
+            Ranges::Partial(vec![range(0, 0), range(9, 9)])
+        );
+
+        assert_eq!(parse("bytes=10-", 10), Ranges::Unsatisfiable);
+        assert_eq!(parse("bytes=-0", 10), Ranges::Unsatisfiable);
+        assert_eq!(parse("bytes=0-", 0), Ranges::Unsatisfiable);
+
+        assert_eq!(parse("items=0-4", 10), Ranges::Full);
+        assert_eq!(parse("bytes=4-2", 10), Ranges::Full);
+        assert_eq!(parse("bytes=a-2", 10), Ranges::Full);
+        assert_eq!(parse("bytes=", 10), Ranges::Full);
+        let many = vec!["0-0"; MAX_RANGES + 1].join(",");
+        assert_eq!(parse(&format!("bytes={}", many), 10), Ranges::Full);
+
+        assert_eq!(range(3, 3).len(), 1);
+        assert!(!range(3, 3).is_empty());
+        assert_eq!(range(4, 3).len(), 0);
+        assert!(range(4, 3).is_empty());
+    }
+
+    #[test]
+    fn range_if_range() {
+        let modified = UNIX_EPOCH + Duration::from_secs(784111777);
+        let date = http_date::format(modified);
+        assert!(if_range_holds(&date, None, Some(modified)));
+        assert!(!if_range_holds(
+            &date,
+            None,
+            Some(modified + Duration::from_secs(1))
+        ));
+        assert!(if_range_holds("\"v1\"", Some("\"v1\""), None));
+        assert!(!if_range_holds("\"v1\"", Some("\"v2\""), None));
+        assert!(!if_range_holds("W/\"v1\"", Some("W/\"v1\""), None));
+        assert!(!if_range_holds("garbage", Some("\"v1\""), Some(modified)));
+    }
+
+    // a GET of "0123456789" tagged "v1"
+    fn get(headers: &str) -> Response {
+        let raw = format!("GET /f HTTP/1.1\r\n{}\r\n", headers);
+        let request = Request::from(raw.as_bytes()).unwrap();
+        let mut source = Cursor::new(b"0123456789".to_vec());
+        let etag = Some("\"v1\"");
+        return serve(&request, &mut source, 10, "text/plain", etag, None).unwrap();
+    }
+
+    #[test]
+    fn range_serve() {
+        let full = get("");
+        assert_eq!(full.status, StatusCode::OK);
+        assert_eq!(full.body, b"0123456789".to_vec());
+        assert_eq!(full.header("Accept-Ranges"), Some("bytes"));
+
+        let single = get("Range: bytes=2-4\r\n");
+        assert_eq!(single.status, StatusCode::PARTIAL_CONTENT);
+        assert_eq!(single.body, b"234".to_vec());
+        assert_eq!(single.header("Content-Range"), Some("bytes 2-4/10"));
+
+        let stale = get("Range: bytes=2-4\r\nIf-Range: \"v0\"\r\n");
+        assert_eq!(stale.status, StatusCode::OK);
+        let current = get("Range: bytes=2-4\r\nIf-Range: \"v1\"\r\n");
+        assert_eq!(current.status, StatusCode::PARTIAL_CONTENT);
+
+        let missed = get("Range: bytes=20-\r\n");
+        assert_eq!(missed.status, StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(missed.header("Content-Range"), Some("bytes */10"));
+
+        let multi = get("Range: bytes=0-1,-2\r\n");
+        assert_eq!(multi.status, StatusCode::PARTIAL_CONTENT);
+        let content_type = multi.header("Content-Type").unwrap();
+        let boundary = content_type
+            .strip_prefix("multipart/byteranges; boundary=")
+            .unwrap();
+        let expected = format!(
+            "--{b}\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-1/10\r\n\r\n01\r\n\
+             --{b}\r\nContent-Type: text/plain\r\nContent-Range: bytes 8-9/10\r\n\r\n89\r\n\
+             --{b}--\r\n",
+            b = boundary
+        );
+        assert_eq!(String::from_utf8(multi.body).unwrap(), expected);
+    }
+
+    #[test]
+    fn range_serve_bounded() {
+        // only the announced length is large, nothing past the bound is read to tell
+        let len = MAX_BUFFERED * 2;
+        let serve_large = |headers: &str| {
+            let raw = format!("GET /f HTTP/1.1\r\n{}\r\n", headers);
+            let request = Request::from(raw.as_bytes()).unwrap();
+            let mut source = Cursor::new(b"0123456789".to_vec());
+            return serve(&request, &mut source, len, "text/plain", None, None).unwrap();
+        };
+        let whole = serve_large("");
+        assert_eq!(whole.status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(whole.header("Accept-Ranges"), Some("bytes"));
+
+        let rest = serve_large("Range: bytes=5-\r\n");
+        assert_eq!(rest.status, StatusCode::PARTIAL_CONTENT);
+        let content_range = format!("bytes 5-{}/{}", MAX_BUFFERED + 4, len);
+        assert_eq!(rest.header("Content-Range"), Some(content_range.as_str()));
+        assert_eq!(rest.body, b"56789".to_vec());
+
+        let many = format!("Range: bytes=0-{},-2\r\n", MAX_BUFFERED);
+        assert_eq!(serve_large(&many).status, StatusCode::PAYLOAD_TOO_LARGE);
+        let fits = "Range: bytes=0-1,-2\r\n";
+        assert_eq!(serve_large(fits).status, StatusCode::PARTIAL_CONTENT);
+    }
+}
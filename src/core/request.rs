@@ -1,3 +1,5 @@
+use crate::core::extensions::Extensions;
+use crate::core::urlencoded::{self, FormError, Params};
 use anyhow::anyhow;
 use anyhow::Result;
 use itertools::Itertools;
@@ -6,13 +8,34 @@ use std::collections::HashMap;
  * RFC: https://datatracker.ietf.org/doc/html/rfc9112
  */
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum HttpMethod {
     Get,
     Post,
+    Put,
+    Delete,
+    Head,
+    Patch,
 }
 
-#[derive(PartialEq, Debug)]
+impl HttpMethod {
+    /// Every method a request line is parsed into.
+    pub const ALL: [HttpMethod; 6] = [
+        HttpMethod::Get,
+        HttpMethod::Post,
+        HttpMethod::Put,
+        HttpMethod::Delete,
+        HttpMethod::Head,
+        HttpMethod::Patch,
+    ];
+
+    /// `GET` and `HEAD`, which only read and get the same answer apart from the body.
+    pub fn is_get(&self) -> bool {
+        return matches!(self, HttpMethod::Get | HttpMethod::Head);
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
 
 pub enum Version {
     Http1_1,
@@ -20,17 +43,27 @@ pub enum Version {
 
 // Request only lives as long as the TCP buffer so we tie the lifetime of the Request to that buffer
 // Request is passive, should be ok to make the fields public
+#[derive(Clone)]
 pub struct Request<'a> {
     pub method: HttpMethod,
     pub path: &'a str,
+    // everything after the `?`, still percent encoded
+    pub query: Option<&'a str>,
     pub _version: Version,
     pub headers: HashMap<&'a str, &'a str>,
-    pub body: Option<&'a str>,
+    // anything, only the head has to be text
+    pub body: Option<&'a [u8]>,
+    // filled in by layers for the handlers further in
+    pub extensions: Extensions,
 }
 
 impl<'a> Request<'a> {
     pub fn from(read_buffer: &'a [u8]) -> Result<Request<'a>> {
-        let Ok(request) = std::str::from_utf8(read_buffer) else {
+        let (head, rest) = match head_len(read_buffer) {
+            Some(len) => read_buffer.split_at(len),
+            None => (read_buffer, &[][..]),
+        };
+        let Ok(request) = std::str::from_utf8(head) else {
             return Err(anyhow!("Invalid UTF-8 encoding"));
         };
 
@@ -50,14 +83,21 @@ impl<'a> Request<'a> {
         let method = match status_line[0] {
             "GET" => HttpMethod::Get,
             "POST" => HttpMethod::Post,
+            "PUT" => HttpMethod::Put,
+            "DELETE" => HttpMethod::Delete,
+            "HEAD" => HttpMethod::Head,
+            "PATCH" => HttpMethod::Patch,
             _ => {
                 return Err(anyhow!(
-                    "Invalid HTTP verb, only Get and Post are supported at this time"
+                    "Invalid HTTP verb, only GET, POST, PUT, DELETE, HEAD and PATCH are supported"
                 ))
             }
         };
 
-        let path = status_line[1];
+        let (path, query) = match status_line[1].split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (status_line[1], None),
+        };
 
         // from the spec, an empty line seperates the headers and status line from the body
         let mut empty_line = None;
@@ -71,15 +111,17 @@ impl<'a> Request<'a> {
         }
 
         let mut headers = HashMap::new();
-        let mut body = None;
+        let body = None;
 
         let Some(empty_line) = empty_line else {
             return Ok(Request {
                 method,
                 path,
+                query,
                 _version: version,
                 headers,
                 body,
+                extensions: Extensions::new(),
             });
         };
 
@@ -95,37 +137,83 @@ impl<'a> Request<'a> {
             headers.insert(key_val[0], key_val[1]);
         }
 
-        //not supporting case-insensitive strings at the moment as that would require ownership
-        if let Some(n) = headers.get("Content-Length") {
-            let n = n.parse::<usize>();
+        let mut request = Request {
+            method,
+            path,
+            query,
+            _version: version,
+            headers,
+            body,
+            extensions: Extensions::new(),
+        };
+        // found whatever its case, as `message_len` finds it when the request is read
+        if let Some(n) = request.header("Content-Length") {
+            let n = n.trim().parse::<usize>();
             let Ok(n) = n else {
                 return Err(anyhow!("Unable to parse Content-Length header"));
             };
-            let body_idx = empty_line + 1;
-            if lines.len() <= body_idx {
+            // everything after the empty line, the body may have line breaks of its own
+            if head.len() == read_buffer.len() && n > 0 {
                 return Err(anyhow!(
                     "Content-Length specified, but no body was provided."
                 ));
             }
-            if n > lines[body_idx].len() {
+            let Some(rest) = rest.get(0..n) else {
                 return Err(anyhow!("Content-Length specified is larger than body"));
-            }
-            body = Some(&lines[body_idx][0..n]);
+            };
+            request.body = Some(rest);
         }
 
-        return Ok(Request {
-            method,
-            path,
-            _version: version,
-            headers,
-            body,
-        });
+        return Ok(request);
+    }
+
+    /// Header lookup ignoring the case of the name, as the spec asks for.
+    pub fn header(&self, name: &str) -> Option<&'a str> {
+        return self
+            .headers
+            .iter()
+            .find(|(key, _)| return key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| return *value);
     }
+
+    /// The decoded query string, a request without one has no params.
+    pub fn query_params(&self) -> Result<Params> {
+        return Params::parse(self.query.unwrap_or(""));
+    }
+
+    /// The decoded `application/x-www-form-urlencoded` body, a body of another type is refused.
+    pub fn form(&self) -> Result<Params, FormError> {
+        let body = self.body.unwrap_or_default();
+        return urlencoded::parse_form(self.header("Content-Type"), body);
+    }
+}
+
+/// Length of the request line and headers up to and including the empty line, once all of it is
+/// in `buffer`.
+pub fn head_len(buffer: &[u8]) -> Option<usize> {
+    return buffer
+        .windows(4)
+        .position(|window| return window == b"\r\n\r\n")
+        .map(|position| return position + 4);
+}
+
+/// Length of the whole request, head and body, once the head is in `buffer`.
+pub fn message_len(buffer: &[u8]) -> Option<usize> {
+    let head_len = head_len(buffer)?;
+    let head = String::from_utf8_lossy(&buffer[..head_len]);
+    let content_length = head
+        .split("\r\n")
+        .filter_map(|line| return line.split_once(':'))
+        .find(|(name, _)| return name.trim().eq_ignore_ascii_case("Content-Length"))
+        .and_then(|(_, value)| return value.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    return Some(head_len + content_length);
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::core::request::{HttpMethod, Request, Version};
+    use crate::core::request::{message_len, HttpMethod, Request, Version};
+    use crate::core::urlencoded::FormError;
 
     #[test]
     fn request_home() {
@@ -214,7 +302,7 @@ mod tests {
         assert_eq!(parsed.path, path);
         assert_eq!(parsed._version, Version::Http1_1);
         assert!(!parsed.headers.is_empty());
-        assert_eq!(parsed.body, Some(data_write));
+        assert_eq!(parsed.body, Some(data_write.as_bytes()));
         let len_str = data_write.len().to_string();
         assert_eq!(
             parsed.headers.get("Content-Length"),
@@ -222,6 +310,71 @@ mod tests {
         );
     }
 
+    #[test]
+    fn request_body_with_line_breaks() {
+        let data = "first\r\n\r\nsecond\r\n";
+        let request = format!(
+            "PUT /files/potato HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+            data.len(),
+            data
+        );
+        let parsed = Request::from(request.as_bytes()).unwrap();
+        assert_eq!(parsed.body, Some(data.as_bytes()));
+
+        let request = "PUT /files/potato HTTP/1.1\r\nContent-Length: 50\r\n\r\nshort";
+        assert!(Request::from(request.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn request_body_with_lower_case_length() {
+        let request = "PUT /files/potato HTTP/1.1\r\ncontent-length: 5\r\n\r\nshort and more";
+        assert_eq!(message_len(request.as_bytes()), Some(request.len() - 9));
+        let parsed = Request::from(request.as_bytes()).unwrap();
+        assert_eq!(parsed.body, Some(&b"short"[..]));
+        assert_eq!(parsed.header("Content-Length"), Some("5"));
+    }
+
+    #[test]
+    fn request_query_and_form() {
+        let body = "name=a+b&tag=1&tag=2";
+        let request = format!(
+            "POST /form?page=2&q=%2F HTTP/1.1\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let parsed = Request::from(request.as_bytes()).unwrap();
+        let query = parsed.query_params().unwrap();
+        assert_eq!(query.get("q"), Some("/"));
+        let form = parsed.form().unwrap();
+        assert_eq!(form.get("name"), Some("a b"));
+        assert_eq!(form.get_all("tag").collect::<Vec<_>>(), ["1", "2"]);
+
+        let request =
+            "POST /form HTTP/1.1\r\nContent-Type: text/plain\r\nContent-Length: 3\r\n\r\na=1";
+        let parsed = Request::from(request.as_bytes()).unwrap();
+        assert!(parsed.query_params().unwrap().is_empty());
+        assert!(matches!(
+            parsed.form(),
+            Err(FormError::UnsupportedMediaType(_))
+        ));
+    }
+
+    #[test]
+    fn request_methods() {
+        for (verb, method) in [
+            ("PUT", HttpMethod::Put),
+            ("DELETE", HttpMethod::Delete),
+            ("HEAD", HttpMethod::Head),
+            ("PATCH", HttpMethod::Patch),
+        ] {
+            let request = format!("{} /files/potato HTTP/1.1\r\n\r\n", verb);
+            let parsed = Request::from(request.as_bytes()).unwrap();
+            assert_eq!(parsed.method, method);
+        }
+        assert!(HttpMethod::Head.is_get());
+        assert!(!HttpMethod::Put.is_get());
+    }
+
     #[test]
     fn request_err_invalid_verb() {
         let path = "/ajhkgdslf/aiuay783924/h78^&*%2345";
@@ -321,4 +474,24 @@ mod tests {
         assert!(Request::from(OCTET_4).is_err());
         assert!(Request::from(OCTET_5).is_err());
     }
+
+    #[test]
+    fn request_query() {
+        let request = "GET /search?q=rust&page=2 HTTP/1.1\r\n\r\n";
+        let parsed = Request::from(request.as_bytes()).unwrap();
+        assert_eq!(parsed.path, "/search");
+        assert_eq!(parsed.query, Some("q=rust&page=2"));
+
+        let request = "GET /search HTTP/1.1\r\n\r\n";
+        let parsed = Request::from(request.as_bytes()).unwrap();
+        assert_eq!(parsed.query, None);
+    }
+
+    #[test]
+    fn request_header_ignores_case() {
+        let request = "GET / HTTP/1.1\r\nuser-agent: curl/7.64.1\r\n\r\n";
+        let parsed = Request::from(request.as_bytes()).unwrap();
+        assert_eq!(parsed.header("User-Agent"), Some("curl/7.64.1"));
+        assert_eq!(parsed.header("Host"), None);
+    }
 }
@@ -1,5 +1,282 @@
 #![allow(dead_code)]
 
-pub const OK: &[u8; 19] = b"HTTP/1.1 200 OK\r\n\r\n";
-pub const CREATED: &[u8; 24] = b"HTTP/1.1 201 CREATED\r\n\r\n";
-pub const NOT_FOUND: &[u8; 26] = b"HTTP/1.1 404 Not Found\r\n\r\n";
+use crate::core::extensions::Extensions;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatusCode(pub u16);
+
+impl StatusCode {
+    pub const OK: StatusCode = StatusCode(200);
+    pub const CREATED: StatusCode = StatusCode(201);
+    pub const NO_CONTENT: StatusCode = StatusCode(204);
+    pub const PARTIAL_CONTENT: StatusCode = StatusCode(206);
+    pub const MOVED_PERMANENTLY: StatusCode = StatusCode(301);
+    pub const NOT_MODIFIED: StatusCode = StatusCode(304);
+    pub const PERMANENT_REDIRECT: StatusCode = StatusCode(308);
+    pub const BAD_REQUEST: StatusCode = StatusCode(400);
+    pub const FORBIDDEN: StatusCode = StatusCode(403);
+    pub const NOT_FOUND: StatusCode = StatusCode(404);
+    pub const METHOD_NOT_ALLOWED: StatusCode = StatusCode(405);
+    pub const CONFLICT: StatusCode = StatusCode(409);
+    pub const LENGTH_REQUIRED: StatusCode = StatusCode(411);
+    pub const PRECONDITION_FAILED: StatusCode = StatusCode(412);
+    pub const PAYLOAD_TOO_LARGE: StatusCode = StatusCode(413);
+    pub const UNSUPPORTED_MEDIA_TYPE: StatusCode = StatusCode(415);
+    pub const RANGE_NOT_SATISFIABLE: StatusCode = StatusCode(416);
+    pub const INTERNAL_SERVER_ERROR: StatusCode = StatusCode(500);
+
+    pub fn reason(&self) -> &'static str {
+        match self.0 {
+            200 => return "OK",
+            201 => return "Created",
+            204 => return "No Content",
+            206 => return "Partial Content",
+            301 => return "Moved Permanently",
+            304 => return "Not Modified",
+            308 => return "Permanent Redirect",
+            400 => return "Bad Request",
+            403 => return "Forbidden",
+            404 => return "Not Found",
+            405 => return "Method Not Allowed",
+            409 => return "Conflict",
+            411 => return "Length Required",
+            412 => return "Precondition Failed",
+            413 => return "Content Too Large",
+            415 => return "Unsupported Media Type",
+            416 => return "Range Not Satisfiable",
+            500 => return "Internal Server Error",
+            _ => return "",
+        }
+    }
+}
+
+impl fmt::Display for StatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "{} {}", self.0, self.reason());
+    }
+}
+
+/// A response before it is framed, handlers build one through `IntoResponse`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: StatusCode,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    // for layers further out, never written to the connection
+    pub extensions: Extensions,
+}
+
+impl Response {
+    pub fn new(status: StatusCode) -> Response {
+        return Response {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+            extensions: Extensions::new(),
+        };
+    }
+
+    /// Header lookup ignoring the case of the name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        return self
+            .headers
+            .iter()
+            .find(|(key, _)| return key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| return value.as_str());
+    }
+
+    /// Sets a header, replacing any value it already had.
+    pub fn set_header(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        let name = name.into();
+        self.headers
+            .retain(|(key, _)| return !key.eq_ignore_ascii_case(&name));
+        self.headers.push((name, value.into()));
+    }
+
+    /// The response as written to the connection, `Content-Length` is always derived from the body
+    /// for the statuses that may have one.
+    pub fn into_bytes(self) -> Vec<u8> {
+        let mut bytes = self.head().into_bytes();
+        bytes.extend(self.body);
+        return bytes;
+    }
+
+    /// The response to a `HEAD` request, the headers `into_bytes` would write and no body.
+    pub fn into_head_bytes(self) -> Vec<u8> {
+        return self.head().into_bytes();
+    }
+
+    fn head(&self) -> String {
+        let mut head = format!("HTTP/1.1 {}\r\n", self.status);
+        for (name, value) in &self.headers {
+            if !name.eq_ignore_ascii_case("Content-Length") {
+                head.push_str(&format!("{}: {}\r\n", name, value));
+            }
+        }
+        // 204 and 304 never have a body, the length would be wrong or is forbidden
+        if self.status != StatusCode::NO_CONTENT && self.status != StatusCode::NOT_MODIFIED {
+            head.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+        }
+        head.push_str("\r\n");
+        return head;
+    }
+}
+
+pub trait IntoResponse {
+    fn into_response(self) -> Response;
+}
+
+impl IntoResponse for Response {
+    fn into_response(self) -> Response {
+        return self;
+    }
+}
+
+impl IntoResponse for StatusCode {
+    fn into_response(self) -> Response {
+        return Response::new(self);
+    }
+}
+
+fn with_body(content_type: &str, body: Vec<u8>) -> Response {
+    let mut response = Response::new(StatusCode::OK);
+    response.set_header("Content-Type", content_type);
+    response.body = body;
+    return response;
+}
+
+impl IntoResponse for &str {
+    fn into_response(self) -> Response {
+        return with_body("text/plain", self.as_bytes().to_vec());
+    }
+}
+
+impl IntoResponse for String {
+    fn into_response(self) -> Response {
+        return with_body("text/plain", self.into_bytes());
+    }
+}
+
+impl IntoResponse for Vec<u8> {
+    fn into_response(self) -> Response {
+        return with_body("application/octet-stream", self);
+    }
+}
+
+impl<B: IntoResponse> IntoResponse for (StatusCode, B) {
+    fn into_response(self) -> Response {
+        let (status, body) = self;
+        let mut response = body.into_response();
+        response.status = status;
+        return response;
+    }
+}
+
+impl<H, K, V, B> IntoResponse for (StatusCode, H, B)
+where
+    H: IntoIterator<Item = (K, V)>,
+    K: Into<String>,
+    V: Into<String>,
+    B: IntoResponse,
+{
+    fn into_response(self) -> Response {
+        let (status, headers, body) = self;
+        let mut response = (status, body).into_response();
+        for (name, value) in headers {
+            response.set_header(name, value);
+        }
+        return response;
+    }
+}
+
+impl<T: IntoResponse> IntoResponse for Option<T> {
+    fn into_response(self) -> Response {
+        match self {
+            Some(value) => return value.into_response(),
+            None => return Response::new(StatusCode::NOT_FOUND),
+        }
+    }
+}
+
+impl<T: IntoResponse, E: IntoResponse> IntoResponse for Result<T, E> {
+    fn into_response(self) -> Response {
+        match self {
+            Ok(value) => return value.into_response(),
+            Err(e) => return e.into_response(),
+        }
+    }
+}
+
+// handlers have always answered 404 for any error, `anyhow::Result` handlers keep doing so
+impl IntoResponse for anyhow::Error {
+    fn into_response(self) -> Response {
+        return Response::new(StatusCode::NOT_FOUND);
+    }
+}
+
+pub fn permanent_redirect(location: &str) -> Response {
+    let mut response = Response::new(StatusCode::PERMANENT_REDIRECT);
+    response.set_header("Location", location);
+    return response;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::response::{IntoResponse, Response, StatusCode};
+    use anyhow::anyhow;
+
+    fn framed(response: impl IntoResponse) -> String {
+        return String::from_utf8(response.into_response().into_bytes()).unwrap();
+    }
+
+    #[test]
+    fn response_framing() {
+        assert_eq!(
+            framed("abc"),
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 3\r\n\r\nabc"
+        );
+        assert_eq!(
+            framed(StatusCode::CREATED),
+            "HTTP/1.1 201 Created\r\nContent-Length: 0\r\n\r\n"
+        );
+        assert_eq!(
+            framed(StatusCode::NO_CONTENT),
+            "HTTP/1.1 204 No Content\r\n\r\n"
+        );
+        assert_eq!(
+            String::from_utf8("abc".into_response().into_head_bytes()).unwrap(),
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 3\r\n\r\n"
+        );
+        assert_eq!(
+            framed(vec![1u8, 2]),
+            "HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\nContent-Length: 2\r\n\r\n\u{1}\u{2}"
+        );
+    }
+
+    #[test]
+    fn response_status_and_headers() {
+        let response = (StatusCode::BAD_REQUEST, String::from("nope")).into_response();
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(response.body, b"nope".to_vec());
+
+        let headers = [("Content-Type", "text/html"), ("X-Id", "7")];
+        let response = (StatusCode::OK, headers, "<p>").into_response();
+        assert_eq!(response.header("content-type"), Some("text/html"));
+        assert_eq!(response.header("X-Id"), Some("7"));
+        assert_eq!(response.headers.len(), 2);
+    }
+
+    #[test]
+    fn response_option_and_result() {
+        assert_eq!(None::<String>.into_response().status, StatusCode::NOT_FOUND);
+        assert_eq!(Some("a").into_response().status, StatusCode::OK);
+
+        let ok: Result<&str, StatusCode> = Ok("a");
+        assert_eq!(ok.into_response().body, b"a".to_vec());
+        let err: Result<&str, StatusCode> = Err(StatusCode::FORBIDDEN);
+        assert_eq!(err.into_response(), Response::new(StatusCode::FORBIDDEN));
+        let err: anyhow::Result<String> = Err(anyhow!("missing"));
+        assert_eq!(err.into_response().status, StatusCode::NOT_FOUND);
+    }
+}
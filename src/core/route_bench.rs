@@ -0,0 +1,132 @@
+//! Compares the `Routeable` implementations against a gateway sized route set.
+//!
+//! The timing runs are ignored by default since they only mean something in release mode:
+//! `cargo test --release route_bench -- --ignored --nocapture`
+
+use crate::core::request::Request;
+use crate::core::route_radix::RouteRadix;
+use crate::core::route_table::RouteTable;
+use crate::core::route_trie::RouteTrie;
+use crate::core::routing::{Identifiers, Routeable};
+use anyhow::Result;
+use std::hint::black_box;
+use std::time::Instant;
+
+struct PlaceholderContext {}
+
+fn thunk(_req: &Request, _paths: &Identifiers, _ctx: &PlaceholderContext) -> Result<Vec<u8>> {
+    return Ok(Vec::new());
+}
+
+const VERSIONS: usize = 4;
+const RESOURCES: usize = 100;
+const SHAPES: [&str; 5] = [
+    "",
+    "/{id}",
+    "/{id}/items",
+    "/{id}/items/{item}",
+    "/{id}/owner",
+];
+
+// 2,000 routes
+fn route_set() -> Vec<String> {
+    let mut routes = Vec::new();
+    for version in 1..=VERSIONS {
+        for resource in 0..RESOURCES {
+            for shape in SHAPES {
+                routes.push(format!("/api/v{}/resource{}{}", version, resource, shape));
+            }
+        }
+    }
+    return routes;
+}
+
+// one concrete request path per route
+fn request_paths(routes: &[String]) -> Vec<String> {
+    return routes
+        .iter()
+        .enumerate()
+        .map(|(i, r)| {
+            return r
+                .replace("{id}", &format!("{}", 1000 + i))
+                .replace("{item}", "widget");
+        })
+        .collect();
+}
+
+fn build<T: Routeable<PlaceholderContext>>(routes: &[String]) -> T {
+    let mut table = T::new();
+    for route in routes {
+        table.add_route(route.clone(), thunk).unwrap();
+    }
+    return table;
+}
+
+fn captures<T: Routeable<PlaceholderContext>>(
+    table: &T,
+    path: &str,
+) -> Option<Vec<(String, String)>> {
+    let (_handler, ids) = table.match_route(path)?;
+    let mut values = ids
+        .path_values
+        .iter()
+        .map(|(k, v)| return (k.to_string(), v.to_string()))
+        .collect::<Vec<_>>();
+    values.sort();
+    return Some(values);
+}
+
+#[test]
+fn route_bench_implementations_agree() {
+    let routes = route_set();
+    let trie: RouteTrie<_> = build(&routes);
+    let table: RouteTable<_> = build(&routes);
+    let radix: RouteRadix<_> = build(&routes);
+
+    let misses = [
+        "/api/v9/resource1",
+        "/api/v1/resource1/1/missing",
+        "/api/v1/resource100",
+    ];
+    let paths = request_paths(&routes);
+    for path in paths.iter().map(|p| return p.as_str()).chain(misses) {
+        let expected = captures(&table, path);
+        assert_eq!(captures(&trie, path), expected, "trie: {}", path);
+        assert_eq!(captures(&radix, path), expected, "radix: {}", path);
+    }
+}
+
+fn time_lookups<T: Routeable<PlaceholderContext>>(name: &str, routes: &[String]) {
+    const ROUNDS: usize = 20;
+
+    let start = Instant::now();
+    let table: T = build(routes);
+    let built = start.elapsed();
+
+    let paths = request_paths(routes);
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        for path in &paths {
+            black_box(table.match_route(black_box(path)).is_some());
+        }
+    }
+    let elapsed = start.elapsed();
+    let lookups = (ROUNDS * paths.len()) as u32;
+    println!(
+        "{:<6} build {:>10.2?}  lookup {:>10.2?}/op over {} lookups",
+        name,
+        built,
+        elapsed / lookups,
+        lookups
+    );
+}
+
+#[test]
+#[ignore]
+fn route_bench_lookup() {
+    let routes = route_set();
+    println!("{} routes", routes.len());
+    time_lookups::<RouteTrie<_>>("trie", &routes);
+    time_lookups::<RouteTable<_>>("table", &routes);
+    time_lookups::<RouteRadix<_>>("radix", &routes);
+}
@@ -0,0 +1,308 @@
+use crate::core::introspect::RouteMeta;
+use crate::core::middleware::{Layer, Layered, Middleware};
+use crate::core::request::Request;
+use crate::core::response::Response;
+use crate::core::routing::{split_after_segments, Handler, Identifiers, RouteHandler};
+use crate::core::shared_str::SharedStr;
+use anyhow::anyhow;
+use anyhow::Result;
+use std::sync::Arc;
+
+/// Routes that share a set of layers and a fallback, registered on a `Router` together.
+///
+/// Groups can be mounted at a prefix and nest other groups, so a module can own its routes
+/// without knowing where the server ends up putting them.
+pub struct RouteGroup<Context> {
+    layers: Vec<Layer<Context>>,
+    routes: Vec<(SharedStr, RouteHandler<Context>)>,
+    // name and path of the routes added with `handle_named`
+    names: Vec<(SharedStr, SharedStr)>,
+    fallback: Option<RouteHandler<Context>>,
+    // handlers see the path below the prefix the group is mounted at
+    strip_prefix: bool,
+    nested: Vec<(SharedStr, RouteGroup<Context>)>,
+}
+
+/// A group flattened into handlers for full paths, ready to be registered.
+pub struct Mounted<Context> {
+    pub routes: Vec<(SharedStr, Layered<Context>)>,
+    // keyed by the prefix the fallback answers for
+    pub fallbacks: Vec<(SharedStr, Layered<Context>)>,
+    // route names with the full path they stand for
+    pub names: Vec<(SharedStr, SharedStr)>,
+}
+
+impl<Context: 'static> RouteGroup<Context> {
+    pub fn new() -> Self {
+        return RouteGroup {
+            layers: Vec::new(),
+            routes: Vec::new(),
+            names: Vec::new(),
+            fallback: None,
+            strip_prefix: false,
+            nested: Vec::new(),
+        };
+    }
+
+    /// Adds a layer to every route of the group, including the ones already added and the ones
+    /// of nested groups. Layers of outer groups run first.
+    pub fn layer(&mut self, middleware: impl Middleware<Context> + 'static) {
+        self.layers.push(Arc::new(middleware));
+    }
+
+    pub fn handle(&mut self, path: impl Into<SharedStr>, handler: impl Handler<Context> + 'static) {
+        self.routes.push((path.into(), Arc::new(handler)));
+    }
+
+    /// Adds a route that can be linked to with `url_for` under `name` once the group is mounted.
+    pub fn handle_named(
+        &mut self,
+        name: impl Into<SharedStr>,
+        path: impl Into<SharedStr>,
+        handler: impl Handler<Context> + 'static,
+    ) {
+        let path = path.into();
+        self.names.push((name.into(), path.clone()));
+        self.handle(path, handler);
+    }
+
+    /// Answers requests under the prefix of the group that no route matched. The fallback of the
+    /// innermost group containing the path wins.
+    pub fn fallback(&mut self, handler: impl Handler<Context> + 'static) {
+        self.fallback = Some(Arc::new(handler));
+    }
+
+    /// Hands requests to the handlers of this group, and of the groups nested in it, with the
+    /// prefix the group is mounted at removed from `Request::path`.
+    pub fn strip_prefix(&mut self, enabled: bool) {
+        self.strip_prefix = enabled;
+    }
+
+    pub fn nest(&mut self, prefix: impl Into<SharedStr>, group: RouteGroup<Context>) {
+        self.nested.push((prefix.into(), group));
+    }
+
+    pub fn mount(self, prefix: &str) -> Result<Mounted<Context>> {
+        let prefix = if prefix == "/" { "" } else { prefix };
+        let mut mounted = Mounted {
+            routes: Vec::new(),
+            fallbacks: Vec::new(),
+            names: Vec::new(),
+        };
+        self.flatten(&SharedStr::from(String::from(prefix)), &[], 0, &mut mounted)?;
+        return Ok(mounted);
+    }
+
+    // `strip` is the number of leading path segments handlers should not see, zero for none
+    fn flatten(
+        self,
+        prefix: &SharedStr,
+        outer: &[Layer<Context>],
+        strip: usize,
+        mounted: &mut Mounted<Context>,
+    ) -> Result<()> {
+        check_prefix(prefix)?;
+        let strip = if self.strip_prefix {
+            prefix.split('/').count()
+        } else {
+            strip
+        };
+        let layers = outer
+            .iter()
+            .chain(&self.layers)
+            .cloned()
+            .collect::<Vec<_>>();
+        let wrap = |handler: RouteHandler<Context>| {
+            let handler: RouteHandler<Context> = if strip > 0 {
+                Arc::new(Stripped {
+                    segments: strip,
+                    handler,
+                })
+            } else {
+                handler
+            };
+            return Layered::from_parts(layers.clone(), handler);
+        };
+
+        for (path, handler) in self.routes {
+            mounted.routes.push((join(prefix, &path), wrap(handler)));
+        }
+        for (name, path) in self.names {
+            mounted.names.push((name, join(prefix, &path)));
+        }
+        if let Some(handler) = self.fallback {
+            mounted.fallbacks.push((prefix.clone(), wrap(handler)));
+        }
+        for (inner, group) in self.nested {
+            check_prefix(&inner)?;
+            group.flatten(&join(prefix, &inner), &layers, strip, mounted)?;
+        }
+        return Ok(());
+    }
+}
+
+impl<Context: 'static> Default for RouteGroup<Context> {
+    fn default() -> Self {
+        return RouteGroup::new();
+    }
+}
+
+// prefixes are joined as is, so they either are empty or start with a slash and do not end in one
+fn check_prefix(prefix: &str) -> Result<()> {
+    if prefix.is_empty() || (prefix.starts_with('/') && !prefix.ends_with('/')) {
+        return Ok(());
+    }
+    return Err(anyhow!(
+        "Prefix {} must start with a slash and not end with one",
+        prefix
+    ));
+}
+
+// the root of a group is the prefix itself, `/api` rather than `/api/`
+fn join(prefix: &SharedStr, path: &SharedStr) -> SharedStr {
+    if prefix.is_empty() {
+        return path.clone();
+    }
+    if path.as_str() == "/" {
+        return prefix.clone();
+    }
+    return SharedStr::from(format!("{}{}", prefix, path));
+}
+
+/// The part of the request path a group with `strip_prefix` took off, in `Request::extensions`.
+/// Handlers that link to themselves need it, `Request::path` only has what comes after.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MountPrefix(pub String);
+
+// hands the handler a request whose path starts below the mount prefix
+struct Stripped<Context> {
+    segments: usize,
+    handler: RouteHandler<Context>,
+}
+
+impl<Context> Handler<Context> for Stripped<Context> {
+    fn call(&self, req: &Request, path_vals: &Identifiers, ctx: &Context) -> Response {
+        let (prefix, rest) = split_after_segments(req.path, self.segments);
+        let mut req = req.clone();
+        req.path = if rest.is_empty() { "/" } else { rest };
+        req.extensions.insert(MountPrefix(prefix.to_string()));
+        return self.handler.call(&req, path_vals, ctx);
+    }
+
+    fn meta(&self) -> Option<&RouteMeta> {
+        return self.handler.meta();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::request::Request;
+    use crate::core::route_group::{MountPrefix, RouteGroup};
+    use crate::core::routing::{Captures, Handler, Identifiers};
+
+    struct PlaceholderContext {}
+
+    fn path(req: &Request, _paths: &Identifiers, _ctx: &PlaceholderContext) -> String {
+        return req.path.to_string();
+    }
+
+    fn paths(group: RouteGroup<PlaceholderContext>, prefix: &str) -> Vec<String> {
+        let mounted = group.mount(prefix).unwrap();
+        return mounted
+            .routes
+            .iter()
+            .map(|(path, _)| return path.to_string())
+            .collect();
+    }
+
+    #[test]
+    fn group_joins_prefixes() {
+        let mut inner = RouteGroup::new();
+        inner.handle("/", path);
+        inner.handle("/files/{file}", path);
+        let mut outer = RouteGroup::new();
+        outer.handle("/health", path);
+        outer.nest("/v1", inner);
+
+        assert_eq!(
+            paths(outer, "/api"),
+            vec!["/api/health", "/api/v1", "/api/v1/files/{file}"]
+        );
+    }
+
+    #[test]
+    fn group_names_full_paths() {
+        let mut inner = RouteGroup::new();
+        inner.handle_named("file", "/files/{file}", path);
+        let mut outer = RouteGroup::new();
+        outer.handle_named("root", "/", path);
+        outer.nest("/v1", inner);
+
+        let mounted = outer.mount("/api").unwrap();
+        let names = mounted
+            .names
+            .iter()
+            .map(|(name, path)| return format!("{} {}", name, path))
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["root /api", "file /api/v1/files/{file}"]);
+    }
+
+    #[test]
+    fn group_rejects_bad_prefix() {
+        assert!(RouteGroup::<PlaceholderContext>::new()
+            .mount("api")
+            .is_err());
+        assert!(RouteGroup::<PlaceholderContext>::new()
+            .mount("/api/")
+            .is_err());
+        let mut outer = RouteGroup::<PlaceholderContext>::new();
+        outer.nest("v1", RouteGroup::new());
+        assert!(outer.mount("/api").is_err());
+    }
+
+    #[test]
+    fn group_strips_prefix() {
+        let mut inner = RouteGroup::new();
+        inner.handle("/files/{file}", path);
+        inner.handle("/", path);
+        let mut outer = RouteGroup::new();
+        outer.strip_prefix(true);
+        outer.nest("/v1", inner);
+
+        let mounted = outer.mount("/api").unwrap();
+        let ids = Identifiers::new(Captures::new());
+        let call = |i: usize, raw: &str| {
+            let request = Request::from(raw.as_bytes()).unwrap();
+            let (_, handler) = &mounted.routes[i];
+            let response = handler.call(&request, &ids, &PlaceholderContext {});
+            return String::from_utf8(response.body).unwrap();
+        };
+        assert_eq!(
+            call(0, "GET /api/v1/files/a HTTP/1.1\r\n\r\n"),
+            "/v1/files/a"
+        );
+        assert_eq!(call(1, "GET /api/v1 HTTP/1.1\r\n\r\n"), "/v1");
+    }
+
+    #[test]
+    fn group_records_stripped_prefix() {
+        let prefix = |req: &Request, _paths: &Identifiers, _ctx: &PlaceholderContext| {
+            let prefix = req.extensions.get::<MountPrefix>();
+            return prefix
+                .map(|MountPrefix(p)| return p.clone())
+                .unwrap_or_default();
+        };
+        let mut inner = RouteGroup::new();
+        inner.handle("/{file}", prefix);
+        let mut outer = RouteGroup::new();
+        outer.strip_prefix(true);
+        outer.nest("/v1", inner);
+
+        let mounted = outer.mount("/api/{version}").unwrap();
+        let ids = Identifiers::new(Captures::new());
+        let request = Request::from(b"GET /api/v2/v1/a HTTP/1.1\r\n\r\n").unwrap();
+        let (_, handler) = &mounted.routes[0];
+        let response = handler.call(&request, &ids, &PlaceholderContext {});
+        assert_eq!(response.body, b"/api/v2".to_vec());
+    }
+}
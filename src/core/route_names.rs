@@ -0,0 +1,136 @@
+use crate::core::routing::{parse_path, MatchPolicy, Segments};
+use crate::core::shared_str::SharedStr;
+use crate::core::urlencoded::percent_encode;
+use anyhow::anyhow;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// Routes registered under a name, so links to them are built instead of hard-coding paths.
+///
+/// A router fills it as named routes are added. It is meant to be shared behind an `Arc`, a copy
+/// kept in the context is how handlers get to `url_for`.
+#[derive(Debug)]
+pub struct RouteNames {
+    routes: RwLock<HashMap<SharedStr, Vec<Segments>>>,
+}
+
+impl RouteNames {
+    pub fn new() -> RouteNames {
+        return RouteNames {
+            routes: RwLock::new(HashMap::new()),
+        };
+    }
+
+    pub fn insert(&self, name: impl Into<SharedStr>, path: &SharedStr) -> Result<()> {
+        let name = name.into();
+        let segments = parse_path(path, MatchPolicy::default())?;
+        let mut routes = self
+            .routes
+            .write()
+            .unwrap_or_else(|e| return e.into_inner());
+        if routes.contains_key(&name) {
+            return Err(anyhow!("Route name {} is already taken", name));
+        }
+        routes.insert(name, segments);
+        return Ok(());
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        let routes = self.routes.read().unwrap_or_else(|e| return e.into_inner());
+        return routes.contains_key(name);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let routes = self.routes.read().unwrap_or_else(|e| return e.into_inner());
+        return routes.is_empty();
+    }
+
+    /// Path of the route called `name` with its captures filled in from `params`, percent-encoded.
+    /// Every capture needs exactly one parameter and every parameter has to name a capture.
+    pub fn url_for<K, V>(
+        &self,
+        name: &str,
+        params: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<String>
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let routes = self.routes.read().unwrap_or_else(|e| return e.into_inner());
+        let Some(segments) = routes.get(name) else {
+            return Err(anyhow!("No route is named {}", name));
+        };
+        let mut params = params
+            .into_iter()
+            .map(|(k, v)| return (k.as_ref().to_string(), Some(v.as_ref().to_string())))
+            .collect::<Vec<_>>();
+
+        let mut parts = Vec::with_capacity(segments.len());
+        for segment in segments {
+            match segment {
+                Segments::Literal(literal) => parts.push(literal.to_string()),
+                Segments::Capture(key) => {
+                    // taken out so a key given twice is left over as an extra parameter
+                    let value = params
+                        .iter_mut()
+                        .find(|(k, v)| return k == key.as_str() && v.is_some())
+                        .and_then(|(_, v)| return v.take());
+                    let Some(value) = value else {
+                        return Err(anyhow!("Missing parameter {} for route {}", key, name));
+                    };
+                    parts.push(percent_encode(&value));
+                }
+            }
+        }
+        if let Some((extra, _)) = params.iter().find(|(_, v)| return v.is_some()) {
+            return Err(anyhow!("Unexpected parameter {} for route {}", extra, name));
+        }
+        return Ok(parts.join("/"));
+    }
+}
+
+impl Default for RouteNames {
+    fn default() -> Self {
+        return RouteNames::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::route_names::RouteNames;
+    use crate::core::shared_str::SharedStr;
+
+    #[test]
+    fn route_names_url_for() {
+        let names = RouteNames::new();
+        names.insert("root", &SharedStr::from("/")).unwrap();
+        names
+            .insert("file", &SharedStr::from("/files/{dir}/{file}"))
+            .unwrap();
+
+        assert_eq!(names.url_for("root", [("", ""); 0]).unwrap(), "/");
+        let url = names.url_for("file", [("file", "a b.txt"), ("dir", "x/y")]);
+        assert_eq!(url.unwrap(), "/files/x%2Fy/a%20b.txt");
+    }
+
+    #[test]
+    fn route_names_url_for_errors() {
+        let names = RouteNames::new();
+        names
+            .insert("file", &SharedStr::from("/files/{file}"))
+            .unwrap();
+        assert!(names.insert("file", &SharedStr::from("/other")).is_err());
+
+        let missing = names.url_for("file", [("", ""); 0]).unwrap_err();
+        assert!(missing.to_string().contains("Missing parameter file"));
+        let extra = names
+            .url_for("file", [("file", "a"), ("dir", "b")])
+            .unwrap_err();
+        assert!(extra.to_string().contains("Unexpected parameter dir"));
+        assert!(names
+            .url_for("file", [("file", "a"), ("file", "b")])
+            .is_err());
+        assert!(names.url_for("nope", [("file", "a")]).is_err());
+    }
+}
@@ -0,0 +1,602 @@
+use crate::core::routing::{parse_path, Route};
+use crate::core::routing::{
+    Captures, Handler, Identifiers, MatchPolicy, Routeable, Segments, MAX_CAPTURES,
+};
+use crate::core::shared_str::SharedStr;
+use anyhow::anyhow;
+use anyhow::Result;
+
+// a route broken into the runs of literal text between its captures
+enum Token {
+    Literal(String),
+    Capture(SharedStr),
+}
+
+fn tokenize(segments: &[Segments], policy: &MatchPolicy) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut literal = String::new();
+    for (i, segment) in segments.iter().enumerate() {
+        if i > 0 {
+            literal.push('/');
+        }
+        match segment {
+            Segments::Literal(s) => literal.push_str(&policy.fold(s)),
+            Segments::Capture(key) => {
+                if !literal.is_empty() {
+                    tokens.push(Token::Literal(std::mem::take(&mut literal)));
+                }
+                tokens.push(Token::Capture(key.clone()));
+            }
+        }
+    }
+    if !literal.is_empty() {
+        tokens.push(Token::Literal(literal));
+    }
+    return tokens;
+}
+
+// byte length of the shared prefix, always on a char boundary of both
+fn common_prefix(a: &str, b: &str) -> usize {
+    return a
+        .char_indices()
+        .zip(b.chars())
+        .find(|((_, x), y)| return x != y)
+        .map(|((i, _), _)| return i)
+        .unwrap_or(a.len().min(b.len()));
+}
+
+#[derive(Clone)]
+struct Node {
+    // literal text consumed by this node, folded by the policy
+    prefix: String,
+    // literal children never share a first character
+    children: Vec<Node>,
+    capture: Option<(SharedStr, Box<Node>)>,
+    route: Option<usize>,
+}
+
+impl Node {
+    fn new(prefix: String) -> Node {
+        return Node {
+            prefix,
+            children: Vec::new(),
+            capture: None,
+            route: None,
+        };
+    }
+
+    // `offset` is how much of the leading literal token has already been consumed
+    fn insert(&mut self, tokens: &[Token], offset: usize, route: usize) -> Result<()> {
+        let Some(token) = tokens.first() else {
+            if self.route.is_some() {
+                return Err(anyhow!("This path already has a handler!"));
+            }
+            self.route = Some(route);
+            return Ok(());
+        };
+
+        match token {
+            Token::Literal(literal) => {
+                let rest = &literal[offset..];
+                let Some(first) = rest.chars().next() else {
+                    return self.insert(&tokens[1..], 0, route);
+                };
+                let Some(child) = self
+                    .children
+                    .iter_mut()
+                    .find(|c| return c.prefix.starts_with(first))
+                else {
+                    let mut child = Node::new(rest.to_string());
+                    child.insert(&tokens[1..], 0, route)?;
+                    self.children.push(child);
+                    return Ok(());
+                };
+
+                let shared = common_prefix(&child.prefix, rest);
+                if shared < child.prefix.len() {
+                    // split the child so the shared part becomes its own node
+                    let tail = child.prefix.split_off(shared);
+                    let mut split = Node::new(tail);
+                    std::mem::swap(&mut split.children, &mut child.children);
+                    std::mem::swap(&mut split.capture, &mut child.capture);
+                    std::mem::swap(&mut split.route, &mut child.route);
+                    child.children.push(split);
+                }
+                return child.insert(tokens, offset + shared, route);
+            }
+            Token::Capture(key) => {
+                if let Some((set, _)) = &self.capture {
+                    if set != key {
+                        return Err(anyhow!(
+                            "Add route has capture group with key different than expected"
+                        ));
+                    }
+                } else {
+                    self.capture = Some((key.clone(), Box::new(Node::new(String::new()))));
+                }
+                let Some((_, next)) = self.capture.as_mut() else {
+                    return Err(anyhow!("Failed to set link to capture group"));
+                };
+                return next.insert(&tokens[1..], 0, route);
+            }
+        }
+    }
+
+    // mirrors `remove` without changing anything, the index of the route for exactly `tokens`
+    fn find(&self, tokens: &[Token], offset: usize) -> Option<usize> {
+        let Some(token) = tokens.first() else {
+            return self.route;
+        };
+        match token {
+            Token::Literal(literal) => {
+                let rest = &literal[offset..];
+                let Some(first) = rest.chars().next() else {
+                    return self.find(&tokens[1..], 0);
+                };
+                let child = self
+                    .children
+                    .iter()
+                    .find(|c| return c.prefix.starts_with(first))?;
+                if !rest.starts_with(child.prefix.as_str()) {
+                    return None;
+                }
+                return child.find(tokens, offset + child.prefix.len());
+            }
+            Token::Capture(_) => {
+                let (_, next) = self.capture.as_ref()?;
+                return next.find(&tokens[1..], 0);
+            }
+        }
+    }
+
+    // mirrors `insert`, returns the index of the removed route
+    fn remove(&mut self, tokens: &[Token], offset: usize) -> Option<usize> {
+        let Some(token) = tokens.first() else {
+            return self.route.take();
+        };
+
+        match token {
+            Token::Literal(literal) => {
+                let rest = &literal[offset..];
+                let Some(first) = rest.chars().next() else {
+                    return self.remove(&tokens[1..], 0);
+                };
+                let idx = self
+                    .children
+                    .iter()
+                    .position(|c| return c.prefix.starts_with(first))?;
+                let child = &mut self.children[idx];
+                if !rest.starts_with(child.prefix.as_str()) {
+                    return None;
+                }
+                let removed = child.remove(tokens, offset + child.prefix.len())?;
+                self.compact(idx);
+                return Some(removed);
+            }
+            // capture names are not compared, same as `Route::has_path`
+            Token::Capture(_) => {
+                let (_, next) = self.capture.as_mut()?;
+                let removed = next.remove(&tokens[1..], 0)?;
+                if next.is_empty() {
+                    self.capture = None;
+                }
+                return Some(removed);
+            }
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        return self.route.is_none() && self.children.is_empty() && self.capture.is_none();
+    }
+
+    // drops an emptied literal child, or merges it with its only child to keep the tree compressed
+    fn compact(&mut self, idx: usize) {
+        let child = &mut self.children[idx];
+        if child.is_empty() {
+            self.children.remove(idx);
+            return;
+        }
+        if child.route.is_some() || child.capture.is_some() || child.children.len() != 1 {
+            return;
+        }
+        let Some(only) = child.children.pop() else {
+            return;
+        };
+        child.prefix.push_str(&only.prefix);
+        child.children = only.children;
+        child.capture = only.capture;
+        child.route = only.route;
+    }
+
+    fn reindex(&mut self, from: usize, to: usize) -> bool {
+        if self.route == Some(from) {
+            self.route = Some(to);
+            return true;
+        }
+        if let Some((_, next)) = self.capture.as_mut() {
+            if next.reindex(from, to) {
+                return true;
+            }
+        }
+        return self.children.iter_mut().any(|c| return c.reindex(from, to));
+    }
+
+    // `path` is whatever is left after the prefix of this node was matched
+    fn lookup<'a>(
+        &'a self,
+        path: &'a str,
+        captures: &mut Captures<'a>,
+        policy: &MatchPolicy,
+    ) -> Option<usize> {
+        if path.is_empty() && self.route.is_some() {
+            return self.route;
+        }
+
+        // literals take precedence, but unlike the trie we backtrack into the capture on a miss
+        if let Some(first) = path.chars().next() {
+            let first = if policy.case_insensitive {
+                first.to_ascii_lowercase()
+            } else {
+                first
+            };
+            let child = self
+                .children
+                .iter()
+                .find(|c| return c.prefix.starts_with(first));
+            if let Some(child) = child {
+                let n = child.prefix.len();
+                if let Some(head) = path.get(..n) {
+                    if policy.literal_matches(&child.prefix, head) {
+                        if let Some(route) = child.lookup(&path[n..], captures, policy) {
+                            return Some(route);
+                        }
+                    }
+                }
+            }
+        }
+
+        let (key, next) = self.capture.as_ref()?;
+        let end = path.find('/').unwrap_or(path.len());
+        let mark = captures.len();
+        captures.insert(key, &path[..end]);
+        let route = next.lookup(&path[end..], captures, policy);
+        if route.is_none() {
+            captures.truncate(mark);
+        }
+        return route;
+    }
+}
+
+/// Path compressed radix tree, lookups walk the request path in place and keep captures inline
+/// so matching a route does not allocate.
+pub struct RouteRadix<Context> {
+    root: Node,
+    routes: Vec<Route<Context>>,
+    policy: MatchPolicy,
+}
+
+impl<Context> Clone for RouteRadix<Context> {
+    fn clone(&self) -> Self {
+        return RouteRadix {
+            root: self.root.clone(),
+            routes: self.routes.clone(),
+            policy: self.policy,
+        };
+    }
+}
+
+impl<Context> Routeable<Context> for RouteRadix<Context> {
+    fn with_policy(policy: MatchPolicy) -> Self {
+        return RouteRadix {
+            root: Node::new(String::new()),
+            routes: Vec::new(),
+            policy,
+        };
+    }
+
+    fn policy(&self) -> MatchPolicy {
+        return self.policy;
+    }
+
+    fn insert(&mut self, route: Route<Context>) -> Result<()> {
+        // lookups are to stay off the heap, so are the captures of a match
+        let captures = route
+            .path_segments
+            .iter()
+            .filter(|segment| return matches!(segment, Segments::Capture(_)))
+            .count();
+        if captures > MAX_CAPTURES {
+            return Err(anyhow!(
+                "More than {} capture groups used: {}",
+                MAX_CAPTURES,
+                route.path
+            ));
+        }
+        let tokens = tokenize(&route.path_segments, &self.policy);
+        // a route differing only in capture names is the same route, not a clash of names
+        if let Some(existing) = self.root.find(&tokens, 0) {
+            return Err(anyhow!(
+                "Route {} duplicates already registered route {}",
+                route.path,
+                self.routes[existing].path
+            ));
+        }
+        self.root.insert(&tokens, 0, self.routes.len())?;
+        self.routes.push(route);
+        return Ok(());
+    }
+
+    fn remove_route(&mut self, path: impl Into<SharedStr>) -> Result<Route<Context>> {
+        let path = path.into();
+        let tokens = tokenize(&parse_path(&path, self.policy)?, &self.policy);
+        let Some(idx) = self.root.remove(&tokens, 0) else {
+            return Err(anyhow!("No route registered for {}", path));
+        };
+        // the last route moves into the freed slot
+        let last = self.routes.len() - 1;
+        if idx != last {
+            self.root.reindex(last, idx);
+        }
+        return Ok(self.routes.swap_remove(idx));
+    }
+
+    fn match_route<'a>(
+        &'a self,
+        path: &'a str,
+    ) -> Option<(&'a dyn Handler<Context>, Identifiers<'a>)> {
+        let path = self.policy.normalize(path);
+        let mut path_values = Captures::new();
+        let route = self.root.lookup(path, &mut path_values, &self.policy)?;
+        return Some((
+            self.routes[route].handler.as_ref(),
+            Identifiers::new(path_values),
+        ));
+    }
+
+    fn routes(&self) -> Vec<&Route<Context>> {
+        return self.routes.iter().collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::request::Request;
+    use crate::core::response::StatusCode;
+    use crate::core::route_radix::RouteRadix;
+    use crate::core::routing::Identifiers;
+    use crate::core::routing::Routeable;
+    use crate::core::routing::{MatchPolicy, TrailingSlash};
+    use anyhow::Result;
+
+    struct PlaceholderContext {}
+
+    fn thunk(_req: &Request, _paths: &Identifiers, _ctx: &PlaceholderContext) -> Result<Vec<u8>> {
+        return Ok(vec![1, 2, 3]);
+    }
+
+    const RAW_REQUEST: &[u8; 18] = b"GET / HTTP/1.1\r\n\r\n";
+
+    fn adding_new_literal(radix: &mut RouteRadix<PlaceholderContext>, path: &'static str) {
+        let test_request: Request = Request::from(RAW_REQUEST).unwrap();
+        assert!(radix.add_route(path, thunk).is_ok());
+        let out = radix.match_route(path);
+        assert!(out.is_some());
+        let (handler, ids) = out.unwrap();
+        let response = handler.call(&test_request, &ids, &PlaceholderContext {});
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.body, vec![1, 2, 3]);
+        assert!(ids.path_values.is_empty());
+        // should not be able to overwrite the handler once set
+        assert!(radix.add_route(path, thunk).is_err());
+    }
+
+    fn adding_invalid(radix: &mut RouteRadix<PlaceholderContext>, path: &'static str) {
+        assert!(radix.add_route(path, thunk).is_err());
+    }
+
+    fn adding_new_wild(radix: &mut RouteRadix<PlaceholderContext>, path: &'static str) {
+        let test_request: Request = Request::from(RAW_REQUEST).unwrap();
+        assert!(radix.add_route(path, thunk).is_ok());
+        let out = radix.match_route(path);
+        assert!(out.is_some());
+        let (handler, ids) = out.unwrap();
+        let response = handler.call(&test_request, &ids, &PlaceholderContext {});
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.body, vec![1, 2, 3]);
+        assert!(!ids.path_values.is_empty());
+        // should not be able to overwrite the handler once set
+        assert!(radix.add_route(path, thunk).is_err());
+    }
+
+    #[test]
+    fn radix_matches_literals() {
+        let mut radix = RouteRadix::<PlaceholderContext>::new();
+
+        adding_new_literal(&mut radix, "/");
+        adding_new_literal(&mut radix, "/user");
+        adding_new_literal(&mut radix, "/user/"); // checking distinction
+        adding_new_literal(&mut radix, "/users");
+        adding_new_literal(&mut radix, "/use");
+        assert!(radix.match_route("/us").is_none());
+        assert!(radix.match_route("/userss").is_none());
+    }
+
+    #[test]
+    fn radix_no_match_literals_subpath() {
+        let mut radix = RouteRadix::<PlaceholderContext>::new();
+
+        adding_new_literal(&mut radix, "/");
+        adding_new_literal(&mut radix, "/user/dashboard");
+        assert!(radix.match_route("/user").is_none());
+        assert!(radix.match_route("/user/not_dashboard").is_none());
+        assert!(radix.match_route("/user/dashboard").is_some());
+    }
+
+    #[test]
+    fn radix_no_match_wild_subpath() {
+        let mut radix = RouteRadix::<PlaceholderContext>::new();
+
+        adding_new_literal(&mut radix, "/");
+        adding_new_wild(&mut radix, "/user/{id}/dashboard");
+        assert!(radix.match_route("/user").is_none());
+        assert!(radix.match_route("/user/1234").is_none());
+        assert!(radix.match_route("/user/1234/dashboard").is_some());
+    }
+
+    #[test]
+    fn radix_matches_wilds() {
+        let mut radix = RouteRadix::<PlaceholderContext>::new();
+
+        adding_new_literal(&mut radix, "/");
+        adding_new_wild(&mut radix, "/user/{id}");
+        adding_new_wild(&mut radix, "/user/{id}/");
+
+        // should not be able to rebind the wild match
+        adding_invalid(&mut radix, "/user/{msg}");
+
+        let path = "/user/1234";
+        let Some((_handler, ids)) = radix.match_route(path) else {
+            panic!("Should be valid route");
+        };
+        assert_eq!(ids.path_values.get("id"), Some(&"1234"));
+
+        let path = "/user/1234/";
+        let Some((_handler, ids)) = radix.match_route(path) else {
+            panic!("Should be valid route");
+        };
+        assert_eq!(ids.path_values.get("id"), Some(&"1234"));
+    }
+
+    #[test]
+    fn radix_matches_literal_over_wild() {
+        let mut radix = RouteRadix::<PlaceholderContext>::new();
+
+        adding_new_literal(&mut radix, "/");
+        adding_new_literal(&mut radix, "/user/dashboard");
+        adding_new_wild(&mut radix, "/user/{id}");
+
+        let path = "/user/1234";
+        let Some((_handler, ids)) = radix.match_route(path) else {
+            panic!("Should be valid route");
+        };
+        assert_eq!(ids.path_values.get("id"), Some(&"1234"));
+
+        let path = "/user/dashboard";
+        let Some((_handler, ids)) = radix.match_route(path) else {
+            panic!("Should be valid route");
+        };
+        assert!(ids.path_values.is_empty());
+
+        // shares a prefix with the literal but has to fall back to the capture
+        let path = "/user/dash";
+        let Some((_handler, ids)) = radix.match_route(path) else {
+            panic!("Should be valid route");
+        };
+        assert_eq!(ids.path_values.get("id"), Some(&"dash"));
+    }
+
+    #[test]
+    fn radix_backtracks_out_of_literal() {
+        let mut radix = RouteRadix::<PlaceholderContext>::new();
+
+        adding_new_literal(&mut radix, "/user/dashboard");
+        adding_new_wild(&mut radix, "/user/{id}/settings");
+
+        let Some((_handler, ids)) = radix.match_route("/user/dashboard/settings") else {
+            panic!("Should be valid route");
+        };
+        assert_eq!(ids.path_values.get("id"), Some(&"dashboard"));
+    }
+
+    #[test]
+    fn radix_multiple_captures() {
+        let mut radix = RouteRadix::<PlaceholderContext>::new();
+
+        adding_new_wild(&mut radix, "/org/{org}/repo/{repo}");
+        adding_new_wild(&mut radix, "/org/{org}/members");
+        let Some((_handler, ids)) = radix.match_route("/org/rust/repo/http") else {
+            panic!("Should be valid route");
+        };
+        assert_eq!(ids.path_values.len(), 2);
+        assert_eq!(ids.path_values.get("org"), Some(&"rust"));
+        assert_eq!(ids.path_values.get("repo"), Some(&"http"));
+    }
+
+    #[test]
+    fn radix_policies() {
+        let mut radix = RouteRadix::<PlaceholderContext>::with_policy(MatchPolicy {
+            trailing_slash: TrailingSlash::Ignore,
+            case_insensitive: true,
+        });
+
+        adding_new_literal(&mut radix, "/User/Dashboard");
+        adding_invalid(&mut radix, "/user/dashboard/");
+        assert!(radix.match_route("/USER/dashBOARD/").is_some());
+
+        adding_new_wild(&mut radix, "/Files/{file}");
+        let Some((_handler, ids)) = radix.match_route("/files/README.md") else {
+            panic!("Should be valid route");
+        };
+        assert_eq!(ids.path_values.get("file"), Some(&"README.md"));
+    }
+
+    #[test]
+    fn radix_does_not_accept_empty_capture() {
+        let mut radix = RouteRadix::<PlaceholderContext>::new();
+
+        adding_invalid(&mut radix, "/user/{}");
+    }
+
+    fn thunk2(_req: &Request, _paths: &Identifiers, _ctx: &PlaceholderContext) -> Result<Vec<u8>> {
+        return Ok(vec![4, 5, 6]);
+    }
+
+    #[test]
+    fn radix_remove_route() {
+        let mut radix = RouteRadix::<PlaceholderContext>::new();
+
+        adding_new_literal(&mut radix, "/user");
+        adding_new_literal(&mut radix, "/user/dashboard");
+        adding_new_wild(&mut radix, "/user/{id}/settings");
+
+        assert!(radix.remove_route("/user/dashboard").is_ok());
+        assert!(radix.match_route("/user/dashboard").is_none());
+        assert!(radix.remove_route("/user/dashboard").is_err());
+        // capture names do not have to match the registered ones
+        assert!(radix.remove_route("/user/{other}/settings").is_ok());
+        assert!(radix.match_route("/user/1/settings").is_none());
+        assert!(radix.match_route("/user").is_some());
+        assert_eq!(radix.routes().len(), 1);
+
+        // the removed paths can be registered again
+        adding_new_literal(&mut radix, "/user/dashboard");
+        adding_new_wild(&mut radix, "/user/{name}/settings");
+    }
+
+    #[test]
+    fn radix_replace_route() {
+        let test_request: Request = Request::from(RAW_REQUEST).unwrap();
+        let mut radix = RouteRadix::<PlaceholderContext>::new();
+
+        adding_new_wild(&mut radix, "/files/{file}");
+        let previous = radix.replace_route("/files/{file}", thunk2);
+        assert_eq!(previous.unwrap().path, "/files/{file}");
+        let (handler, ids) = radix.match_route("/files/a.txt").unwrap();
+        let response = handler.call(&test_request, &ids, &PlaceholderContext {});
+        assert_eq!(response.body, vec![4, 5, 6]);
+
+        assert!(radix.replace_route("/missing", thunk2).is_err());
+        assert_eq!(radix.routes().len(), 1);
+    }
+
+    #[test]
+    fn radix_too_many_captures() {
+        let mut radix = RouteRadix::<PlaceholderContext>::new();
+        assert!(radix
+            .add_route("/{a}/{b}/{c}/{d}/{e}/{f}/{g}/{h}", thunk)
+            .is_ok());
+        let refused = radix.add_route("/x/{a}/{b}/{c}/{d}/{e}/{f}/{g}/{h}/{i}", thunk);
+        assert!(refused.is_err());
+        assert!(radix.match_route("/x/1/2/3/4/5/6/7/8/9").is_none());
+    }
+}
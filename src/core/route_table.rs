@@ -1,46 +1,140 @@
-use crate::core::routing::{Identifiers, Route, RouteHandler, Routeable};
+use crate::core::routing::{parse_path, Handler, Identifiers, MatchPolicy, Route};
+use crate::core::routing::{Routeable, Segments};
+use crate::core::shared_str::SharedStr;
 use anyhow::anyhow;
 use anyhow::Result;
 use itertools::Itertools;
-use std::collections::HashSet;
+use std::collections::HashMap;
+
+// index of the segment routes are bucketed on, the one after the leading slash
+fn key_index(segment_count: usize) -> usize {
+    return if segment_count > 1 { 1 } else { 0 };
+}
+
+// every route in a bucket has the same number of segments, each list is kept in precedence order
+struct Bucket<Context> {
+    // keyed by the folded literal at `key_index`
+    literal: HashMap<SharedStr, Vec<Route<Context>>>,
+    // routes that capture at `key_index`
+    capture: Vec<Route<Context>>,
+}
 
 pub struct RouteTable<Context> {
-    routes: HashSet<Route<Context>>,
+    buckets: HashMap<usize, Bucket<Context>>,
+    policy: MatchPolicy,
+}
+
+impl<Context> RouteTable<Context> {
+    // the precedence ordered list a route with these segments belongs in
+    fn candidates_mut(&mut self, segments: &[Segments]) -> &mut Vec<Route<Context>> {
+        let count = segments.len();
+        let bucket = self.buckets.entry(count).or_insert_with(|| {
+            return Bucket {
+                literal: HashMap::new(),
+                capture: Vec::new(),
+            };
+        });
+        match &segments[key_index(count)] {
+            Segments::Literal(s) => {
+                return bucket.literal.entry(self.policy.fold_key(s)).or_default();
+            }
+            Segments::Capture(_) => return &mut bucket.capture,
+        }
+    }
+}
+
+impl<Context> Clone for Bucket<Context> {
+    fn clone(&self) -> Self {
+        return Bucket {
+            literal: self.literal.clone(),
+            capture: self.capture.clone(),
+        };
+    }
+}
+
+impl<Context> Clone for RouteTable<Context> {
+    fn clone(&self) -> Self {
+        return RouteTable {
+            buckets: self.buckets.clone(),
+            policy: self.policy,
+        };
+    }
 }
 
 impl<Context> Routeable<Context> for RouteTable<Context> {
-    fn new() -> Self {
+    fn with_policy(policy: MatchPolicy) -> Self {
         return RouteTable {
-            routes: HashSet::new(),
+            buckets: HashMap::new(),
+            policy,
         };
     }
 
-    fn add_route(&mut self, path: &'static str, handler: RouteHandler<Context>) -> Result<()> {
-        if !self.routes.insert(Route::new(path, handler)?) {
-            return Err(anyhow!(format!("Handler for {} already set!", path)));
+    fn policy(&self) -> MatchPolicy {
+        return self.policy;
+    }
+
+    fn insert(&mut self, route: Route<Context>) -> Result<()> {
+        let candidates = self.candidates_mut(&route.path_segments);
+        if let Some(existing) = candidates.iter().find(|r| return **r == route) {
+            return Err(anyhow!(
+                "Route {} duplicates already registered route {}",
+                route.path,
+                existing.path
+            ));
         }
+        // sorted on insert so lookups can stop at the first match
+        let idx = candidates.partition_point(|r| return *r < route);
+        candidates.insert(idx, route);
         return Ok(());
     }
 
-    fn match_route<'a>(&self, path: &'a str) -> Option<(RouteHandler<Context>, Identifiers<'a>)> {
-        // need to sort routes so that we try literals first then captures
-        let sorted_routes = self.routes.iter().sorted().collect_vec();
+    fn remove_route(&mut self, path: impl Into<SharedStr>) -> Result<Route<Context>> {
+        let path = path.into();
+        let segments = parse_path(&path, self.policy)?;
+        let candidates = self.candidates_mut(&segments);
+        let Some(idx) = candidates.iter().position(|r| return r.has_path(&segments)) else {
+            return Err(anyhow!("No route registered for {}", path));
+        };
+        return Ok(candidates.remove(idx));
+    }
 
-        for route in sorted_routes {
+    fn match_route<'a>(
+        &'a self,
+        path: &'a str,
+    ) -> Option<(&'a dyn Handler<Context>, Identifiers<'a>)> {
+        let path = self.policy.normalize(path);
+        let count = path.split('/').count();
+        let bucket = self.buckets.get(&count)?;
+        let key = path.split('/').nth(key_index(count))?;
+
+        // routes with a literal at the key sort before the ones capturing it
+        let literal = bucket.literal.get(self.policy.fold(key).as_ref());
+        let candidates = literal.into_iter().flatten().chain(bucket.capture.iter());
+        for route in candidates {
             if let Some(scope) = route.matches(path) {
-                return Some((route.handler, scope));
+                return Some((route.handler.as_ref(), scope));
             }
         }
         return None;
     }
+
+    fn routes(&self) -> Vec<&Route<Context>> {
+        return self
+            .buckets
+            .values()
+            .flat_map(|b| return b.literal.values().flatten().chain(b.capture.iter()))
+            .collect_vec();
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::core::request::Request;
+    use crate::core::response::StatusCode;
     use crate::core::route_table::RouteTable;
     use crate::core::routing::Identifiers;
     use crate::core::routing::Routeable;
+    use crate::core::routing::{MatchPolicy, TrailingSlash};
     use anyhow::Result;
 
     struct PlaceholderContext {}
@@ -57,10 +151,9 @@ mod tests {
         let out = table.match_route(path);
         assert!(out.is_some());
         let (handler, ids) = out.unwrap();
-        let response = handler(&test_request, &ids, &PlaceholderContext {});
-        assert!(response.is_ok());
-        let response = response.unwrap();
-        assert_eq!(response, vec![1, 2, 3]);
+        let response = handler.call(&test_request, &ids, &PlaceholderContext {});
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.body, vec![1, 2, 3]);
         assert!(ids.path_values.is_empty());
         // should not be able to overwrite the handler once set
         assert!(table.add_route(path, thunk).is_err());
@@ -76,10 +169,9 @@ mod tests {
         let out = table.match_route(path);
         assert!(out.is_some());
         let (handler, ids) = out.unwrap();
-        let response = handler(&test_request, &ids, &PlaceholderContext {});
-        assert!(response.is_ok());
-        let response = response.unwrap();
-        assert_eq!(response, vec![1, 2, 3]);
+        let response = handler.call(&test_request, &ids, &PlaceholderContext {});
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.body, vec![1, 2, 3]);
         assert!(!ids.path_values.is_empty());
         // should not be able to overwrite the handler once set
         assert!(table.add_route(path, thunk).is_err());
@@ -194,4 +286,121 @@ mod tests {
 
         adding_invalid(&mut table, "/user/{}");
     }
+
+    #[test]
+    fn table_ignore_trailing_slash() {
+        let mut table = RouteTable::<PlaceholderContext>::with_policy(MatchPolicy {
+            trailing_slash: TrailingSlash::Ignore,
+            ..MatchPolicy::default()
+        });
+
+        adding_new_literal(&mut table, "/");
+        adding_new_literal(&mut table, "/user");
+        // same route as "/user" under this policy
+        adding_invalid(&mut table, "/user/");
+        assert!(table.match_route("/user/").is_some());
+
+        adding_new_wild(&mut table, "/user/{id}/");
+        let Some((_handler, ids)) = table.match_route("/user/1234") else {
+            panic!("Should be valid route");
+        };
+        assert_eq!(ids.path_values.get("id"), Some(&"1234"));
+    }
+
+    #[test]
+    fn table_case_insensitive() {
+        let mut table = RouteTable::<PlaceholderContext>::with_policy(MatchPolicy {
+            case_insensitive: true,
+            ..MatchPolicy::default()
+        });
+
+        adding_new_literal(&mut table, "/User/Dashboard");
+        adding_invalid(&mut table, "/user/dashboard");
+        assert!(table.match_route("/USER/dashBOARD").is_some());
+
+        adding_new_wild(&mut table, "/Files/{file}");
+        let Some((_handler, ids)) = table.match_route("/files/README.md") else {
+            panic!("Should be valid route");
+        };
+        assert_eq!(ids.path_values.get("file"), Some(&"README.md"));
+    }
+
+    #[test]
+    fn table_case_sensitive_by_default() {
+        let mut table = RouteTable::<PlaceholderContext>::new();
+
+        adding_new_literal(&mut table, "/user");
+        assert!(table.match_route("/User").is_none());
+        adding_new_literal(&mut table, "/User");
+    }
+
+    #[test]
+    fn table_precedence_across_buckets() {
+        let mut table = RouteTable::<PlaceholderContext>::new();
+
+        adding_new_wild(&mut table, "/{a}/x");
+        adding_new_wild(&mut table, "/x/{b}");
+        adding_new_wild(&mut table, "/{a}");
+
+        // the literal in the first segment wins
+        let Some((_handler, ids)) = table.match_route("/x/x") else {
+            panic!("Should be valid route");
+        };
+        assert_eq!(ids.path_values.get("b"), Some(&"x"));
+
+        let Some((_handler, ids)) = table.match_route("/y/x") else {
+            panic!("Should be valid route");
+        };
+        assert_eq!(ids.path_values.get("a"), Some(&"y"));
+
+        // falls back to the capture bucket when the literal bucket has no match
+        let Some((_handler, ids)) = table.match_route("/x") else {
+            panic!("Should be valid route");
+        };
+        assert_eq!(ids.path_values.get("a"), Some(&"x"));
+        assert!(table.match_route("/y/y").is_none());
+        assert_eq!(table.routes().len(), 3);
+    }
+
+    fn thunk2(_req: &Request, _paths: &Identifiers, _ctx: &PlaceholderContext) -> Result<Vec<u8>> {
+        return Ok(vec![4, 5, 6]);
+    }
+
+    #[test]
+    fn table_remove_route() {
+        let mut table = RouteTable::<PlaceholderContext>::new();
+
+        adding_new_literal(&mut table, "/user");
+        adding_new_literal(&mut table, "/user/dashboard");
+        adding_new_wild(&mut table, "/user/{id}/settings");
+
+        assert!(table.remove_route("/user/dashboard").is_ok());
+        assert!(table.match_route("/user/dashboard").is_none());
+        assert!(table.remove_route("/user/dashboard").is_err());
+        // capture names do not have to match the registered ones
+        assert!(table.remove_route("/user/{other}/settings").is_ok());
+        assert!(table.match_route("/user/1/settings").is_none());
+        assert!(table.match_route("/user").is_some());
+        assert_eq!(table.routes().len(), 1);
+
+        // the removed paths can be registered again
+        adding_new_literal(&mut table, "/user/dashboard");
+        adding_new_wild(&mut table, "/user/{name}/settings");
+    }
+
+    #[test]
+    fn table_replace_route() {
+        let test_request: Request = Request::from(RAW_REQUEST).unwrap();
+        let mut table = RouteTable::<PlaceholderContext>::new();
+
+        adding_new_wild(&mut table, "/files/{file}");
+        let previous = table.replace_route("/files/{file}", thunk2);
+        assert_eq!(previous.unwrap().path, "/files/{file}");
+        let (handler, ids) = table.match_route("/files/a.txt").unwrap();
+        let response = handler.call(&test_request, &ids, &PlaceholderContext {});
+        assert_eq!(response.body, vec![4, 5, 6]);
+
+        assert!(table.replace_route("/missing", thunk2).is_err());
+        assert_eq!(table.routes().len(), 1);
+    }
 }
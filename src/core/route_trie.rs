@@ -1,46 +1,52 @@
-use crate::core::routing::Route;
-use crate::core::routing::{Identifiers, RouteHandler, Routeable, Segments};
+use crate::core::routing::{parse_path, Route};
+use crate::core::routing::{Captures, Handler, Identifiers, MatchPolicy, Routeable, Segments};
+use crate::core::shared_str::SharedStr;
 use anyhow::anyhow;
 use anyhow::Result;
 use itertools::Itertools;
 use std::collections::HashMap;
 
 pub struct RouteTrie<Context> {
-    literal_routes: HashMap<&'static str, Box<RouteTrie<Context>>>,
-    wild_route: Option<(&'static str, Box<RouteTrie<Context>>)>,
-    pub handler: Option<RouteHandler<Context>>,
+    // keys are folded by the policy so case-insensitive lookups stay a single hash
+    literal_routes: HashMap<SharedStr, Box<RouteTrie<Context>>>,
+    wild_route: Option<(SharedStr, Box<RouteTrie<Context>>)>,
+    pub route: Option<Route<Context>>,
+    policy: MatchPolicy,
 }
 
 impl<Context> RouteTrie<Context> {
-    fn add_segments(
-        &mut self,
-        segments: &[Segments],
-        handler: RouteHandler<Context>,
-    ) -> Result<()> {
-        if segments.is_empty() {
-            if self.handler.is_some() {
-                return Err(anyhow!("This path already has a handler!"));
+    // depth is the index of the segment of the route that this node consumes
+    fn add_segments(&mut self, route: Route<Context>, depth: usize) -> Result<()> {
+        if depth == route.path_segments.len() {
+            if let Some(existing) = &self.route {
+                return Err(anyhow!(
+                    "Route {} duplicates already registered route {}",
+                    route.path,
+                    existing.path
+                ));
             }
-            self.handler = Some(handler);
+            self.route = Some(route);
             return Ok(());
         }
 
-        let curr = &segments[0];
-
-        let next: &mut RouteTrie<Context> = match curr {
+        let next: &mut RouteTrie<Context> = match &route.path_segments[depth] {
             Segments::Literal(s) => self
                 .literal_routes
-                .entry(s)
-                .or_insert(Box::new(RouteTrie::new())),
+                .entry(self.policy.fold_key(s))
+                .or_insert(Box::new(RouteTrie::with_policy(self.policy))),
             Segments::Capture(s) => {
-                if let Some((set, _)) = self.wild_route {
-                    if &set != s {
+                if let Some((set, _)) = &self.wild_route {
+                    if set != s {
                         return Err(anyhow!(
-                            "Add route has capture group with key different than expected"
+                            "Route {} captures {{{}}} where another route already captures {{{}}}",
+                            route.path,
+                            s,
+                            set
                         ));
                     }
                 } else {
-                    self.wild_route = Some((s, Box::new(RouteTrie::new())));
+                    self.wild_route =
+                        Some((s.clone(), Box::new(RouteTrie::with_policy(self.policy))));
                 }
 
                 let Some((_, next)) = self.wild_route.as_mut() else {
@@ -49,23 +55,82 @@ impl<Context> RouteTrie<Context> {
                 next
             }
         };
-        next.add_segments(&segments[1..], handler)?;
+        next.add_segments(route, depth + 1)?;
 
         return Ok(());
     }
 
+    // the route registered for exactly these segments, capture names are not compared
+    fn find_segments(&self, segments: &[Segments]) -> Option<&Route<Context>> {
+        let Some(curr) = segments.first() else {
+            return self.route.as_ref();
+        };
+        match curr {
+            Segments::Literal(s) => {
+                let next = self.literal_routes.get(self.policy.fold(s).as_ref())?;
+                return next.find_segments(&segments[1..]);
+            }
+            Segments::Capture(_) => {
+                let (_, next) = self.wild_route.as_ref()?;
+                return next.find_segments(&segments[1..]);
+            }
+        }
+    }
+
+    fn remove_segments(&mut self, segments: &[Segments]) -> Option<Route<Context>> {
+        let Some(curr) = segments.first() else {
+            return self.route.take();
+        };
+
+        match curr {
+            Segments::Literal(s) => {
+                let key = self.policy.fold(s);
+                let next = self.literal_routes.get_mut(key.as_ref())?;
+                let removed = next.remove_segments(&segments[1..]);
+                if next.is_empty() {
+                    self.literal_routes.remove(key.as_ref());
+                }
+                return removed;
+            }
+            Segments::Capture(_) => {
+                let (_, next) = self.wild_route.as_mut()?;
+                let removed = next.remove_segments(&segments[1..]);
+                if next.is_empty() {
+                    self.wild_route = None;
+                }
+                return removed;
+            }
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        return self.route.is_none() && self.literal_routes.is_empty() && self.wild_route.is_none();
+    }
+
+    fn collect_routes<'t>(&'t self, routes: &mut Vec<&'t Route<Context>>) {
+        if let Some(route) = &self.route {
+            routes.push(route);
+        }
+        for next in self.literal_routes.values() {
+            next.collect_routes(routes);
+        }
+        if let Some((_, next)) = &self.wild_route {
+            next.collect_routes(routes);
+        }
+    }
+
     fn match_internal<'a>(
-        &self,
+        &'a self,
         path: &[&'a str],
-        mut path_values: HashMap<&'static str, &'a str>,
-    ) -> Option<(RouteHandler<Context>, Identifiers<'a>)> {
+        mut path_values: Captures<'a>,
+    ) -> Option<(&'a dyn Handler<Context>, Identifiers<'a>)> {
         if path.is_empty() {
-            let handler = self.handler?;
-            return Some((handler, Identifiers { path_values }));
+            let route = self.route.as_ref()?;
+            return Some((route.handler.as_ref(), Identifiers::new(path_values)));
         }
 
         // check literals first
-        if let Some(next) = self.literal_routes.get(path[0]) {
+        if let Some(next) = self.literal_routes.get(self.policy.fold(path[0]).as_ref()) {
             return next.match_internal(&path[1..], path_values);
         }
 
@@ -79,33 +144,76 @@ impl<Context> RouteTrie<Context> {
     }
 }
 
+impl<Context> Clone for RouteTrie<Context> {
+    fn clone(&self) -> Self {
+        return RouteTrie {
+            literal_routes: self.literal_routes.clone(),
+            wild_route: self.wild_route.clone(),
+            route: self.route.clone(),
+            policy: self.policy,
+        };
+    }
+}
+
 impl<Context> Routeable<Context> for RouteTrie<Context> {
-    fn new() -> Self {
+    fn with_policy(policy: MatchPolicy) -> Self {
         return RouteTrie {
             literal_routes: HashMap::new(),
             wild_route: None,
-            handler: None,
+            route: None,
+            policy,
         };
     }
 
-    fn add_route(&mut self, path: &'static str, handler: RouteHandler<Context>) -> Result<()> {
-        let route = Route::new(path, handler)?;
-        self.add_segments(&route.path_segments, handler)?;
-        return Ok(());
+    fn policy(&self) -> MatchPolicy {
+        return self.policy;
     }
 
-    fn match_route<'a>(&self, path: &'a str) -> Option<(RouteHandler<Context>, Identifiers<'a>)> {
-        let path = path.split('/').collect_vec();
-        return self.match_internal(&path, HashMap::new());
+    fn insert(&mut self, route: Route<Context>) -> Result<()> {
+        // looked up first, a route differing only in capture names is the same route and not a
+        // clash of capture names
+        if let Some(existing) = self.find_segments(&route.path_segments) {
+            return Err(anyhow!(
+                "Route {} duplicates already registered route {}",
+                route.path,
+                existing.path
+            ));
+        }
+        return self.add_segments(route, 0);
+    }
+
+    fn remove_route(&mut self, path: impl Into<SharedStr>) -> Result<Route<Context>> {
+        let path = path.into();
+        let segments = parse_path(&path, self.policy)?;
+        let Some(route) = self.remove_segments(&segments) else {
+            return Err(anyhow!("No route registered for {}", path));
+        };
+        return Ok(route);
+    }
+
+    fn match_route<'a>(
+        &'a self,
+        path: &'a str,
+    ) -> Option<(&'a dyn Handler<Context>, Identifiers<'a>)> {
+        let path = self.policy.normalize(path).split('/').collect_vec();
+        return self.match_internal(&path, Captures::new());
+    }
+
+    fn routes(&self) -> Vec<&Route<Context>> {
+        let mut routes = Vec::new();
+        self.collect_routes(&mut routes);
+        return routes;
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::core::request::Request;
+    use crate::core::response::StatusCode;
     use crate::core::route_trie::RouteTrie;
     use crate::core::routing::Identifiers;
     use crate::core::routing::Routeable;
+    use crate::core::routing::{MatchPolicy, TrailingSlash};
     use anyhow::Result;
 
     struct PlaceholderContext {}
@@ -122,10 +230,9 @@ mod tests {
         let out = trie.match_route(path);
         assert!(out.is_some());
         let (handler, ids) = out.unwrap();
-        let response = handler(&test_request, &ids, &PlaceholderContext {});
-        assert!(response.is_ok());
-        let response = response.unwrap();
-        assert_eq!(response, vec![1, 2, 3]);
+        let response = handler.call(&test_request, &ids, &PlaceholderContext {});
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.body, vec![1, 2, 3]);
         assert!(ids.path_values.is_empty());
         // should not be able to overwrite the handler once set
         assert!(trie.add_route(path, thunk).is_err());
@@ -141,10 +248,9 @@ mod tests {
         let out = trie.match_route(path);
         assert!(out.is_some());
         let (handler, ids) = out.unwrap();
-        let response = handler(&test_request, &ids, &PlaceholderContext {});
-        assert!(response.is_ok());
-        let response = response.unwrap();
-        assert_eq!(response, vec![1, 2, 3]);
+        let response = handler.call(&test_request, &ids, &PlaceholderContext {});
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.body, vec![1, 2, 3]);
         assert!(!ids.path_values.is_empty());
         // should not be able to overwrite the handler once set
         assert!(trie.add_route(path, thunk).is_err());
@@ -259,4 +365,130 @@ mod tests {
 
         adding_invalid(&mut trie, "/user/{}");
     }
+
+    #[test]
+    fn trie_ignore_trailing_slash() {
+        let mut trie = RouteTrie::<PlaceholderContext>::with_policy(MatchPolicy {
+            trailing_slash: TrailingSlash::Ignore,
+            ..MatchPolicy::default()
+        });
+
+        adding_new_literal(&mut trie, "/");
+        adding_new_literal(&mut trie, "/user");
+        // same route as "/user" under this policy
+        adding_invalid(&mut trie, "/user/");
+        assert!(trie.match_route("/user/").is_some());
+
+        adding_new_wild(&mut trie, "/user/{id}/");
+        let Some((_handler, ids)) = trie.match_route("/user/1234") else {
+            panic!("Should be valid route");
+        };
+        assert_eq!(ids.path_values.get("id"), Some(&"1234"));
+    }
+
+    #[test]
+    fn trie_case_insensitive() {
+        let mut trie = RouteTrie::<PlaceholderContext>::with_policy(MatchPolicy {
+            case_insensitive: true,
+            ..MatchPolicy::default()
+        });
+
+        adding_new_literal(&mut trie, "/User/Dashboard");
+        adding_invalid(&mut trie, "/user/dashboard");
+        assert!(trie.match_route("/USER/dashBOARD").is_some());
+
+        adding_new_wild(&mut trie, "/Files/{file}");
+        let Some((_handler, ids)) = trie.match_route("/files/README.md") else {
+            panic!("Should be valid route");
+        };
+        assert_eq!(ids.path_values.get("file"), Some(&"README.md"));
+    }
+
+    #[test]
+    fn trie_case_sensitive_by_default() {
+        let mut trie = RouteTrie::<PlaceholderContext>::new();
+
+        adding_new_literal(&mut trie, "/user");
+        assert!(trie.match_route("/User").is_none());
+        adding_new_literal(&mut trie, "/User");
+    }
+
+    fn thunk2(_req: &Request, _paths: &Identifiers, _ctx: &PlaceholderContext) -> Result<Vec<u8>> {
+        return Ok(vec![4, 5, 6]);
+    }
+
+    #[test]
+    fn trie_remove_route() {
+        let mut trie = RouteTrie::<PlaceholderContext>::new();
+
+        adding_new_literal(&mut trie, "/user");
+        adding_new_literal(&mut trie, "/user/dashboard");
+        adding_new_wild(&mut trie, "/user/{id}/settings");
+
+        assert!(trie.remove_route("/user/dashboard").is_ok());
+        assert!(trie.match_route("/user/dashboard").is_none());
+        assert!(trie.remove_route("/user/dashboard").is_err());
+        // capture names do not have to match the registered ones
+        assert!(trie.remove_route("/user/{other}/settings").is_ok());
+        assert!(trie.match_route("/user/1/settings").is_none());
+        assert!(trie.match_route("/user").is_some());
+        assert_eq!(trie.routes().len(), 1);
+
+        // the removed paths can be registered again
+        adding_new_literal(&mut trie, "/user/dashboard");
+        adding_new_wild(&mut trie, "/user/{name}/settings");
+    }
+
+    #[test]
+    fn trie_replace_route() {
+        let test_request: Request = Request::from(RAW_REQUEST).unwrap();
+        let mut trie = RouteTrie::<PlaceholderContext>::new();
+
+        adding_new_wild(&mut trie, "/files/{file}");
+        let previous = trie.replace_route("/files/{file}", thunk2);
+        assert_eq!(previous.unwrap().path, "/files/{file}");
+        let (handler, ids) = trie.match_route("/files/a.txt").unwrap();
+        let response = handler.call(&test_request, &ids, &PlaceholderContext {});
+        assert_eq!(response.body, vec![4, 5, 6]);
+
+        assert!(trie.replace_route("/missing", thunk2).is_err());
+        assert_eq!(trie.routes().len(), 1);
+
+        // the new capture name clashes with the one `/files/{file}/meta` keeps in place
+        adding_new_wild(&mut trie, "/files/{file}/meta");
+        assert!(trie.replace_route("/files/{name}", thunk).is_err());
+        let (handler, ids) = trie.match_route("/files/a.txt").unwrap();
+        assert_eq!(ids.path_values.get("file"), Some(&"a.txt"));
+        let response = handler.call(&test_request, &ids, &PlaceholderContext {});
+        assert_eq!(response.body, vec![4, 5, 6]);
+        assert_eq!(trie.routes().len(), 2);
+    }
+
+    #[test]
+    fn trie_runtime_paths() {
+        let mut trie = RouteTrie::<PlaceholderContext>::new();
+        for tenant in ["acme", "globex"] {
+            let path = format!("/{}/files/{{file}}", tenant);
+            assert!(trie.add_route(path, thunk).is_ok());
+        }
+
+        let (_handler, ids) = trie.match_route("/globex/files/a.txt").unwrap();
+        assert_eq!(ids.path_values.get("file").unwrap(), &"a.txt");
+        assert!(trie.match_route("/initech/files/a.txt").is_none());
+        assert!(trie
+            .remove_route(String::from("/acme/files/{file}"))
+            .is_ok());
+        assert!(trie.match_route("/acme/files/a.txt").is_none());
+    }
+
+    #[test]
+    fn trie_many_captures() {
+        let mut trie = RouteTrie::<PlaceholderContext>::new();
+        let path = "/{a}/{b}/{c}/{d}/{e}/{f}/{g}/{h}/{i}/{j}";
+        assert!(trie.add_route(path, thunk).is_ok());
+        let (_handler, ids) = trie.match_route("/1/2/3/4/5/6/7/8/9/10").unwrap();
+        assert_eq!(ids.path_values.len(), 10);
+        assert_eq!(ids.path_values.get("a"), Some(&"1"));
+        assert_eq!(ids.path_values.get("j"), Some(&"10"));
+    }
 }
@@ -1,61 +1,342 @@
-use crate::core::request::Request;
+use crate::core::diagnostics;
+use crate::core::diagnostics::RouteReport;
+use crate::core::extensions::Extensions;
+use crate::core::introspect;
+use crate::core::introspect::RouteInfo;
+use crate::core::live_routes::LiveRoutes;
+use crate::core::middleware::{Layer, Middleware, Next};
+use crate::core::openapi::OpenApi;
+use crate::core::request::{self, HttpMethod, Request};
 use crate::core::response;
-use crate::core::routing::RouteHandler;
+use crate::core::response::{Response, StatusCode};
+use crate::core::route_group::RouteGroup;
+use crate::core::route_names::RouteNames;
+use crate::core::routing::split_after_segments;
+use crate::core::routing::Handler;
+use crate::core::routing::Route;
 use crate::core::routing::Routeable;
+use crate::core::routing::{MatchPolicy, TrailingSlash};
+use crate::core::shared_str::SharedStr;
+use anyhow::anyhow;
 use anyhow::Result;
-use std::io::{Read, Write};
+use itertools::Itertools;
+use std::io::{self, Read, Write};
 use std::net::{TcpListener, TcpStream};
 use std::sync::Arc;
 use std::thread;
 
 pub struct Router<Context, T: Routeable<Context> + Sync + Send> {
     pub listener: TcpListener,
-    routes: T,
+    routes: Arc<LiveRoutes<T>>,
     context: Context,
+    policy: MatchPolicy,
+    // refuse to run while the route set has conflicts
+    strict: bool,
+    // print the routes and their conflicts when `run` starts
+    report: bool,
+    // run around every matched route, outside the layers of groups and routes
+    layers: Vec<Layer<Context>>,
+    // routes for mount prefixes, tried in order when nothing else matched, longest prefix first
+    fallbacks: Vec<Route<Context>>,
+    names: Arc<RouteNames>,
+    // values from `manage`, every request starts out with them in its extensions
+    state: Extensions,
 }
 
 impl<Context, T: Routeable<Context> + Sync + Send> Router<Context, T> {
     pub fn new(addr: &str, context: Context) -> Result<Router<Context, T>> {
+        return Router::with_policy(addr, context, MatchPolicy::default());
+    }
+
+    pub fn with_policy(
+        addr: &str,
+        context: Context,
+        policy: MatchPolicy,
+    ) -> Result<Router<Context, T>> {
         let listener = TcpListener::bind(addr)?;
         return Ok(Router {
             listener,
-            routes: T::new(),
+            routes: Arc::new(LiveRoutes::new(T::with_policy(policy))),
             context,
+            policy,
+            strict: false,
+            report: false,
+            layers: Vec::new(),
+            fallbacks: Vec::new(),
+            names: Arc::new(RouteNames::new()),
+            state: Extensions::new(),
         });
     }
 
-    pub fn handle(&mut self, path: &'static str, handler: RouteHandler<Context>) -> Result<()> {
-        return self.routes.add_route(path, handler);
+    pub fn strict(&mut self, enabled: bool) {
+        self.strict = enabled;
     }
 
-    pub fn handle_connection(&self, mut stream: TcpStream) {
-        const MAX_HTTP_REQUEST_SIZE: usize = 8192;
-        let mut read_buffer: [u8; MAX_HTTP_REQUEST_SIZE] = [0; MAX_HTTP_REQUEST_SIZE];
-        if let Err(_e) = stream.read(&mut read_buffer) {
-            println!("Error reading from the connection: {}", _e);
-            return;
+    /// Prints the route report of `validate` when `run` starts.
+    pub fn print_report(&mut self, enabled: bool) {
+        self.report = enabled;
+    }
+
+    pub fn validate(&self) -> RouteReport {
+        return diagnostics::validate(self.routes.snapshot().as_ref());
+    }
+
+    /// Every route served right now with what it documents about itself, ordered by path.
+    pub fn routes_info(&self) -> Vec<RouteInfo> {
+        return introspect::routes_info(self.routes.snapshot().as_ref());
+    }
+
+    /// Serves an OpenAPI 3.1 document of the routes at `path`, it follows live route changes.
+    pub fn openapi(&mut self, path: impl Into<SharedStr>, title: &str, version: &str) -> Result<()>
+    where
+        T: Clone + 'static,
+        Context: 'static,
+    {
+        let document = OpenApi::new(title, version, self.live_routes());
+        let document = introspect::describe(document)
+            .summary("OpenAPI document of this server")
+            .method(HttpMethod::Get)
+            .response_content_type("application/json");
+        return self.handle(path, document);
+    }
+
+    /// Registers a state object for handlers and layers, one per type.
+    ///
+    /// Handlers take it with the `Managed<T>` extractor, layers with `Identifiers::managed`.
+    /// Every request gets the same instance: the router never locks or copies it, so it is shared
+    /// by all serving threads at once and changes go through the interior mutability of `T`, such
+    /// as atomics, a `Mutex` or an `RwLock`. State can only be added before `run`, which takes the
+    /// router, so the set of state objects is fixed while serving. A value a layer puts in the
+    /// extensions under the same type hides the managed one for the handlers further in.
+    pub fn manage<S: Send + Sync + 'static>(&mut self, state: S) -> Result<()> {
+        if self.state.contains::<S>() {
+            return Err(anyhow!(
+                "State of type {} is already managed",
+                std::any::type_name::<S>()
+            ));
+        }
+        self.state.insert(state);
+        return Ok(());
+    }
+
+    /// The state registered for `S` with `manage`.
+    pub fn state<S: Send + Sync + 'static>(&self) -> Option<Arc<S>> {
+        return self.state.get_shared();
+    }
+
+    /// Adds a layer around every route, layers run in the order they were added.
+    pub fn layer(&mut self, middleware: impl Middleware<Context> + 'static) {
+        self.layers.push(Arc::new(middleware));
+    }
+
+    /// Handle to the routes that stays usable after `run`, for adding or removing routes live.
+    pub fn live_routes(&self) -> Arc<LiveRoutes<T>> {
+        return self.routes.clone();
+    }
+
+    pub fn handle(
+        &mut self,
+        path: impl Into<SharedStr>,
+        handler: impl Handler<Context> + 'static,
+    ) -> Result<()>
+    where
+        T: Clone,
+    {
+        let route = Route::with_policy(path, handler, self.policy)?;
+        // duplicates are refused by the routes themselves
+        let add = |routes: &mut T| return routes.insert(route);
+        // no copy needed while nothing else has seen the routes yet
+        if let Some(live) = Arc::get_mut(&mut self.routes) {
+            if let Some(routes) = live.get_mut() {
+                return add(routes);
+            }
+        }
+        return self.routes.update(add);
+    }
+
+    /// Registers a route that can be linked to with `url_for` under `name`.
+    pub fn handle_named(
+        &mut self,
+        name: impl Into<SharedStr>,
+        path: impl Into<SharedStr>,
+        handler: impl Handler<Context> + 'static,
+    ) -> Result<()>
+    where
+        T: Clone,
+    {
+        let name = name.into();
+        let path = path.into();
+        // checked before the route goes in so a taken name leaves the router unchanged
+        if self.names.contains(&name) {
+            return Err(anyhow!("Route name {} is already taken", name));
+        }
+        self.handle(path.clone(), handler)?;
+        return self.names.insert(name, &path);
+    }
+
+    /// Path of the route registered as `name`, see `RouteNames::url_for`.
+    pub fn url_for<K, V>(
+        &self,
+        name: &str,
+        params: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<String>
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        return self.names.url_for(name, params);
+    }
+
+    pub fn route_names(&self) -> Arc<RouteNames> {
+        return self.names.clone();
+    }
+
+    /// Records named routes in `names` from now on, usually the one the context holds so handlers
+    /// can build urls. Has to be called before any named route is registered.
+    pub fn share_route_names(&mut self, names: Arc<RouteNames>) -> Result<()> {
+        if !self.names.is_empty() {
+            return Err(anyhow!(
+                "Named routes were registered before sharing the names"
+            ));
         }
+        self.names = names;
+        return Ok(());
+    }
+
+    /// Registers every route of the group, or none of them if one cannot be added.
+    pub fn group(&mut self, group: RouteGroup<Context>) -> Result<()>
+    where
+        T: Clone,
+        Context: 'static,
+    {
+        return self.mount("", group);
+    }
+
+    /// Registers every route of the group below `prefix`, such as `/api/v1`.
+    pub fn mount(&mut self, prefix: &str, group: RouteGroup<Context>) -> Result<()>
+    where
+        T: Clone,
+        Context: 'static,
+    {
+        let mounted = group.mount(prefix)?;
+        // names are checked before anything is registered, as in `handle_named`
+        let names = mounted.names.iter().map(|(name, _)| return name);
+        if let Some(name) = names.clone().duplicates().next() {
+            return Err(anyhow!("Route name {} is used twice in the group", name));
+        }
+        if let Some(name) = names.clone().find(|name| return self.names.contains(name)) {
+            return Err(anyhow!("Route name {} is already taken", name));
+        }
+        let routes = mounted
+            .routes
+            .into_iter()
+            .map(|(path, handler)| return Route::with_policy(path, handler, self.policy))
+            .collect::<Result<Vec<_>>>()?;
+        // one update for the whole group, a conflict halfway through publishes none of it
+        self.routes.update(|current| {
+            for route in routes {
+                current.insert(route)?;
+            }
+            return Ok(());
+        })?;
+        for (name, path) in mounted.names {
+            self.names.insert(name, &path)?;
+        }
+        for (prefix, handler) in mounted.fallbacks {
+            self.add_fallback(Route::with_policy(prefix, handler, self.policy)?);
+        }
+        return Ok(());
+    }
+
+    /// Answers the requests no route or mounted group matched.
+    pub fn fallback(&mut self, handler: impl Handler<Context> + 'static) -> Result<()> {
+        self.add_fallback(Route::with_policy("", handler, self.policy)?);
+        return Ok(());
+    }
+
+    fn add_fallback(&mut self, route: Route<Context>) {
+        let n = route.path_segments.len();
+        let idx = self
+            .fallbacks
+            .partition_point(|r| return r.path_segments.len() >= n);
+        self.fallbacks.insert(idx, route);
+    }
+
+    pub fn handle_connection(&self, mut stream: TcpStream) {
+        let read_buffer = match read_message(&mut stream) {
+            Ok(Some(read_buffer)) => read_buffer,
+            Ok(None) => {
+                let response = Response::new(StatusCode::PAYLOAD_TOO_LARGE);
+                if let Err(_e) = stream.write_all(&response.into_bytes()) {
+                    println!("Error writing to the connection: {}", _e);
+                }
+                return;
+            }
+            Err(_e) => {
+                println!("Error reading from the connection: {}", _e);
+                return;
+            }
+        };
         // let response = parse_request(&read_buffer, context);
         let request = Request::from(&read_buffer);
 
-        let response = match request {
-            Err(_e) => response::NOT_FOUND.to_vec(),
-            Ok(req) => self.handle_request(&req),
+        let bytes = match request {
+            Err(_e) => Response::new(StatusCode::NOT_FOUND).into_bytes(),
+            // handlers answer `HEAD` like `GET`, the body is only left out here
+            Ok(req) if req.method == HttpMethod::Head => {
+                self.handle_request(&req).into_head_bytes()
+            }
+            Ok(req) => self.handle_request(&req).into_bytes(),
         };
-        if let Err(_e) = stream.write(&response) {
+        if let Err(_e) = stream.write_all(&bytes) {
             println!("Error writing to the connection: {}", _e);
             return;
         }
     }
 
-    pub fn handle_request(&self, request: &Request) -> Vec<u8> {
-        let Some((handler, scope)) = self.routes.match_route(request.path) else {
-            return response::NOT_FOUND.to_vec();
+    pub fn handle_request(&self, request: &Request) -> Response {
+        // the whole request is served from one snapshot even if the routes change meanwhile
+        let routes = self.routes.snapshot();
+        let Some((handler, mut scope)) = routes.match_route(request.path) else {
+            if let Some(location) = self.redirect_target(routes.as_ref(), request.path) {
+                // the query was split off the path, it belongs to the new location as well
+                let location = match request.query {
+                    Some(query) => format!("{}?{}", location, query),
+                    None => location,
+                };
+                return response::permanent_redirect(&location);
+            }
+            return self.handle_fallback(request);
         };
-        if let Ok(buf) = handler(request, &scope, &self.context) {
-            return buf;
+        scope.state = Some(&self.state);
+        return Next::new(&self.layers, handler).run(request, &scope, &self.context);
+    }
+
+    fn handle_fallback(&self, request: &Request) -> Response {
+        for route in &self.fallbacks {
+            let (prefix, _) = split_after_segments(request.path, route.path_segments.len());
+            let Some(mut scope) = route.matches(prefix) else {
+                continue;
+            };
+            scope.state = Some(&self.state);
+            let next = Next::new(&self.layers, route.handler.as_ref());
+            return next.run(request, &scope, &self.context);
         }
-        return response::NOT_FOUND.to_vec();
+        return Response::new(StatusCode::NOT_FOUND);
+    }
+
+    // the other trailing slash form of an unmatched path, if that one is registered
+    fn redirect_target(&self, routes: &T, path: &str) -> Option<String> {
+        if self.policy.trailing_slash != TrailingSlash::Redirect {
+            return None;
+        }
+        let alternate = match path.strip_suffix('/') {
+            Some(stripped) if !stripped.is_empty() => stripped.to_string(),
+            Some(_) => return None,
+            None => format!("{}/", path),
+        };
+        routes.match_route(&alternate)?;
+        return Some(alternate);
     }
 }
 
@@ -63,7 +344,19 @@ impl<Context, T: Routeable<Context> + Sync + Send> Router<Context, T> {
 impl<Context: Sync + Send + 'static, T: Routeable<Context> + Sync + Send + 'static>
     Router<Context, T>
 {
-    pub fn run(self) {
+    pub fn run(self) -> Result<()> {
+        let report = self.validate();
+        if self.report {
+            println!("{}", report);
+        }
+        if self.strict && !report.is_clean() {
+            return Err(anyhow!(
+                "Refusing to run with {} route conflict(s)\n{}",
+                report.conflicts.len(),
+                report
+            ));
+        }
+
         let app = Arc::new(self);
         for stream in app.listener.incoming() {
             match stream {
@@ -78,16 +371,71 @@ impl<Context: Sync + Send + 'static, T: Routeable<Context> + Sync + Send + 'stat
                 }
             }
         }
+        return Ok(());
+    }
+}
+
+// the request line and headers have to fit in this much, bodies in `MAX_REQUEST_SIZE`
+const MAX_HEAD_SIZE: usize = 8192;
+const MAX_REQUEST_SIZE: usize = 16 * 1024 * 1024;
+
+// the whole request as announced by its `Content-Length`, `None` when it is too large to take
+fn read_message(stream: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0; MAX_HEAD_SIZE];
+    loop {
+        match request::message_len(&buffer) {
+            Some(len) if len > MAX_REQUEST_SIZE => return Ok(None),
+            Some(len) if buffer.len() >= len => return Ok(Some(buffer)),
+            None if buffer.len() > MAX_HEAD_SIZE => return Ok(None),
+            _ => {}
+        }
+        let n = stream.read(&mut chunk)?;
+        // the client stopped sending, parsing tells whether what came is enough
+        if n == 0 {
+            return Ok(Some(buffer));
+        }
+        buffer.extend_from_slice(&chunk[..n]);
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::core::diagnostics::ConflictKind;
+    use crate::core::extract::{extract, Extension, Managed};
+    use crate::core::introspect::describe;
+    use crate::core::middleware::{Layered, Next};
+    use crate::core::request::Request;
+    use crate::core::response::{self, IntoResponse, Response, StatusCode};
+    use crate::core::route_group::RouteGroup;
+    use crate::core::route_names::RouteNames;
+    use crate::core::route_radix::RouteRadix;
     use crate::core::route_table::RouteTable;
-    use crate::core::router::Router;
+    use crate::core::route_trie::RouteTrie;
+    use crate::core::router::{read_message, Router, MAX_HEAD_SIZE};
+    use crate::core::routing::Routeable;
+    use crate::core::routing::{parse_path, Handler, Identifiers, Route};
+    use crate::core::routing::{MatchPolicy, TrailingSlash};
+    use crate::core::shared_str::SharedStr;
+    use anyhow::{anyhow, Result};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
 
     struct PlaceholderContext {}
 
+    fn thunk(_req: &Request, _paths: &Identifiers, _ctx: &PlaceholderContext) -> StatusCode {
+        return StatusCode::OK;
+    }
+
+    fn get(
+        app: &Router<PlaceholderContext, RouteTrie<PlaceholderContext>>,
+        path: &str,
+    ) -> Response {
+        let raw = format!("GET {} HTTP/1.1\r\n\r\n", path);
+        let request = Request::from(raw.as_bytes()).unwrap();
+        return app.handle_request(&request);
+    }
+
     #[test]
     fn router_new_ok() {
         let app = Router::<_, RouteTable<_>>::new("127.0.0.1:4221", &PlaceholderContext {});
@@ -99,4 +447,465 @@ mod tests {
         let app = Router::<_, RouteTable<_>>::new("not valid ip", &PlaceholderContext {});
         assert!(app.is_err());
     }
+
+    #[test]
+    fn router_strict_trailing_slash() {
+        let mut app = Router::<_, RouteTrie<_>>::new("127.0.0.1:0", PlaceholderContext {}).unwrap();
+        app.handle("/user", thunk).unwrap();
+        assert_eq!(get(&app, "/user").status, StatusCode::OK);
+        assert_eq!(get(&app, "/user/").status, StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn router_redirects_trailing_slash() {
+        let policy = MatchPolicy {
+            trailing_slash: TrailingSlash::Redirect,
+            ..MatchPolicy::default()
+        };
+        let mut app =
+            Router::<_, RouteTrie<_>>::with_policy("127.0.0.1:0", PlaceholderContext {}, policy)
+                .unwrap();
+        app.handle("/", thunk).unwrap();
+        app.handle("/user", thunk).unwrap();
+        app.handle("/docs/", thunk).unwrap();
+
+        assert_eq!(get(&app, "/user").status, StatusCode::OK);
+        assert_eq!(get(&app, "/user/"), response::permanent_redirect("/user"));
+        assert_eq!(get(&app, "/docs"), response::permanent_redirect("/docs/"));
+        assert_eq!(
+            get(&app, "/user/?page=2&q=a%2Fb"),
+            response::permanent_redirect("/user?page=2&q=a%2Fb")
+        );
+        assert_eq!(get(&app, "/missing/").status, StatusCode::NOT_FOUND);
+        assert_eq!(get(&app, "/").status, StatusCode::OK);
+    }
+
+    fn duplicate_error<T>() -> String
+    where
+        T: Routeable<PlaceholderContext> + Clone + Sync + Send,
+    {
+        let mut app = Router::<_, T>::new("127.0.0.1:0", PlaceholderContext {}).unwrap();
+        app.handle("/files/{file}", thunk).unwrap();
+        return app.handle("/files/{name}", thunk).unwrap_err().to_string();
+    }
+
+    #[test]
+    fn router_duplicate_names_existing_route() {
+        for err in [
+            duplicate_error::<RouteTrie<_>>(),
+            duplicate_error::<RouteTable<_>>(),
+            duplicate_error::<RouteRadix<_>>(),
+        ] {
+            assert!(err.contains("/files/{name}"));
+            assert!(err.contains("/files/{file}"));
+        }
+    }
+
+    // keeps whatever it is given, like a `Routeable` that does not look for duplicates
+    struct Unchecked(Vec<Route<PlaceholderContext>>, MatchPolicy);
+
+    impl Clone for Unchecked {
+        fn clone(&self) -> Self {
+            return Unchecked(self.0.clone(), self.1);
+        }
+    }
+
+    impl Routeable<PlaceholderContext> for Unchecked {
+        fn with_policy(policy: MatchPolicy) -> Self {
+            return Unchecked(Vec::new(), policy);
+        }
+
+        fn policy(&self) -> MatchPolicy {
+            return self.1;
+        }
+
+        fn insert(&mut self, route: Route<PlaceholderContext>) -> Result<()> {
+            self.0.push(route);
+            return Ok(());
+        }
+
+        fn remove_route(
+            &mut self,
+            path: impl Into<SharedStr>,
+        ) -> Result<Route<PlaceholderContext>> {
+            let segments = parse_path(&path.into(), self.1)?;
+            let idx = self.0.iter().position(|r| return r.has_path(&segments));
+            return Ok(self
+                .0
+                .remove(idx.ok_or_else(|| return anyhow!("No route"))?));
+        }
+
+        fn match_route<'a>(
+            &'a self,
+            path: &'a str,
+        ) -> Option<(&'a dyn Handler<PlaceholderContext>, Identifiers<'a>)> {
+            for route in &self.0 {
+                if let Some(path_values) = route.matches(path) {
+                    return Some((route.handler.as_ref(), path_values));
+                }
+            }
+            return None;
+        }
+
+        fn routes(&self) -> Vec<&Route<PlaceholderContext>> {
+            return self.0.iter().collect();
+        }
+    }
+
+    #[test]
+    fn router_reports_duplicates_the_routes_accept() {
+        let mut app = Router::<_, Unchecked>::new("127.0.0.1:0", PlaceholderContext {}).unwrap();
+        app.handle("/files/{file}", thunk).unwrap();
+        app.handle("/files/{name}", thunk).unwrap();
+        let report = app.validate();
+        assert_eq!(report.conflicts.len(), 1);
+        assert_eq!(report.conflicts[0].kind, ConflictKind::Duplicate);
+
+        app.strict(true);
+        let err = app.run().unwrap_err().to_string();
+        assert!(err.contains("Refusing to run with 1 route conflict(s)"));
+        assert!(err.contains("duplicate: /files/"));
+    }
+
+    #[test]
+    fn router_strict_refuses_conflicts() {
+        let mut app = Router::<_, RouteTrie<_>>::new("127.0.0.1:0", PlaceholderContext {}).unwrap();
+        app.handle("/user/dashboard", thunk).unwrap();
+        app.handle("/user/{id}/settings", thunk).unwrap();
+        assert!(!app.validate().is_clean());
+
+        app.strict(true);
+        assert!(app.run().is_err());
+    }
+
+    #[test]
+    fn router_live_route_updates() {
+        let mut app = Router::<_, RouteTrie<_>>::new("127.0.0.1:0", PlaceholderContext {}).unwrap();
+        app.handle("/", thunk).unwrap();
+        app.handle("/beta", thunk).unwrap();
+
+        let live = app.live_routes();
+        let removed = live.update(|routes| {
+            routes.remove_route("/beta")?;
+            return Ok(());
+        });
+        assert!(removed.is_ok());
+        assert_eq!(get(&app, "/beta").status, StatusCode::NOT_FOUND);
+
+        // registering keeps working once a handle is out, it goes through a copy instead
+        app.handle("/plugin/{name}", thunk).unwrap();
+        assert_eq!(get(&app, "/plugin/x").status, StatusCode::OK);
+
+        let removed = live.update(|routes| {
+            routes.remove_route("/missing")?;
+            return Ok(());
+        });
+        assert!(removed.is_err());
+        assert_eq!(get(&app, "/").status, StatusCode::OK);
+    }
+
+    #[test]
+    fn router_closure_handlers_share_state() {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let mut app = Router::<_, RouteTrie<_>>::new("127.0.0.1:0", PlaceholderContext {}).unwrap();
+        for path in ["/a", "/b"] {
+            let hits = hits.clone();
+            let counter = move |_req: &Request, _ids: &Identifiers, _ctx: &PlaceholderContext| {
+                hits.fetch_add(1, Ordering::Relaxed);
+                return StatusCode::OK;
+            };
+            app.handle(path, counter).unwrap();
+        }
+
+        assert_eq!(get(&app, "/a").status, StatusCode::OK);
+        assert_eq!(get(&app, "/b").status, StatusCode::OK);
+        assert_eq!(get(&app, "/a").status, StatusCode::OK);
+        assert_eq!(hits.load(Ordering::Relaxed), 3);
+    }
+
+    fn tagged(
+        tag: &'static str,
+    ) -> impl Fn(&Request, &Identifiers, &PlaceholderContext, Next<PlaceholderContext>) -> Response
+    {
+        return move |req, ids, ctx, next| {
+            let mut response = next.run(req, ids, ctx);
+            response.body.splice(0..0, tag.bytes());
+            return response;
+        };
+    }
+
+    #[test]
+    fn router_middleware_order() {
+        let mut app = Router::<_, RouteTrie<_>>::new("127.0.0.1:0", PlaceholderContext {}).unwrap();
+        app.layer(tagged("global1 "));
+        app.layer(tagged("global2 "));
+
+        let mut group = RouteGroup::new();
+        group.layer(tagged("group "));
+        group.handle("/grouped", thunk);
+        group.handle("/both", Layered::new(thunk).layer(tagged("route ")));
+        app.group(group).unwrap();
+        app.handle("/plain", thunk).unwrap();
+
+        let body = |path| return String::from_utf8(get(&app, path).body).unwrap();
+        assert_eq!(body("/plain"), "global1 global2 ");
+        assert_eq!(body("/grouped"), "global1 global2 group ");
+        assert_eq!(body("/both"), "global1 global2 group route ");
+        // unmatched paths never reach the layers
+        assert_eq!(get(&app, "/missing").status, StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn router_middleware_short_circuit() {
+        let mut app = Router::<_, RouteTrie<_>>::new("127.0.0.1:0", PlaceholderContext {}).unwrap();
+        app.layer(
+            |req: &Request, ids: &Identifiers, ctx: &PlaceholderContext, next: Next<_>| {
+                if req.headers.contains_key("Authorization") {
+                    return next.run(req, ids, ctx);
+                }
+                return Response::new(StatusCode(401));
+            },
+        );
+        app.handle("/", thunk).unwrap();
+
+        assert_eq!(get(&app, "/").status, StatusCode(401));
+        let raw = "GET / HTTP/1.1\r\nAuthorization: token\r\n\r\n";
+        let request = Request::from(raw.as_bytes()).unwrap();
+        assert_eq!(app.handle_request(&request).status, StatusCode::OK);
+    }
+
+    fn echo_path(
+        req: &Request,
+        _paths: &Identifiers,
+        _ctx: &PlaceholderContext,
+    ) -> Result<Vec<u8>> {
+        return Ok(req.path.as_bytes().to_vec());
+    }
+
+    fn missing(_req: &Request, _paths: &Identifiers, _ctx: &PlaceholderContext) -> Result<Vec<u8>> {
+        return Ok(b"missing".to_vec());
+    }
+
+    #[test]
+    fn router_mount_group() {
+        let mut files = RouteGroup::new();
+        files.strip_prefix(true);
+        files.handle("/{file}", echo_path);
+        files.fallback(
+            |req: &Request, ids: &Identifiers, _ctx: &PlaceholderContext| {
+                let version = ids.path_values.get("version").unwrap();
+                return format!("no file at {} in {}", req.path, version);
+            },
+        );
+        let mut api = RouteGroup::new();
+        api.handle("/", echo_path);
+        api.nest("/files", files);
+
+        let mut app = Router::<_, RouteTrie<_>>::new("127.0.0.1:0", PlaceholderContext {}).unwrap();
+        app.mount("/api/{version}", api).unwrap();
+        app.fallback(missing).unwrap();
+
+        assert_eq!(get(&app, "/api/v1").body, b"/api/v1".to_vec());
+        assert_eq!(get(&app, "/api/v1/files/a.txt").body, b"/a.txt".to_vec());
+        assert_eq!(
+            get(&app, "/api/v2/files/a/b").body,
+            b"no file at /a/b in v2".to_vec()
+        );
+        assert_eq!(get(&app, "/api/v1/other").body, b"missing".to_vec());
+        assert_eq!(get(&app, "/").body, b"missing".to_vec());
+    }
+
+    #[test]
+    fn router_mount_conflicts_with_existing_routes() {
+        let mut app = Router::<_, RouteTrie<_>>::new("127.0.0.1:0", PlaceholderContext {}).unwrap();
+        app.handle("/api/health", thunk).unwrap();
+        let mut api = RouteGroup::new();
+        api.handle("/health", thunk);
+        assert!(app.mount("/api", api).is_err());
+        // without a fallback unmatched paths below the prefix are still not found
+        assert_eq!(get(&app, "/api/other").status, StatusCode::NOT_FOUND);
+
+        // the routes before the conflicting one are not left behind
+        let mut pages = RouteGroup::new();
+        pages.handle("/about", thunk);
+        pages.handle("/api/health", thunk);
+        pages.handle("/contact", thunk);
+        assert!(app.group(pages).is_err());
+        assert_eq!(get(&app, "/about").status, StatusCode::NOT_FOUND);
+        assert_eq!(app.validate().routes, vec!["/api/health"]);
+    }
+
+    #[test]
+    fn router_url_for_named_routes() {
+        let mut files = RouteGroup::new();
+        files.handle_named("file", "/{file}", thunk);
+        let mut app = Router::<_, RouteTrie<_>>::new("127.0.0.1:0", PlaceholderContext {}).unwrap();
+        app.handle_named("home", "/", thunk).unwrap();
+        app.mount("/files", files).unwrap();
+
+        assert_eq!(app.url_for("home", [("", ""); 0]).unwrap(), "/");
+        let url = app.url_for("file", [("file", "a b.txt")]).unwrap();
+        assert_eq!(url, "/files/a%20b.txt");
+        assert_eq!(get(&app, &url).status, StatusCode::OK);
+
+        // a taken name does not register the route
+        assert!(app.handle_named("home", "/other", thunk).is_err());
+        assert_eq!(get(&app, "/other").status, StatusCode::NOT_FOUND);
+        let mut taken = RouteGroup::new();
+        taken.handle("/list", thunk);
+        taken.handle_named("home", "/home", thunk);
+        assert!(app.mount("/more", taken).is_err());
+        assert_eq!(get(&app, "/more/list").status, StatusCode::NOT_FOUND);
+        let mut twice = RouteGroup::new();
+        twice.handle_named("page", "/a", thunk);
+        twice.handle_named("page", "/b", thunk);
+        assert!(app.group(twice).is_err());
+        assert_eq!(get(&app, "/a").status, StatusCode::NOT_FOUND);
+        assert!(app.share_route_names(Arc::new(RouteNames::new())).is_err());
+    }
+
+    #[test]
+    fn router_describes_routes() {
+        let mut api = RouteGroup::new();
+        api.strip_prefix(true);
+        api.layer(tagged("api "));
+        api.handle("/{id}", describe(thunk).summary("One item"));
+        let mut app = Router::<_, RouteTrie<_>>::new("127.0.0.1:0", PlaceholderContext {}).unwrap();
+        app.mount("/items", api).unwrap();
+        app.openapi("/openapi.json", "items", "1").unwrap();
+
+        let info = app.routes_info();
+        let paths = info
+            .iter()
+            .map(|r| return r.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec!["/items/{id}", "/openapi.json"]);
+        // the layers and prefix stripping wrapped around the handler keep its documentation
+        let summary = info[0]
+            .meta
+            .as_ref()
+            .and_then(|m| return m.summary.as_deref());
+        assert_eq!(summary, Some("One item"));
+
+        // routes added later show up in the document
+        app.handle("/health", thunk).unwrap();
+        let response = get(&app, "/openapi.json");
+        assert_eq!(response.header("Content-Type"), Some("application/json"));
+        let body = String::from_utf8(response.body).unwrap();
+        assert!(body.starts_with(r#"{"openapi":"3.1.0","info":{"title":"items""#));
+        assert!(body.contains(r#""/health":{"get""#));
+        assert!(body.contains(r#""/items/{id}":{"get":{"summary":"One item""#));
+    }
+
+    #[derive(Clone)]
+    struct User(String);
+
+    // how long the handler took, as measured by the handler itself
+    struct Timing(u32);
+
+    #[test]
+    fn router_layers_share_extensions() {
+        let mut app = Router::<_, RouteTrie<_>>::new("127.0.0.1:0", PlaceholderContext {}).unwrap();
+        app.layer(
+            |req: &Request, ids: &Identifiers, ctx: &PlaceholderContext, next: Next<_>| {
+                let mut response = next.run(req, ids, ctx);
+                if let Some(Timing(ms)) = response.extensions.get::<Timing>() {
+                    let ms = ms.to_string();
+                    response.set_header("Server-Timing", ms);
+                }
+                return response;
+            },
+        );
+        app.layer(
+            |req: &Request, ids: &Identifiers, ctx: &PlaceholderContext, next: Next<_>| {
+                let Some(token) = req.header("Authorization") else {
+                    return next.run(req, ids, ctx);
+                };
+                let mut req = req.clone();
+                req.extensions.insert(User(token.to_string()));
+                return next.run(&req, ids, ctx);
+            },
+        );
+        let whoami = |Extension(User(name)): Extension<User>| {
+            let mut response = name.into_response();
+            response.extensions.insert(Timing(3));
+            return response;
+        };
+        app.handle("/whoami", extract(whoami)).unwrap();
+
+        let raw = "GET /whoami HTTP/1.1\r\nAuthorization: ada\r\n\r\n";
+        let response = app.handle_request(&Request::from(raw.as_bytes()).unwrap());
+        assert_eq!(response.body, b"ada".to_vec());
+        assert_eq!(response.header("Server-Timing"), Some("3"));
+        let response = get(&app, "/whoami");
+        assert_eq!(response.status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(response.header("Server-Timing"), None);
+    }
+
+    struct Hits(AtomicUsize);
+
+    #[test]
+    fn router_managed_state() {
+        let mut app = Router::<_, RouteTrie<_>>::new("127.0.0.1:0", PlaceholderContext {}).unwrap();
+        app.manage(Hits(AtomicUsize::new(0))).unwrap();
+        app.manage(String::from("v1")).unwrap();
+        assert!(app.manage(String::from("v2")).is_err());
+        app.layer(
+            |req: &Request, ids: &Identifiers, ctx: &PlaceholderContext, next: Next<_>| {
+                let hits = ids.managed::<Hits>().unwrap();
+                hits.0.fetch_add(1, Ordering::Relaxed);
+                return next.run(req, ids, ctx);
+            },
+        );
+        app.layer(
+            |req: &Request, ids: &Identifiers, ctx: &PlaceholderContext, next: Next<_>| {
+                if req.path != "/beta" {
+                    return next.run(req, ids, ctx);
+                }
+                let mut req = req.clone();
+                req.extensions.insert(String::from("beta"));
+                return next.run(&req, ids, ctx);
+            },
+        );
+        let count = |Managed(hits): Managed<Hits>, version: Managed<String>| {
+            return format!("{} {}", version.as_str(), hits.0.load(Ordering::Relaxed));
+        };
+        app.handle("/count", extract(count)).unwrap();
+        assert_eq!(get(&app, "/count").body, b"v1 1".to_vec());
+        // a value the layer put in the extensions hides the managed one
+        app.handle("/beta", extract(count)).unwrap();
+        assert_eq!(get(&app, "/beta").body, b"beta 2".to_vec());
+
+        // every serving thread works on the same instance
+        let app = Arc::new(app);
+        let threads = (0..4)
+            .map(|_| {
+                let app = app.clone();
+                return std::thread::spawn(move || {
+                    for _ in 0..25 {
+                        get(&app, "/count");
+                    }
+                });
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        let hits = app.state::<Hits>().unwrap();
+        assert_eq!(hits.0.load(Ordering::Relaxed), 102);
+        assert!(app.state::<u32>().is_none());
+    }
+
+    #[test]
+    fn router_reads_whole_messages() {
+        // a body in several reads, as it comes over a slow connection
+        let raw = b"POST /a HTTP/1.1\r\nContent-Length: 6\r\n\r\nab\r\ncd";
+        let mut stream = std::io::Read::chain(&raw[..30], &raw[30..]);
+        assert_eq!(read_message(&mut stream).unwrap(), Some(raw.to_vec()));
+
+        let endless = format!("GET / HTTP/1.1\r\nX: {}", "a".repeat(MAX_HEAD_SIZE));
+        assert_eq!(read_message(&mut endless.as_bytes()).unwrap(), None);
+        let huge = b"POST /a HTTP/1.1\r\ncontent-length: 999999999\r\n\r\n";
+        assert_eq!(read_message(&mut &huge[..]).unwrap(), None);
+    }
 }
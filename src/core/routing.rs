@@ -1,24 +1,133 @@
+use crate::core::extensions::Extensions;
+use crate::core::introspect::RouteMeta;
 use crate::core::request::Request;
+use crate::core::response::{IntoResponse, Response};
+use crate::core::shared_str::SharedStr;
 use anyhow::anyhow;
 use anyhow::Result;
 use itertools::Itertools;
+use std::borrow::Cow;
 use std::cmp::{Eq, Ord, Ordering, PartialEq, PartialOrd};
-use std::collections::HashMap;
 use std::collections::HashSet;
+use std::fmt;
 use std::hash::Hash;
 use std::hash::Hasher;
+use std::sync::Arc;
+
+/// Captures kept inline, a match with no more than these does not allocate.
+pub const MAX_CAPTURES: usize = 8;
+
+/// Capture key to path value pairs, stored in a fixed array so matching does not allocate.
+/// Routes with more captures than fit spill the rest to the heap.
+#[derive(Debug, Clone)]
+pub struct Captures<'a> {
+    entries: [(&'a str, &'a str); MAX_CAPTURES],
+    len: usize,
+    // past the inline ones, empty and unallocated for most routes
+    spilled: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> Default for Captures<'a> {
+    fn default() -> Self {
+        return Captures::new();
+    }
+}
+
+impl<'a> Captures<'a> {
+    pub fn new() -> Captures<'a> {
+        return Captures {
+            entries: [("", ""); MAX_CAPTURES],
+            len: 0,
+            spilled: Vec::new(),
+        };
+    }
+
+    pub fn insert(&mut self, key: &'a str, value: &'a str) {
+        if self.len < MAX_CAPTURES {
+            self.entries[self.len] = (key, value);
+            self.len += 1;
+            return;
+        }
+        self.spilled.push((key, value));
+    }
+
+    pub fn get(&self, key: &str) -> Option<&&'a str> {
+        return self
+            .iter()
+            .find(|(k, _)| return *k == key)
+            .map(|(_, v)| return v);
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        return self.get(key).is_some();
+    }
+
+    pub fn len(&self) -> usize {
+        return self.len + self.spilled.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(&'a str, &'a str)> {
+        return self.entries[..self.len].iter().chain(&self.spilled);
+    }
+
+    /// Drops every capture inserted after the first `len`, used when a match attempt backtracks.
+    pub fn truncate(&mut self, len: usize) {
+        self.spilled.truncate(len.saturating_sub(MAX_CAPTURES));
+        self.len = self.len.min(len);
+    }
+}
 
 pub struct Identifiers<'a> {
-    pub path_values: HashMap<&'static str, &'a str>,
+    pub path_values: Captures<'a>,
+    /// The state registered with `Router::manage`, set by the router before the layers run.
+    pub state: Option<&'a Extensions>,
+}
+
+impl<'a> Identifiers<'a> {
+    pub fn new(path_values: Captures<'a>) -> Identifiers<'a> {
+        return Identifiers {
+            path_values,
+            state: None,
+        };
+    }
+
+    /// The state registered for `T` with `Router::manage`.
+    pub fn managed<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        return self.state?.get_shared();
+    }
 }
 
-pub type RouteHandler<Context> =
-    fn(req: &Request, path_vals: &Identifiers, ctx: &Context) -> Result<Vec<u8>>;
+/// Anything that can answer a matched request, implemented for `fn` items and closures so a
+/// handler can carry its own configuration. Handlers run on the serving threads concurrently.
+pub trait Handler<Context>: Send + Sync {
+    fn call(&self, req: &Request, path_vals: &Identifiers, ctx: &Context) -> Response;
+
+    /// Documentation of the route, see `introspect::describe`. Wrapping handlers pass it through.
+    fn meta(&self) -> Option<&RouteMeta> {
+        return None;
+    }
+}
+
+impl<Context, F, R> Handler<Context> for F
+where
+    F: Fn(&Request, &Identifiers, &Context) -> R + Send + Sync,
+    R: IntoResponse,
+{
+    fn call(&self, req: &Request, path_vals: &Identifiers, ctx: &Context) -> Response {
+        return self(req, path_vals, ctx).into_response();
+    }
+}
 
-#[derive(Debug)]
+pub type RouteHandler<Context> = Arc<dyn Handler<Context>>;
+
+#[derive(Debug, Clone)]
 pub enum Segments {
-    Literal(&'static str),
-    Capture(&'static str),
+    Literal(SharedStr),
+    Capture(SharedStr),
 }
 
 impl PartialEq for Segments {
@@ -60,39 +169,163 @@ impl Ord for Segments {
     }
 }
 
+impl fmt::Display for Segments {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Segments::Literal(s) => return write!(f, "{}", s),
+            Segments::Capture(s) => return write!(f, "{{{}}}", s),
+        }
+    }
+}
+
 impl Segments {
-    pub fn new(seg: &'static str) -> Result<Segments> {
-        let chars = seg.chars().collect_vec();
-        let n = chars.len();
+    pub fn new(seg: impl Into<SharedStr>) -> Result<Segments> {
+        let seg = seg.into();
+        let n = seg.len();
         if n < 2 {
             return Ok(Segments::Literal(seg));
         }
 
-        if chars[0] == '{' && chars[n - 1] == '}' {
+        if seg.starts_with('{') && seg.ends_with('}') {
             if n == 2 {
                 return Err(anyhow!("Capture group does not have associated key."));
             }
-            return Ok(Segments::Capture(&seg[1..n - 1]));
+            return Ok(Segments::Capture(seg.slice(1..n - 1)));
         }
         return Ok(Segments::Literal(seg));
     }
 }
 
+/// How a router treats a trailing `/` at the end of a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TrailingSlash {
+    /// `/user` and `/user/` are distinct routes.
+    #[default]
+    Strict,
+    /// `/user` and `/user/` are the same route, registering both is a duplicate.
+    Ignore,
+    /// Only the registered form matches, the router redirects the other form to it with a 308.
+    Redirect,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MatchPolicy {
+    pub trailing_slash: TrailingSlash,
+    // only applies to literal segments, captured values are always passed through untouched
+    pub case_insensitive: bool,
+}
+
+impl MatchPolicy {
+    /// Drops the trailing slash of a path when the policy ignores it, the root `/` is left alone.
+    pub fn normalize<'a>(&self, path: &'a str) -> &'a str {
+        if self.trailing_slash != TrailingSlash::Ignore || path.len() < 2 {
+            return path;
+        }
+        return path.strip_suffix('/').unwrap_or(path);
+    }
+
+    /// Canonical form of a literal segment, used as the lookup key by the routing structures.
+    pub fn fold<'a>(&self, segment: &'a str) -> Cow<'a, str> {
+        if self.case_insensitive && segment.bytes().any(|b| return b.is_ascii_uppercase()) {
+            return Cow::Owned(segment.to_ascii_lowercase());
+        }
+        return Cow::Borrowed(segment);
+    }
+
+    /// Same as `fold` but keeps static text static, for keys that outlive the lookup.
+    pub fn fold_key(&self, segment: &SharedStr) -> SharedStr {
+        match self.fold(segment) {
+            Cow::Borrowed(_) => return segment.clone(),
+            Cow::Owned(folded) => return SharedStr::from(folded),
+        }
+    }
+
+    pub fn literal_matches(&self, literal: &str, segment: &str) -> bool {
+        if self.case_insensitive {
+            return literal.eq_ignore_ascii_case(segment);
+        }
+        return literal == segment;
+    }
+
+    pub fn segments_eq(&self, a: &Segments, b: &Segments) -> bool {
+        match (a, b) {
+            (Segments::Literal(a), Segments::Literal(b)) => return self.literal_matches(a, b),
+            (Segments::Capture(_), Segments::Capture(_)) => return true,
+            _ => return false,
+        }
+    }
+
+    /// Orders segments so that the ones `segments_eq` takes for equal compare equal.
+    pub fn segments_cmp(&self, a: &Segments, b: &Segments) -> Ordering {
+        match (a, b) {
+            (Segments::Literal(a), Segments::Literal(b)) => return self.fold(a).cmp(&self.fold(b)),
+            _ => return a.cmp(b),
+        }
+    }
+}
+
 pub trait Routeable<Context> {
-    fn new() -> Self;
-    fn add_route(&mut self, path: &'static str, handler: RouteHandler<Context>) -> Result<()>;
-    fn match_route<'a>(&self, path: &'a str) -> Option<(RouteHandler<Context>, Identifiers<'a>)>;
+    fn new() -> Self
+    where
+        Self: Sized,
+    {
+        return Self::with_policy(MatchPolicy::default());
+    }
+    fn with_policy(policy: MatchPolicy) -> Self;
+    fn policy(&self) -> MatchPolicy;
+    /// Adds an already built route, the route must have been built with `self.policy()`.
+    fn insert(&mut self, route: Route<Context>) -> Result<()>;
+    fn add_route(
+        &mut self,
+        path: impl Into<SharedStr>,
+        handler: impl Handler<Context> + 'static,
+    ) -> Result<()> {
+        return self.insert(Route::with_policy(path, handler, self.policy())?);
+    }
+    /// Removes the route registered for `path`, capture names do not have to match.
+    fn remove_route(&mut self, path: impl Into<SharedStr>) -> Result<Route<Context>>;
+    /// Swaps the handler of an existing route, the routes are left untouched on failure.
+    fn replace_route(
+        &mut self,
+        path: impl Into<SharedStr>,
+        handler: impl Handler<Context> + 'static,
+    ) -> Result<Route<Context>>
+    where
+        Self: Sized + Clone,
+    {
+        // changed on a copy, putting the old route back after a failed insert could fail as well
+        let path = path.into();
+        let mut replaced = self.clone();
+        let previous = replaced.remove_route(path.clone())?;
+        replaced.add_route(path, handler)?;
+        *self = replaced;
+        return Ok(previous);
+    }
+    fn match_route<'a>(
+        &'a self,
+        path: &'a str,
+    ) -> Option<(&'a dyn Handler<Context>, Identifiers<'a>)>;
+    /// Every registered route, in no particular order.
+    fn routes(&self) -> Vec<&Route<Context>>;
 }
 
-#[derive(Debug)]
 pub struct Route<Context> {
+    pub path: SharedStr, // as registered, before the policy normalizes it
     pub path_segments: Vec<Segments>,
     pub handler: RouteHandler<Context>, // handler should not matter when comparing routes
+    pub policy: MatchPolicy,
 }
 
 impl<Context> Hash for Route<Context> {
     fn hash<H: Hasher>(&self, hasher: &mut H) {
-        self.path_segments.hash(hasher);
+        // must agree with the policy aware equality below
+        self.path_segments.len().hash(hasher);
+        for segment in &self.path_segments {
+            match segment {
+                Segments::Literal(s) => self.policy.fold(s).hash(hasher),
+                Segments::Capture(_) => 0.hash(hasher),
+            }
+        }
     }
 }
 
@@ -105,75 +338,150 @@ where
     return iter.into_iter().all(move |x| return uniq.insert(x));
 }
 
-impl<Context> Route<Context> {
-    pub fn new(path: &'static str, handler: RouteHandler<Context>) -> Result<Route<Context>> {
-        let path_segments = path
-            .split('/')
-            .map(|s| return Segments::new(s))
-            .collect_vec();
-        if path_segments.iter().any(|e| return e.is_err()) {
-            return Err(anyhow!(
-                "Capture group(s) do not have associated key: {path}"
-            ));
-        }
-        let captured = path_segments.iter().filter_map(|s| match s {
-            Ok(Segments::Capture(x)) => return Some(x),
+/// Splits a request path after its first `count` segments, counting the empty one before the
+/// leading slash. The second part is empty or starts with a slash.
+pub fn split_after_segments(path: &str, count: usize) -> (&str, &str) {
+    if count == 0 {
+        return ("", path);
+    }
+    match path.match_indices('/').nth(count - 1) {
+        Some((end, _)) => return path.split_at(end),
+        None => return (path, ""),
+    }
+}
+
+/// Splits a route path into its segments, normalized by the policy.
+pub fn parse_path(path: &SharedStr, policy: MatchPolicy) -> Result<Vec<Segments>> {
+    let mut start = 0;
+    let mut path_segments = Vec::new();
+    // sliced out of `path` so static paths keep borrowing instead of copying
+    for seg in policy.normalize(path).split('/') {
+        path_segments.push(Segments::new(path.slice(start..start + seg.len())));
+        start += seg.len() + 1;
+    }
+    if path_segments.iter().any(|e| return e.is_err()) {
+        return Err(anyhow!(
+            "Capture group(s) do not have associated key: {path}"
+        ));
+    }
+    let captured = path_segments.iter().filter_map(|s| match s {
+        Ok(Segments::Capture(x)) => return Some(x),
+        _ => return None,
+    });
+    if !has_unique_elements(captured) {
+        return Err(anyhow!(
+            "Same key used with multiple capture groups: {}",
+            path
+        ));
+    }
+    return Ok(path_segments
+        .into_iter()
+        .filter_map(|res| match res {
+            Ok(x) => return Some(x),
             _ => return None,
-        });
-        if !has_unique_elements(captured) {
-            return Err(anyhow!(
-                "Same key used with multiple capture groups: {}",
-                path
-            ));
-        }
-        let path_segments = path_segments
-            .into_iter()
-            .filter_map(|res| match res {
-                Ok(x) => return Some(x),
-                _ => return None,
-            })
-            .collect_vec();
+        })
+        .collect_vec());
+}
+
+impl<Context> Route<Context> {
+    pub fn new(
+        path: impl Into<SharedStr>,
+        handler: impl Handler<Context> + 'static,
+    ) -> Result<Route<Context>> {
+        return Route::with_policy(path, handler, MatchPolicy::default());
+    }
+
+    pub fn with_policy(
+        path: impl Into<SharedStr>,
+        handler: impl Handler<Context> + 'static,
+        policy: MatchPolicy,
+    ) -> Result<Route<Context>> {
+        let path = path.into();
         return Ok(Route {
-            path_segments,
-            handler,
+            path_segments: parse_path(&path, policy)?,
+            path,
+            handler: Arc::new(handler),
+            policy,
         });
     }
 
-    pub fn matches<'a>(&self, path: &'a str) -> Option<Identifiers<'a>> {
-        let mut path_values = HashMap::new();
+    /// Whether the route was registered for the given segments, ignoring the capture names.
+    pub fn has_path(&self, segments: &[Segments]) -> bool {
+        if self.path_segments.len() != segments.len() {
+            return false;
+        }
+        return self
+            .path_segments
+            .iter()
+            .zip(segments.iter())
+            .all(|(a, b)| return self.policy.segments_eq(a, b));
+    }
+
+    pub fn matches<'a>(&'a self, path: &'a str) -> Option<Identifiers<'a>> {
+        let mut path_values = Captures::new();
 
-        let path = path.split('/').collect_vec();
-        if path.len() != self.path_segments.len() {
+        let path = self.policy.normalize(path);
+        if path.split('/').count() != self.path_segments.len() {
             return None;
         }
-        for (segment, p) in self.path_segments.iter().zip(path.iter()) {
+        for (segment, p) in self.path_segments.iter().zip(path.split('/')) {
             match segment {
                 Segments::Capture(key) => {
-                    path_values.insert(*key, *p);
+                    path_values.insert(key, p);
                 }
                 Segments::Literal(route) => {
-                    if route != p {
+                    if !self.policy.literal_matches(route, p) {
                         return None;
                     }
                 }
             }
         }
 
-        return Some(Identifiers { path_values });
+        return Some(Identifiers::new(path_values));
     }
 }
 
 impl<Context> PartialEq for Route<Context> {
     fn eq(&self, other: &Self) -> bool {
-        return self.path_segments.eq(&other.path_segments);
+        return self.has_path(&other.path_segments);
+    }
+}
+
+// handlers are shared, so cloning a route never needs `Context: Clone`
+impl<Context> Clone for Route<Context> {
+    fn clone(&self) -> Self {
+        return Route {
+            path: self.path.clone(),
+            path_segments: self.path_segments.clone(),
+            handler: self.handler.clone(),
+            policy: self.policy,
+        };
+    }
+}
+
+impl<Context> fmt::Debug for Route<Context> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return f
+            .debug_struct("Route")
+            .field("path", &self.path)
+            .field("path_segments", &self.path_segments)
+            .field("policy", &self.policy)
+            .finish_non_exhaustive();
     }
 }
 
 impl<Context> Eq for Route<Context> {}
 
+// must agree with the policy aware equality, as the hash does
 impl<Context> Ord for Route<Context> {
     fn cmp(&self, other: &Self) -> Ordering {
-        return self.path_segments.cmp(&other.path_segments);
+        for (a, b) in self.path_segments.iter().zip(&other.path_segments) {
+            let ordering = self.policy.segments_cmp(a, b);
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        return self.path_segments.len().cmp(&other.path_segments.len());
     }
 }
 
@@ -187,10 +495,17 @@ impl<Context> PartialOrd for Route<Context> {
 #[cfg(test)]
 mod tests {
     use crate::core::request::Request;
+    use crate::core::response::{IntoResponse, Response};
     use crate::core::routing::Route;
     use crate::core::routing::Segments;
+    use crate::core::routing::{
+        split_after_segments, Captures, Handler, MatchPolicy, TrailingSlash,
+    };
+    use crate::core::shared_str::SharedStr;
     use anyhow::anyhow;
     use anyhow::Result;
+    use std::cmp::Ordering;
+    use std::collections::BTreeSet;
 
     #[derive(Debug)]
     struct PlaceholderContext {}
@@ -203,7 +518,7 @@ mod tests {
         let segment = Segments::new(orig);
         assert!(segment.is_ok());
         let segment = segment.unwrap();
-        assert_eq!(segment, Segments::Literal(orig));
+        assert_eq!(segment, Segments::Literal(orig.into()));
     }
 
     #[test]
@@ -212,7 +527,7 @@ mod tests {
         let segment = Segments::new(orig);
         assert!(segment.is_ok());
         let segment = segment.unwrap();
-        assert_eq!(segment, Segments::Capture("capture"));
+        assert_eq!(segment, Segments::Capture("capture".into()));
     }
 
     #[test]
@@ -238,6 +553,78 @@ mod tests {
         return Err(anyhow!("thunk2"));
     }
 
+    struct Greeting {
+        greeting: String,
+    }
+
+    impl Handler<PlaceholderContext> for Greeting {
+        fn call(
+            &self,
+            _req: &Request,
+            path_vals: &Identifiers,
+            _ctx: &PlaceholderContext,
+        ) -> Response {
+            let name = path_vals.path_values.get("name").unwrap();
+            return format!("{} {}", self.greeting, name).into_response();
+        }
+    }
+
+    #[test]
+    fn route_handler_kinds() {
+        let request = Request::from(b"GET /hello/bob HTTP/1.1\r\n\r\n").unwrap();
+        let ctx = PlaceholderContext {};
+        let greeting = String::from("hi");
+        let handlers = [
+            Route::new("/hello/{name}", thunk).unwrap(),
+            Route::new(
+                "/hello/{name}",
+                move |_req: &Request, ids: &Identifiers, _ctx: &PlaceholderContext| {
+                    let name = ids.path_values.get("name").unwrap();
+                    return format!("{} {}", greeting, name);
+                },
+            )
+            .unwrap(),
+            Route::new(
+                "/hello/{name}",
+                Greeting {
+                    greeting: String::from("hey"),
+                },
+            )
+            .unwrap(),
+        ];
+
+        let responses = handlers
+            .iter()
+            .map(|route| {
+                let ids = route.matches(request.path).unwrap();
+                return route.handler.call(&request, &ids, &ctx).body;
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            responses,
+            vec![b"".to_vec(), b"hi bob".to_vec(), b"hey bob".to_vec()]
+        );
+    }
+
+    #[test]
+    fn split_after_segments_cases() {
+        assert_eq!(
+            split_after_segments("/api/v1/files", 0),
+            ("", "/api/v1/files")
+        );
+        assert_eq!(
+            split_after_segments("/api/v1/files", 1),
+            ("", "/api/v1/files")
+        );
+        assert_eq!(
+            split_after_segments("/api/v1/files", 3),
+            ("/api/v1", "/files")
+        );
+        assert_eq!(split_after_segments("/api/v1", 3), ("/api/v1", ""));
+        assert_eq!(split_after_segments("/api/v1/", 3), ("/api/v1", "/"));
+        assert_eq!(split_after_segments("/api", 3), ("/api", ""));
+    }
+
     #[test]
     fn route_same_if_path_same() {
         let orig = "/some/path/potato";
@@ -296,6 +683,28 @@ mod tests {
         assert_eq!(vals.path_values.get("msg").unwrap(), &msg);
     }
 
+    #[test]
+    fn route_from_runtime_path() {
+        let prefix = String::from("tenant");
+        let route = Route::new(format!("/{}/{{id}}", prefix), thunk).unwrap();
+        assert!(matches!(route.path, SharedStr::Shared(_)));
+        assert_eq!(route, Route::new("/tenant/{other}", thunk).unwrap());
+        let vals = route.matches("/tenant/42").unwrap();
+        assert_eq!(vals.path_values.get("id").unwrap(), &"42");
+    }
+
+    #[test]
+    fn route_static_path_not_copied() {
+        let route = Route::new("/some/{id}", thunk).unwrap();
+        for segment in &route.path_segments {
+            match segment {
+                Segments::Literal(s) | Segments::Capture(s) => {
+                    assert!(matches!(s, SharedStr::Static(_)));
+                }
+            }
+        }
+    }
+
     #[test]
     fn route_matches_fails() {
         let orig = "/some/{id}/potato";
@@ -321,4 +730,85 @@ mod tests {
         let route = Route::new(orig, thunk);
         assert!(route.is_err());
     }
+
+    #[test]
+    fn route_strict_trailing_slash() {
+        let route = Route::new("/user", thunk).unwrap();
+        assert!(route.matches("/user").is_some());
+        assert!(route.matches("/user/").is_none());
+    }
+
+    #[test]
+    fn route_ignore_trailing_slash() {
+        let policy = MatchPolicy {
+            trailing_slash: TrailingSlash::Ignore,
+            ..MatchPolicy::default()
+        };
+        let route = Route::with_policy("/user/", thunk, policy).unwrap();
+        assert!(route.matches("/user").is_some());
+        assert!(route.matches("/user/").is_some());
+        assert!(route.matches("/user//").is_none());
+
+        let other = Route::with_policy("/user", thunk2, policy).unwrap();
+        assert_eq!(route, other);
+
+        let root = Route::with_policy("/", thunk, policy).unwrap();
+        assert!(root.matches("/").is_some());
+    }
+
+    #[test]
+    fn route_case_insensitive() {
+        let strict = Route::new("/User/{id}", thunk).unwrap();
+        assert!(strict.matches("/user/1").is_none());
+
+        let policy = MatchPolicy {
+            case_insensitive: true,
+            ..MatchPolicy::default()
+        };
+        let route = Route::with_policy("/User/{id}", thunk, policy).unwrap();
+        let vals = route.matches("/uSER/AbC").unwrap();
+        // captured values keep their original case
+        assert_eq!(vals.path_values.get("id"), Some(&"AbC"));
+
+        let other = Route::with_policy("/user/{name}", thunk2, policy).unwrap();
+        assert_eq!(route, other);
+        // ordered as they compare, a set keeps one of them
+        assert_eq!(route.cmp(&other), Ordering::Equal);
+        let after = Route::with_policy("/USER/{id}/x", thunk, policy).unwrap();
+        assert_eq!(route.cmp(&after), Ordering::Less);
+        let routes = BTreeSet::from([route, other, after]);
+        assert_eq!(routes.len(), 2);
+    }
+
+    #[test]
+    fn route_many_captures() {
+        let orig = "/{a}/{b}/{c}/{d}/{e}/{f}/{g}/{h}/{i}";
+        let route = Route::new(orig, thunk).unwrap();
+        let vals = route.matches("/1/2/3/4/5/6/7/8/9").unwrap();
+        assert_eq!(vals.path_values.get("i"), Some(&"9"));
+    }
+
+    #[test]
+    fn captures_truncate() {
+        let mut captures = Captures::new();
+        captures.insert("a", "1");
+        captures.insert("b", "2");
+        captures.truncate(1);
+        assert_eq!(captures.len(), 1);
+        assert_eq!(captures.get("a"), Some(&"1"));
+        assert!(!captures.contains_key("b"));
+
+        // past the inline ones as well
+        let keys = ["a", "b", "c", "d", "e", "f", "g", "h", "i", "j"];
+        for key in keys {
+            captures.insert(key, key);
+        }
+        assert_eq!(captures.len(), 11);
+        captures.truncate(10);
+        assert_eq!(captures.get("i"), Some(&"i"));
+        assert!(!captures.contains_key("j"));
+        captures.truncate(8);
+        assert!(!captures.contains_key("h"));
+        assert_eq!(captures.iter().count(), 8);
+    }
 }
@@ -0,0 +1,231 @@
+use crate::core::response::{IntoResponse, Response, StatusCode};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// What to do about symbolic links met while resolving a path below the base directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymlinkPolicy {
+    /// Refuse any path that goes through a link.
+    #[default]
+    Deny,
+    /// Follow links as long as they point somewhere inside the base directory.
+    WithinBase,
+    /// Follow links wherever they point, the links themselves are trusted.
+    Follow,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PathError {
+    #[error("Path {0} is absolute")]
+    Absolute(String),
+    #[error("Path {0} leaves the base directory")]
+    Escapes(String),
+    #[error("Path {0} goes through a symbolic link")]
+    Symlink(String),
+    #[error("Path {0} contains a NUL byte")]
+    Nul(String),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl PathError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            PathError::Io(e) if e.kind() == io::ErrorKind::NotFound => {
+                return StatusCode::NOT_FOUND
+            }
+            PathError::Io(_) => return StatusCode::INTERNAL_SERVER_ERROR,
+            _ => return StatusCode::FORBIDDEN,
+        }
+    }
+}
+
+// the message would tell a client how the files are laid out, only the status is answered
+impl IntoResponse for PathError {
+    fn into_response(self) -> Response {
+        return Response::new(self.status());
+    }
+}
+
+/// The components of an untrusted relative path, with `.` and empty ones dropped.
+///
+/// Absolute paths, `..` and NUL bytes are refused rather than normalized away. Every storage
+/// takes names by these rules, whether or not it has a directory on disk.
+pub fn components(relative: &str) -> Result<Vec<&str>, PathError> {
+    if relative.contains('\0') {
+        return Err(PathError::Nul(relative.to_string()));
+    }
+    // a leading backslash is a root on some platforms and a file name on others
+    let first = Path::new(relative).components().next();
+    if relative.starts_with('\\')
+        || matches!(first, Some(Component::RootDir | Component::Prefix(_)))
+    {
+        return Err(PathError::Absolute(relative.to_string()));
+    }
+    let mut parts = Vec::new();
+    for part in relative.split(std::path::is_separator) {
+        match part {
+            "" | "." => continue,
+            ".." => return Err(PathError::Escapes(relative.to_string())),
+            part => parts.push(part),
+        }
+    }
+    return Ok(parts);
+}
+
+/// A directory that untrusted relative paths are resolved against without getting out of it.
+#[derive(Debug, Clone)]
+pub struct SafeRoot {
+    // canonical, so resolved paths can be compared against it
+    base: PathBuf,
+    symlinks: SymlinkPolicy,
+}
+
+impl SafeRoot {
+    pub fn new(base: impl AsRef<Path>, symlinks: SymlinkPolicy) -> io::Result<SafeRoot> {
+        return Ok(SafeRoot {
+            base: base.as_ref().canonicalize()?,
+            symlinks,
+        });
+    }
+
+    pub fn base(&self) -> &Path {
+        return &self.base;
+    }
+
+    pub fn symlinks(&self) -> SymlinkPolicy {
+        return self.symlinks;
+    }
+
+    /// Location of `relative` below the base, which does not have to exist yet.
+    ///
+    /// Absolute paths and `..` are refused outright rather than normalized away. The part of the
+    /// path that exists is checked one component at a time so a link anywhere along the way is
+    /// caught, and whatever it resolves to has to stay under the base unless links are trusted.
+    pub fn resolve(&self, relative: &str) -> Result<PathBuf, PathError> {
+        let parts = components(relative)?;
+        let mut resolved = self.base.clone();
+        let mut parts = parts.into_iter();
+        for part in parts.by_ref() {
+            resolved.push(part);
+            let metadata = match resolved.symlink_metadata() {
+                Ok(metadata) => metadata,
+                // nothing below a missing component can be a link yet
+                Err(e) if e.kind() == io::ErrorKind::NotFound => break,
+                Err(e) => return Err(PathError::Io(e)),
+            };
+            if !metadata.file_type().is_symlink() {
+                continue;
+            }
+            match self.symlinks {
+                SymlinkPolicy::Deny => return Err(PathError::Symlink(relative.to_string())),
+                SymlinkPolicy::WithinBase => {
+                    resolved = resolved.canonicalize()?;
+                    if !resolved.starts_with(&self.base) {
+                        return Err(PathError::Escapes(relative.to_string()));
+                    }
+                }
+                SymlinkPolicy::Follow => resolved = resolved.canonicalize()?,
+            }
+        }
+        resolved.extend(parts);
+        return Ok(resolved);
+    }
+
+    /// Checks a path found on disk, such as by walking the base, the same way as `resolve`.
+    pub fn check(&self, path: &Path) -> Result<PathBuf, PathError> {
+        let display = path.display().to_string();
+        let Ok(relative) = path.strip_prefix(&self.base) else {
+            return Err(PathError::Escapes(display));
+        };
+        let Some(relative) = relative.to_str() else {
+            return Err(PathError::Escapes(display));
+        };
+        return self.resolve(relative);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::response::StatusCode;
+    use crate::core::safe_path::{components, PathError, SafeRoot, SymlinkPolicy};
+    use std::fs;
+    use std::path::PathBuf;
+
+    // a fresh directory per test, laid out as base/{a.txt, sub/b.txt} next to outside/secret
+    fn layout(name: &str) -> (PathBuf, PathBuf) {
+        let root = std::env::temp_dir().join(format!("safe_path_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let base = root.join("base");
+        fs::create_dir_all(base.join("sub")).unwrap();
+        fs::create_dir_all(root.join("outside")).unwrap();
+        fs::write(base.join("a.txt"), "a").unwrap();
+        fs::write(base.join("sub/b.txt"), "b").unwrap();
+        fs::write(root.join("outside/secret"), "s").unwrap();
+        return (root, base);
+    }
+
+    #[test]
+    fn safe_path_components() {
+        assert_eq!(components("a//./b/").unwrap(), vec!["a", "b"]);
+        assert_eq!(components("").unwrap(), Vec::<&str>::new());
+        assert!(matches!(components("a/../b"), Err(PathError::Escapes(_))));
+        assert!(matches!(components("/a"), Err(PathError::Absolute(_))));
+        assert!(matches!(components("\\a"), Err(PathError::Absolute(_))));
+        assert!(matches!(components("a\0"), Err(PathError::Nul(_))));
+    }
+
+    #[test]
+    fn safe_path_rejects_escapes() {
+        let (root, base) = layout("escapes");
+        let safe = SafeRoot::new(&base, SymlinkPolicy::Deny).unwrap();
+        let base = safe.base().to_path_buf();
+
+        assert_eq!(safe.resolve("sub/./b.txt").unwrap(), base.join("sub/b.txt"));
+        assert_eq!(safe.resolve("new/file").unwrap(), base.join("new/file"));
+        for bad in ["../outside/secret", "sub/../../outside", "..", "a\0b"] {
+            let err = safe.resolve(bad).unwrap_err();
+            assert_eq!(err.status(), StatusCode::FORBIDDEN, "{}", bad);
+        }
+        assert!(matches!(
+            safe.resolve("/etc/passwd"),
+            Err(PathError::Absolute(_))
+        ));
+        assert!(safe.check(&base.join("sub/b.txt")).is_ok());
+        assert!(safe.check(&root.join("outside/secret")).is_err());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn safe_path_symlink_policy() {
+        use std::os::unix::fs::symlink;
+
+        let (root, base) = layout("symlinks");
+        symlink(root.join("outside"), base.join("out")).unwrap();
+        symlink(base.join("sub"), base.join("inner")).unwrap();
+
+        let deny = SafeRoot::new(&base, SymlinkPolicy::Deny).unwrap();
+        assert!(matches!(
+            deny.resolve("inner/b.txt"),
+            Err(PathError::Symlink(_))
+        ));
+        assert!(matches!(
+            deny.resolve("out/secret"),
+            Err(PathError::Symlink(_))
+        ));
+
+        let within = SafeRoot::new(&base, SymlinkPolicy::WithinBase).unwrap();
+        let inner = within.resolve("inner/b.txt").unwrap();
+        assert_eq!(inner, within.base().join("sub/b.txt"));
+        assert!(matches!(
+            within.resolve("out/secret"),
+            Err(PathError::Escapes(_))
+        ));
+
+        let follow = SafeRoot::new(&base, SymlinkPolicy::Follow).unwrap();
+        let secret = follow.resolve("out/secret").unwrap();
+        assert_eq!(fs::read_to_string(secret).unwrap(), "s");
+        fs::remove_dir_all(root).unwrap();
+    }
+}
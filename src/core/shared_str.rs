@@ -0,0 +1,146 @@
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::{Deref, Range};
+use std::sync::Arc;
+
+/// Text of a route, either borrowed for the whole program or shared ownership of text that was
+/// only known at runtime. Static text is never copied, shared text is cloned by reference count.
+#[derive(Clone)]
+pub enum SharedStr {
+    Static(&'static str),
+    Shared(Arc<str>),
+}
+
+impl SharedStr {
+    pub fn as_str(&self) -> &str {
+        match self {
+            SharedStr::Static(s) => return s,
+            SharedStr::Shared(s) => return s,
+        }
+    }
+
+    /// Part of the text, only shared text has to be copied for it.
+    pub fn slice(&self, range: Range<usize>) -> SharedStr {
+        match self {
+            SharedStr::Static(s) => return SharedStr::Static(&s[range]),
+            SharedStr::Shared(s) => return SharedStr::Shared(Arc::from(&s[range])),
+        }
+    }
+}
+
+impl Deref for SharedStr {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        return self.as_str();
+    }
+}
+
+impl Borrow<str> for SharedStr {
+    fn borrow(&self) -> &str {
+        return self.as_str();
+    }
+}
+
+impl From<&'static str> for SharedStr {
+    fn from(s: &'static str) -> Self {
+        return SharedStr::Static(s);
+    }
+}
+
+impl From<String> for SharedStr {
+    fn from(s: String) -> Self {
+        return SharedStr::Shared(Arc::from(s));
+    }
+}
+
+impl From<Arc<str>> for SharedStr {
+    fn from(s: Arc<str>) -> Self {
+        return SharedStr::Shared(s);
+    }
+}
+
+// everything below compares the text only, so static and shared text are interchangeable
+impl PartialEq for SharedStr {
+    fn eq(&self, other: &Self) -> bool {
+        return self.as_str() == other.as_str();
+    }
+}
+
+impl Eq for SharedStr {}
+
+impl PartialEq<str> for SharedStr {
+    fn eq(&self, other: &str) -> bool {
+        return self.as_str() == other;
+    }
+}
+
+impl PartialEq<&str> for SharedStr {
+    fn eq(&self, other: &&str) -> bool {
+        return self.as_str() == *other;
+    }
+}
+
+impl Hash for SharedStr {
+    fn hash<H: Hasher>(&self, hasher: &mut H) {
+        self.as_str().hash(hasher);
+    }
+}
+
+impl PartialOrd for SharedStr {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        return Some(self.cmp(other));
+    }
+}
+
+impl Ord for SharedStr {
+    fn cmp(&self, other: &Self) -> Ordering {
+        return self.as_str().cmp(other.as_str());
+    }
+}
+
+impl fmt::Display for SharedStr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return f.write_str(self.as_str());
+    }
+}
+
+impl fmt::Debug for SharedStr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return fmt::Debug::fmt(self.as_str(), f);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::shared_str::SharedStr;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    #[test]
+    fn shared_str_static_and_owned_are_equal() {
+        let a = SharedStr::from("/user/{id}");
+        let b = SharedStr::from(String::from("/user/{id}"));
+        let c = SharedStr::from(Arc::<str>::from("/user/{id}"));
+        assert_eq!(a, b);
+        assert_eq!(b, c);
+        assert_eq!(a, "/user/{id}");
+    }
+
+    #[test]
+    fn shared_str_slice() {
+        let a = SharedStr::from("/user/{id}");
+        assert!(matches!(a.slice(1..5), SharedStr::Static("user")));
+        let b = SharedStr::from(String::from("/user/{id}"));
+        assert_eq!(b.slice(7..9), "id");
+    }
+
+    #[test]
+    fn shared_str_lookup_by_str() {
+        let mut map = HashMap::new();
+        map.insert(SharedStr::from(String::from("key")), 1);
+        assert_eq!(map.get("key"), Some(&1));
+    }
+}
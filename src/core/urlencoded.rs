@@ -0,0 +1,247 @@
+use crate::core::response::{IntoResponse, Response, StatusCode};
+use anyhow::anyhow;
+use anyhow::Result;
+
+/// What HTML forms send by default, the same encoding as a query string.
+pub const FORM_MEDIA_TYPE: &str = "application/x-www-form-urlencoded";
+
+/// Decodes the `%XX` escapes of a path segment.
+pub fn percent_decode(s: &str) -> Result<String> {
+    return decode_with(s, false);
+}
+
+/// Decodes a query or form component, where `+` also stands for a space.
+pub fn decode(s: &str) -> Result<String> {
+    return decode_with(s, true);
+}
+
+/// Escapes everything but the unreserved characters, so the result is safe as a path segment.
+pub fn percent_encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for byte in s.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    return encoded;
+}
+
+/// Splits `a=1&b=2` into decoded pairs, in order. A key without `=` gets an empty value.
+pub fn parse(s: &str) -> Result<Vec<(String, String)>> {
+    return s
+        .split('&')
+        .filter(|pair| return !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            return Ok((decode(key)?, decode(value)?));
+        })
+        .collect();
+}
+
+/// Decoded pairs of a query or form in the order they came, a key may come more than once, as for
+/// checkboxes sharing a name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Params(Vec<(String, String)>);
+
+impl Params {
+    pub fn parse(s: &str) -> Result<Params> {
+        return Ok(Params(parse(s)?));
+    }
+
+    /// The first value of `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        return self
+            .0
+            .iter()
+            .find(|(k, _)| return k == key)
+            .map(|(_, value)| return value.as_str());
+    }
+
+    /// Every value of `key`, in order.
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> {
+        return self
+            .0
+            .iter()
+            .filter(move |(k, _)| return k == key)
+            .map(|(_, value)| return value.as_str());
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        return self.get(key).is_some();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        return self
+            .0
+            .iter()
+            .map(|(key, value)| return (key.as_str(), value.as_str()));
+    }
+
+    pub fn len(&self) -> usize {
+        return self.0.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.0.is_empty();
+    }
+
+    pub fn into_pairs(self) -> Vec<(String, String)> {
+        return self.0;
+    }
+}
+
+impl From<Vec<(String, String)>> for Params {
+    fn from(pairs: Vec<(String, String)>) -> Params {
+        return Params(pairs);
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FormError {
+    #[error("Unsupported content type {0}, expected {FORM_MEDIA_TYPE}")]
+    UnsupportedMediaType(String),
+    #[error("Invalid form: {0}")]
+    Invalid(anyhow::Error),
+}
+
+impl FormError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            FormError::UnsupportedMediaType(_) => return StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            FormError::Invalid(_) => return StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl IntoResponse for FormError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        return (status, self.to_string()).into_response();
+    }
+}
+
+/// Decodes a form body, which is only taken as one when `content_type` says so.
+pub fn parse_form(content_type: Option<&str>, body: &[u8]) -> Result<Params, FormError> {
+    match content_type {
+        Some(content_type) => {
+            let media_type = content_type.split(';').next().unwrap_or("").trim();
+            if !media_type.eq_ignore_ascii_case(FORM_MEDIA_TYPE) {
+                return Err(FormError::UnsupportedMediaType(content_type.to_string()));
+            }
+        }
+        // nothing to mistake for a form
+        None if body.is_empty() => return Ok(Params::default()),
+        None => return Err(FormError::UnsupportedMediaType("(none)".to_string())),
+    }
+    // anything outside of ASCII is escaped, raw bytes are not a form
+    let Ok(body) = std::str::from_utf8(body) else {
+        return Err(FormError::Invalid(anyhow!("Body is not UTF-8")));
+    };
+    return Params::parse(body).map_err(FormError::Invalid);
+}
+
+fn decode_with(s: &str, plus_as_space: bool) -> Result<String> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let Some(byte) = bytes
+                    .get(i + 1..i + 3)
+                    .and_then(|hex| return std::str::from_utf8(hex).ok())
+                    .and_then(|hex| return u8::from_str_radix(hex, 16).ok())
+                else {
+                    return Err(anyhow!("Invalid percent escape in {}", s));
+                };
+                decoded.push(byte);
+                i += 3;
+            }
+            b'+' if plus_as_space => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            byte => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    let Ok(decoded) = String::from_utf8(decoded) else {
+        return Err(anyhow!("Percent escapes in {} are not valid UTF-8", s));
+    };
+    return Ok(decoded);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::response::StatusCode;
+    use crate::core::urlencoded::{
+        decode, parse, parse_form, percent_decode, percent_encode, Params,
+    };
+
+    #[test]
+    fn urlencoded_decode() {
+        assert_eq!(decode("a+b%20c%2Fd").unwrap(), "a b c/d");
+        assert_eq!(percent_decode("a+b%20c").unwrap(), "a+b c");
+        assert_eq!(decode("caf%C3%A9").unwrap(), "café");
+        assert!(decode("%zz").is_err());
+        assert!(decode("%4").is_err());
+        assert!(decode("%ff").is_err());
+    }
+
+    #[test]
+    fn urlencoded_percent_encode() {
+        assert_eq!(percent_encode("a-b_c.d~"), "a-b_c.d~");
+        assert_eq!(percent_encode("a b/c?é"), "a%20b%2Fc%3F%C3%A9");
+        assert_eq!(percent_decode(&percent_encode("x/y z")).unwrap(), "x/y z");
+    }
+
+    #[test]
+    fn urlencoded_parse() {
+        let pairs = parse("q=rust+lang&page=2&flag&&empty=").unwrap();
+        let expected = [
+            ("q", "rust lang"),
+            ("page", "2"),
+            ("flag", ""),
+            ("empty", ""),
+        ];
+        let expected = expected
+            .iter()
+            .map(|(k, v)| return (k.to_string(), v.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(pairs, expected);
+        assert!(parse("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn urlencoded_params() {
+        let params = Params::parse("tag=a&name=x+y&tag=b%26c&tag").unwrap();
+        assert_eq!(params.get("name"), Some("x y"));
+        assert_eq!(params.get("tag"), Some("a"));
+        assert_eq!(params.get_all("tag").collect::<Vec<_>>(), ["a", "b&c", ""]);
+        assert_eq!(params.get("missing"), None);
+        assert!(!params.contains_key("Name"));
+        assert_eq!(params.len(), 4);
+    }
+
+    #[test]
+    fn urlencoded_forms() {
+        let content_type = Some("application/x-www-form-urlencoded; charset=UTF-8");
+        let form = parse_form(content_type, b"a=1&b=%C3%A9").unwrap();
+        assert_eq!(form.get("b"), Some("\u{e9}"));
+        assert!(parse_form(None, b"").unwrap().is_empty());
+
+        let mismatched = parse_form(Some("application/json"), b"{}").unwrap_err();
+        assert_eq!(mismatched.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let untyped = parse_form(None, b"a=1").unwrap_err();
+        assert_eq!(untyped.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let invalid = parse_form(content_type, b"a=%zz").unwrap_err();
+        assert_eq!(invalid.status(), StatusCode::BAD_REQUEST);
+        let binary = parse_form(content_type, b"a=\xff").unwrap_err();
+        assert_eq!(binary.status(), StatusCode::BAD_REQUEST);
+    }
+}
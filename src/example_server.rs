@@ -1,3 +1,12 @@
 pub mod context;
 pub mod file_handler;
+pub mod file_index;
+pub mod memory_storage;
+pub mod middleware;
+pub mod name_locks;
 pub mod routes;
+pub mod static_files;
+pub mod storage;
+#[cfg(test)]
+pub mod test_support;
+pub mod uploads;
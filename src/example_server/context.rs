@@ -1,13 +1,90 @@
+use crate::core::route_names::RouteNames;
 use crate::example_server::file_handler::FileHandler;
+use crate::example_server::memory_storage::MemoryStorage;
+use crate::example_server::name_locks::NameLocks;
+use crate::example_server::storage::Storage;
+use crate::example_server::uploads::{Uploads, DEFAULT_EXPIRY};
 use std::env;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 #[derive(Debug)]
 pub struct ServerContext {
-    pub file_handler: FileHandler,
+    // the directory given on the command line, or memory when testing
+    pub storage: Box<dyn Storage>,
+    // per file, held from checking the preconditions of a write to `storage` until it is done, so
+    // two writes cannot both pass an `If-Match` for the same version
+    pub writes: NameLocks,
+    // resumable uploads into `storage`
+    pub uploads: Uploads,
+    // shared with the router, for linking to named routes
+    pub route_names: Arc<RouteNames>,
 }
 
 pub fn get_context() -> ServerContext {
+    let args: Vec<String> = env::args().collect();
+    // files can be asked for by name alone, wherever they are below the directory
+    const INDEX_FLAG: &str = "--index-files";
+    // `/files` keeps what is posted in memory and leaves the directory alone
+    const MEMORY_FLAG: &str = "--in-memory";
+
+    let storage: Box<dyn Storage> = if args.iter().any(|arg| return arg == MEMORY_FLAG) {
+        Box::new(MemoryStorage::new())
+    } else {
+        let mut file_handler = FileHandler::new(base_directory()).unwrap();
+        if args.iter().any(|arg| return arg == INDEX_FLAG) {
+            file_handler.enable_index();
+        }
+        Box::new(file_handler)
+    };
+
+    // uploads abandoned while the server was down
+    let uploads = Uploads::new(DEFAULT_EXPIRY);
+    uploads.sweep(storage.as_ref());
+
+    return ServerContext {
+        storage,
+        writes: NameLocks::new(),
+        uploads,
+        route_names: Arc::new(RouteNames::new()),
+    };
+}
+
+/// Whether `/static` lists directories without an index file, `--autoindex`. Off unless asked
+/// for, the directory is the one `/files` and `/uploads` write to.
+pub fn autoindex() -> bool {
+    return env::args().any(|arg| return arg == "--autoindex");
+}
+
+/// A directory of pages for `/static`, `--static-directory`. Without one `/static` offers the
+/// files of the directory `/files` writes to, as downloads only.
+pub fn static_directory() -> Option<PathBuf> {
+    let args: Vec<String> = env::args().collect();
+    const STATIC_DIR_FLAG: &str = "--static-directory";
+
+    let dir = args
+        .iter()
+        .position(|arg| return arg == STATIC_DIR_FLAG)
+        .and_then(|i| return args.get(i + 1));
+    return dir.map(PathBuf::from);
+}
+
+/// The file `/static` serves for a directory of pages, `--index-file` or `index.html`.
+pub fn index_file() -> String {
+    let args: Vec<String> = env::args().collect();
+    const INDEX_FILE_FLAG: &str = "--index-file";
+
+    let name = args
+        .iter()
+        .position(|arg| return arg == INDEX_FILE_FLAG)
+        .and_then(|i| return args.get(i + 1));
+    return name
+        .cloned()
+        .unwrap_or_else(|| return String::from("index.html"));
+}
+
+/// The directory files are served from, `--directory` or the working directory.
+pub fn base_directory() -> PathBuf {
     let args: Vec<String> = env::args().collect();
     const DIR_FLAG: &str = "--directory";
 
@@ -24,8 +101,5 @@ pub fn get_context() -> ServerContext {
             base = PathBuf::from(args[i + 1].clone());
         }
     }
-
-    return ServerContext {
-        file_handler: FileHandler::new(base).unwrap(),
-    };
+    return base;
 }
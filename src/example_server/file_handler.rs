@@ -1,65 +1,425 @@
 #![allow(unused)]
 
-use std::fs::File;
-use std::io::{Read, Write};
-use std::path::PathBuf;
-use anyhow::{Result, anyhow};
+use crate::core::safe_path::{PathError, SafeRoot, SymlinkPolicy};
+use crate::example_server::file_index::FileIndex;
+use crate::example_server::storage::{Entry, Opened, Stat, Storage, Written};
+use anyhow::{anyhow, Result};
+use std::fs::{self, File, Metadata, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
 
+// tells apart the temporary files of writes running at the same time
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
 
+/// The local file system as `Storage`, everything below one directory.
 #[derive(Debug)]
 pub struct FileHandler {
-    base_dir: PathBuf,
+    // every path handed out is resolved through it, names come straight from request paths
+    root: SafeRoot,
+    // only for looking files up by name, see `enable_index`
+    index: Option<Arc<RwLock<FileIndex>>>,
 }
 
 impl FileHandler {
     pub fn new(path: PathBuf) -> Result<FileHandler> {
+        return FileHandler::with_symlinks(path, SymlinkPolicy::default());
+    }
+
+    pub fn with_symlinks(path: PathBuf, symlinks: SymlinkPolicy) -> Result<FileHandler> {
         if !path.is_absolute() {
             return Err(anyhow!("path is not absolute"));
         }
-        return Ok(FileHandler { base_dir: path });
-    }
-
-    pub fn search(&self, file: &str) -> Option<PathBuf> {
-        let mut bfs: Vec<PathBuf> = vec![self.base_dir.clone()];
-
-        while let Some(curr) = bfs.pop() {
-            if let Ok(entries) = curr.read_dir() {
-                for entry in entries.flatten() {
-                    let next = entry.path();
-                    if next.is_dir() {
-                        bfs.push(next);
-                        continue;
-                    }
-                    if next.ends_with(file) {
-                        return Some(next);
-                    }
+        return Ok(FileHandler {
+            root: SafeRoot::new(path, symlinks)?,
+            index: None,
+        });
+    }
+
+    /// Looks names up in an index of the whole directory as well, a file named `a.txt` is found
+    /// wherever it is. Direct paths still win over the index. Writes through the handler update
+    /// the index right away, changes made around the server show up with the next rebuild.
+    pub fn enable_index(&mut self) {
+        let index = Arc::new(RwLock::new(FileIndex::build(self.root.base())));
+        let base = self.root.base().to_path_buf();
+        FileIndex::refresh_every(Arc::downgrade(&index), base, FileIndex::REBUILD_INTERVAL);
+        self.index = Some(index);
+    }
+
+    fn find_indexed(
+        &self,
+        index: &RwLock<FileIndex>,
+        file: &str,
+    ) -> Result<Option<String>, PathError> {
+        let index = index.read().unwrap_or_else(|e| return e.into_inner());
+        // a candidate deleted or replaced by a link around the server stays indexed until the
+        // next rebuild, the ones after it are tried all the same
+        let mut refused = None;
+        for relative in index.candidates(file) {
+            // names are handed back to `Storage` methods, which take them as UTF-8
+            let Some(name) = relative.to_str() else {
+                continue;
+            };
+            match self.root.resolve(name) {
+                Ok(path) if path.is_file() => return Ok(Some(name.to_string())),
+                Ok(_) => {}
+                Err(e) => {
+                    refused.get_or_insert(e);
                 }
             }
         }
+        return refused.map_or(Ok(None), Err);
+    }
+
+    fn update_index(&self, file: &Path, update: impl FnOnce(&mut FileIndex, &Path)) {
+        if let (Some(index), Ok(relative)) = (&self.index, file.strip_prefix(self.root.base())) {
+            let mut index = index.write().unwrap_or_else(|e| return e.into_inner());
+            update(&mut index, relative);
+        }
+    }
+
+    pub fn base(&self) -> &Path {
+        return self.root.base();
+    }
+
+    pub fn get_path(&self, name: &str) -> Result<PathBuf, PathError> {
+        return self.root.resolve(name);
+    }
+}
+
+impl Storage for FileHandler {
+    fn stat(&self, name: &str) -> Result<Option<Stat>, PathError> {
+        let path = self.root.resolve(name)?;
+        match fs::metadata(path) {
+            Ok(metadata) => return Ok(Some(stat(&metadata))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(PathError::Io(e)),
+        }
+    }
 
-        return None;
+    fn read_range(&self, name: &str, start: u64, len: u64) -> Result<Vec<u8>, PathError> {
+        let mut file = File::open(self.root.resolve(name)?)?;
+        file.seek(SeekFrom::Start(start))?;
+        let mut buffer = Vec::new();
+        file.take(len).read_to_end(&mut buffer)?;
+        return Ok(buffer);
     }
 
-    pub fn read(&self, file: PathBuf) -> Vec<u8> {
-        let mut file_buffer: Vec<u8> = Vec::new();
-        if let Ok(mut file) = File::open(file) {
-            let _ = file.read_to_end(&mut file_buffer);
+    // files are only ever replaced by renaming another one over them, the open one stays as it is
+    fn open(&self, name: &str) -> Result<Option<Opened>, PathError> {
+        let file = match File::open(self.root.resolve(name)?) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(PathError::Io(e)),
+        };
+        let metadata = file.metadata()?;
+        if metadata.is_dir() {
+            return Ok(None);
         }
-        return file_buffer;
+        return Ok(Some(Opened {
+            stat: stat(&metadata),
+            source: Box::new(file),
+        }));
     }
 
-    pub fn write(&self, file: PathBuf, data: &[u8]) -> Result<usize> {
-        let mut file = match File::create(file) {
-            Err(_) => return Err(anyhow!("Unable to create file")),
-            Ok(f) => f,
+    /// The data goes to a temporary file next to the target that is synced and then renamed over
+    /// it, so a crash only ever leaves the old or the new content, and of concurrent writers the
+    /// last one wins whole.
+    fn write(&self, name: &str, data: &[u8]) -> Result<Written, PathError> {
+        let file = self.root.resolve(name)?;
+        let (Some(dir), Some(file_name)) = (file.parent(), file.file_name()) else {
+            return Err(PathError::Escapes(name.to_string()));
         };
-        match file.write_all(data) {
-            Ok(()) => return Ok(data.len()),
-            Err(_e) => return Err(anyhow!("Unable to write to file")),
+        // a dot file, which no client can reach, see `storage::is_hidden`
+        let temp = dir.join(format!(
+            ".{}.{}-{}.tmp",
+            file_name.to_string_lossy(),
+            process::id(),
+            TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let written = write_synced(&temp, data).and_then(|()| return move_into_place(&temp, &file));
+        let written = match written {
+            Ok(written) => written,
+            Err(e) => {
+                let _ = fs::remove_file(&temp);
+                return Err(PathError::Io(e));
+            }
+        };
+        // the rename itself is only durable once the directory is, not every platform can sync one
+        if let Ok(dir) = File::open(dir) {
+            let _ = dir.sync_all();
+        }
+        self.update_index(&file, |index, relative| {
+            return index.insert(relative.to_path_buf());
+        });
+        return Ok(written);
+    }
+
+    fn append(&self, name: &str, data: &[u8]) -> Result<u64, PathError> {
+        let file = self.root.resolve(name)?;
+        let mut handle = OpenOptions::new().append(true).create(true).open(&file)?;
+        handle.write_all(data)?;
+        handle.sync_data()?;
+        return Ok(handle.metadata()?.len());
+    }
+
+    fn rename(&self, from: &str, to: &str) -> Result<Written, PathError> {
+        let from = self.root.resolve(from)?;
+        let to = self.root.resolve(to)?;
+        let written = move_into_place(&from, &to)?;
+        if let Some(Ok(dir)) = to.parent().map(File::open) {
+            let _ = dir.sync_all();
         }
+        self.update_index(&from, |index, relative| return index.remove(relative));
+        self.update_index(&to, |index, relative| {
+            return index.insert(relative.to_path_buf());
+        });
+        return Ok(written);
     }
 
-    pub fn get_path(&self, name: &str) -> PathBuf {
-        return self.base_dir.join(name);
+    fn delete(&self, name: &str) -> Result<bool, PathError> {
+        let file = self.root.resolve(name)?;
+        if !file.is_file() {
+            return Ok(false);
+        }
+        match fs::remove_file(&file) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(PathError::Io(e)),
+            Ok(()) => {}
+        }
+        self.update_index(&file, |index, relative| return index.remove(relative));
+        return Ok(true);
+    }
+
+    /// Leaves out what `resolve` would refuse, such as links under the default policy.
+    fn list(&self, dir: &str) -> Result<Vec<Entry>, PathError> {
+        let path = self.root.resolve(dir)?;
+        let mut entries = path
+            .read_dir()?
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().to_str()?.to_string();
+                let path = self.root.check(&entry.path()).ok()?;
+                let metadata = fs::metadata(path).ok()?;
+                return Some(Entry {
+                    name,
+                    stat: stat(&metadata),
+                });
+            })
+            .collect::<Vec<_>>();
+        entries.sort_by(|a, b| return a.name.cmp(&b.name));
+        return Ok(entries);
+    }
+
+    /// The direct path first, then the index if it is enabled.
+    fn find(&self, file: &str) -> Result<Option<String>, PathError> {
+        if self.root.resolve(file)?.is_file() {
+            return Ok(Some(file.to_string()));
+        }
+        let Some(index) = &self.index else {
+            return Ok(None);
+        };
+        // a miss does not rebuild the index, misses are cheap to cause from outside
+        return self.find_indexed(index, file);
+    }
+}
+
+fn stat(metadata: &Metadata) -> Stat {
+    return Stat {
+        len: metadata.len(),
+        modified: metadata.modified().ok(),
+        is_dir: metadata.is_dir(),
+    };
+}
+
+// a link is only made where there was no file, which tells a created file from a replaced one
+// even with other writers around. Where links are not supported the rename has to decide alone
+fn move_into_place(from: &Path, to: &Path) -> io::Result<Written> {
+    match fs::hard_link(from, to) {
+        Ok(()) => {
+            // the file is in place already, a left over dot file is not worth failing for
+            let _ = fs::remove_file(from);
+            return Ok(Written::Created);
+        }
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            fs::rename(from, to)?;
+            return Ok(Written::Replaced);
+        }
+        Err(_) => {
+            let existed = to.is_file();
+            fs::rename(from, to)?;
+            if existed {
+                return Ok(Written::Replaced);
+            }
+            return Ok(Written::Created);
+        }
+    }
+}
+
+fn write_synced(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut handle = OpenOptions::new().write(true).create_new(true).open(path)?;
+    handle.write_all(data)?;
+    return handle.sync_all();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::safe_path::PathError;
+    use crate::example_server::file_handler::FileHandler;
+    use crate::example_server::storage::{Storage, Written};
+    use std::fs;
+    use std::io::Read;
+    use std::path::PathBuf;
+
+    // a fresh directory per test
+    fn scratch(name: &str) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("file_handler_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        return root;
+    }
+
+    fn names(root: &PathBuf) -> Vec<String> {
+        let mut names = fs::read_dir(root)
+            .unwrap()
+            .map(|entry| return entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        names.sort();
+        return names;
+    }
+
+    #[test]
+    fn file_handler_writes() {
+        let root = scratch("writes");
+        let handler = FileHandler::new(root.clone()).unwrap();
+        assert_eq!(handler.write("a.txt", b"one").unwrap(), Written::Created);
+        assert_eq!(handler.write("a.txt", b"two").unwrap(), Written::Replaced);
+        assert_eq!(fs::read(root.join("a.txt")).unwrap(), b"two");
+        // the temporary file is gone either way
+        assert_eq!(names(&root), vec!["a.txt"]);
+        assert!(handler.write("missing/a.txt", b"x").is_err());
+        assert_eq!(names(&root), vec!["a.txt"]);
+
+        // of writers creating the same file only one created it
+        let created = std::thread::scope(|scope| {
+            let writers = (0..8)
+                .map(|n| {
+                    let handler = &handler;
+                    return scope.spawn(move || {
+                        return handler.write("b.txt", &[n]).unwrap();
+                    });
+                })
+                .collect::<Vec<_>>();
+            return writers
+                .into_iter()
+                .map(|writer| return writer.join().unwrap())
+                .filter(|written| return *written == Written::Created)
+                .count();
+        });
+        assert_eq!(created, 1);
+        assert_eq!(names(&root), vec!["a.txt", "b.txt"]);
+
+        assert_eq!(handler.append("c.txt", b"ab").unwrap(), 2);
+        assert_eq!(handler.append("c.txt", b"cd").unwrap(), 4);
+        assert_eq!(handler.rename("c.txt", "d.txt").unwrap(), Written::Created);
+        assert_eq!(handler.rename("d.txt", "a.txt").unwrap(), Written::Replaced);
+        assert_eq!(fs::read(root.join("a.txt")).unwrap(), b"abcd");
+        assert_eq!(names(&root), vec!["a.txt", "b.txt"]);
+
+        assert!(handler.delete("a.txt").unwrap());
+        assert!(!handler.delete("a.txt").unwrap());
+        assert_eq!(handler.stat("a.txt").unwrap(), None);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn file_handler_reads() {
+        let root = scratch("reads");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("sub/a.txt"), "0123456789").unwrap();
+        let handler = FileHandler::new(root.clone()).unwrap();
+        assert_eq!(handler.read_range("sub/a.txt", 2, 3).unwrap(), b"234");
+        assert_eq!(handler.read_range("sub/a.txt", 8, 10).unwrap(), b"89");
+        assert_eq!(handler.stat("sub/a.txt").unwrap().unwrap().len, 10);
+        assert!(handler.stat("sub").unwrap().unwrap().is_dir);
+        assert_eq!(handler.stat("sub/b.txt").unwrap(), None);
+        let entries = handler.list("").unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "sub");
+
+        // an open file keeps reading the version it was opened at
+        let mut opened = handler.open("sub/a.txt").unwrap().unwrap();
+        assert_eq!(opened.stat.len, 10);
+        handler.write("sub/a.txt", b"replaced").unwrap();
+        let mut read = String::new();
+        opened.source.read_to_string(&mut read).unwrap();
+        assert_eq!(read, "0123456789");
+        assert!(handler.open("sub").unwrap().is_none());
+        assert!(handler.open("sub/b.txt").unwrap().is_none());
+        assert!(handler.open("../x").is_err());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn file_handler_find_indexed() {
+        let root = scratch("find");
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("a/x.txt"), "a").unwrap();
+        fs::write(root.join("a/b/x.txt"), "b").unwrap();
+        let mut handler = FileHandler::new(root.clone()).unwrap();
+        assert_eq!(handler.find("x.txt").unwrap(), None);
+        handler.enable_index();
+
+        assert_eq!(handler.find("x.txt").unwrap().as_deref(), Some("a/x.txt"));
+        assert_eq!(
+            handler.find("b/x.txt").unwrap().as_deref(),
+            Some("a/b/x.txt")
+        );
+        // deleted around the server, the next candidate is found without a rebuild
+        fs::remove_file(root.join("a/x.txt")).unwrap();
+        assert_eq!(handler.find("x.txt").unwrap().as_deref(), Some("a/b/x.txt"));
+
+        // a miss does not walk the directory, writes through the handler are indexed at once
+        fs::write(root.join("a/y.txt"), "y").unwrap();
+        assert_eq!(handler.find("y.txt").unwrap(), None);
+        handler.write("a/b/z.txt", b"z").unwrap();
+        assert_eq!(handler.find("z.txt").unwrap().as_deref(), Some("a/b/z.txt"));
+        handler.delete("a/b/z.txt").unwrap();
+        assert_eq!(handler.find("z.txt").unwrap(), None);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn file_handler_find_indexed_past_links() {
+        use std::os::unix::fs::symlink;
+
+        let root = scratch("links");
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("a/x.txt"), "a").unwrap();
+        fs::write(root.join("a/b/x.txt"), "b").unwrap();
+        // links are not indexed, whether to a file or to a directory
+        let outside = scratch("links_outside");
+        fs::write(outside.join("z.txt"), "z").unwrap();
+        fs::create_dir(root.join("l")).unwrap();
+        symlink(root.join("a/b/x.txt"), root.join("l/y.txt")).unwrap();
+        symlink(&outside, root.join("l/out")).unwrap();
+        let mut handler = FileHandler::new(root.clone()).unwrap();
+        handler.enable_index();
+        assert_eq!(handler.find("y.txt").unwrap(), None);
+        assert_eq!(handler.find("z.txt").unwrap(), None);
+        fs::remove_dir_all(outside).unwrap();
+
+        // the first candidate turned into a link the policy refuses, the second is found
+        fs::remove_file(root.join("a/x.txt")).unwrap();
+        symlink(root.join("a/b/x.txt"), root.join("a/x.txt")).unwrap();
+        assert_eq!(handler.find("x.txt").unwrap().as_deref(), Some("a/b/x.txt"));
+        // with none left the refusal is the answer
+        fs::remove_file(root.join("a/b/x.txt")).unwrap();
+        assert!(matches!(handler.find("x.txt"), Err(PathError::Symlink(_))));
+        fs::remove_dir_all(root).unwrap();
     }
 }
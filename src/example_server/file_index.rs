@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::{RwLock, Weak};
+use std::thread;
+use std::time::Duration;
+
+/// Files below a directory by file name, for finding a file without knowing where it is.
+///
+/// Candidates for a name are kept shallowest first, then in path order, so the same request
+/// always finds the same file whatever order the directory is read in.
+#[derive(Debug)]
+pub struct FileIndex {
+    // relative to the indexed directory
+    by_name: HashMap<OsString, Vec<PathBuf>>,
+    // counts inserts and removals, a rebuild that missed one is thrown away
+    revision: u64,
+}
+
+impl FileIndex {
+    // how often the directory is walked again for the changes made around the server
+    pub const REBUILD_INTERVAL: Duration = Duration::from_secs(30);
+
+    /// Walks `base`. Links are left out whatever the symlink policy, a file behind one is only
+    /// found by its own path.
+    pub fn build(base: &Path) -> FileIndex {
+        let mut index = FileIndex {
+            by_name: HashMap::new(),
+            revision: 0,
+        };
+        let mut pending = vec![PathBuf::new()];
+        while let Some(dir) = pending.pop() {
+            let Ok(entries) = base.join(&dir).read_dir() else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let relative = dir.join(entry.file_name());
+                // the type of the entry itself, a link is not followed
+                let Ok(file_type) = entry.file_type() else {
+                    continue;
+                };
+                if file_type.is_dir() {
+                    pending.push(relative);
+                } else if file_type.is_file() {
+                    index.insert(relative);
+                }
+            }
+        }
+        return index;
+    }
+
+    pub fn insert(&mut self, relative: PathBuf) {
+        let Some(name) = relative.file_name() else {
+            return;
+        };
+        self.revision += 1;
+        let candidates = self.by_name.entry(name.to_os_string()).or_default();
+        let key = |p: &PathBuf| return (p.components().count(), p.clone());
+        if let Err(idx) = candidates.binary_search_by_key(&key(&relative), key) {
+            candidates.insert(idx, relative);
+        }
+    }
+
+    pub fn remove(&mut self, relative: &Path) {
+        let Some(name) = relative.file_name() else {
+            return;
+        };
+        self.revision += 1;
+        if let Some(candidates) = self.by_name.get_mut(name) {
+            candidates.retain(|candidate| return candidate != relative);
+        }
+    }
+
+    /// The indexed files whose path ends with the components of `name`, in lookup order.
+    pub fn candidates<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Path> {
+        let candidates = Path::new(name)
+            .file_name()
+            .and_then(|file_name| return self.by_name.get(file_name));
+        return candidates
+            .into_iter()
+            .flatten()
+            .filter(move |candidate| return candidate.ends_with(name))
+            .map(PathBuf::as_path);
+    }
+
+    /// Walks `base` again and swaps the result in, unless a write changed the index meanwhile.
+    /// The walk runs without the lock, lookups go on while it does.
+    pub fn rebuild(index: &RwLock<FileIndex>, base: &Path) -> bool {
+        let revision = index
+            .read()
+            .unwrap_or_else(|e| return e.into_inner())
+            .revision;
+        let rebuilt = FileIndex::build(base);
+        let mut index = index.write().unwrap_or_else(|e| return e.into_inner());
+        if index.revision != revision {
+            return false;
+        }
+        *index = FileIndex {
+            revision: revision + 1,
+            ..rebuilt
+        };
+        return true;
+    }
+
+    /// Rebuilds the index every `interval` until it is dropped.
+    pub fn refresh_every(index: Weak<RwLock<FileIndex>>, base: PathBuf, interval: Duration) {
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            let Some(index) = index.upgrade() else {
+                return;
+            };
+            FileIndex::rebuild(&index, &base);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::example_server::file_index::FileIndex;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::sync::{Arc, RwLock};
+    use std::time::Duration;
+
+    // a fresh directory per test holding a.txt, sub/a.txt, sub/deeper/a.txt and sub/b.txt
+    fn layout(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("file_index_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("sub/deeper")).unwrap();
+        for file in ["a.txt", "sub/a.txt", "sub/deeper/a.txt", "sub/b.txt"] {
+            fs::write(root.join(file), file).unwrap();
+        }
+        return root;
+    }
+
+    fn candidates(index: &FileIndex, name: &str) -> Vec<PathBuf> {
+        return index.candidates(name).map(Path::to_path_buf).collect();
+    }
+
+    fn find(index: &FileIndex, name: &str) -> Option<PathBuf> {
+        return index.candidates(name).next().map(Path::to_path_buf);
+    }
+
+    #[test]
+    fn file_index_build_and_find() {
+        let root = layout("build");
+        let index = FileIndex::build(&root);
+        let all = ["a.txt", "sub/a.txt", "sub/deeper/a.txt"].map(PathBuf::from);
+        assert_eq!(candidates(&index, "a.txt"), all);
+        assert_eq!(find(&index, "a.txt"), Some(PathBuf::from("a.txt")));
+        assert_eq!(
+            find(&index, "deeper/a.txt"),
+            Some(PathBuf::from("sub/deeper/a.txt"))
+        );
+        assert_eq!(find(&index, "b.txt"), Some(PathBuf::from("sub/b.txt")));
+        // the components have to match whole
+        assert_eq!(find(&index, "r/a.txt"), None);
+        assert_eq!(find(&index, "missing.txt"), None);
+        assert_eq!(find(&index, ""), None);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn file_index_insert_and_remove() {
+        let root = layout("insert");
+        let mut index = FileIndex::build(&root);
+        // shallowest first, then in path order, whatever order they come in
+        index.insert(PathBuf::from("z/c.txt"));
+        index.insert(PathBuf::from("c.txt"));
+        index.insert(PathBuf::from("b/c.txt"));
+        index.insert(PathBuf::from("c.txt"));
+        let expected = ["c.txt", "b/c.txt", "z/c.txt"].map(PathBuf::from);
+        assert_eq!(candidates(&index, "c.txt"), expected);
+
+        index.remove(Path::new("a.txt"));
+        assert_eq!(find(&index, "a.txt"), Some(PathBuf::from("sub/a.txt")));
+        index.remove(Path::new("never/indexed.txt"));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn file_index_rebuild() {
+        let root = layout("rebuild");
+        let index = RwLock::new(FileIndex::build(&root));
+        fs::write(root.join("sub/new.txt"), "new").unwrap();
+        fs::remove_file(root.join("a.txt")).unwrap();
+        assert_eq!(find(&index.read().unwrap(), "new.txt"), None);
+
+        assert!(FileIndex::rebuild(&index, &root));
+        let rebuilt = index.read().unwrap();
+        assert_eq!(
+            find(&rebuilt, "new.txt"),
+            Some(PathBuf::from("sub/new.txt"))
+        );
+        assert_eq!(find(&rebuilt, "a.txt"), Some(PathBuf::from("sub/a.txt")));
+        drop(rebuilt);
+
+        // the timer stops with the index
+        let shared = Arc::new(index);
+        FileIndex::refresh_every(Arc::downgrade(&shared), root.clone(), Duration::ZERO);
+        fs::write(root.join("later.txt"), "later").unwrap();
+        while find(&shared.read().unwrap(), "later.txt").is_none() {
+            std::thread::yield_now();
+        }
+        drop(shared);
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,268 @@
+use crate::core::safe_path::{self, PathError};
+use crate::example_server::storage::{Entry, Opened, Stat, Storage, Written};
+use std::collections::BTreeMap;
+use std::io::{self, Cursor};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+/// `Storage` kept in memory, for tests and anything that should not outlive the process.
+///
+/// Only files are stored, directories are whatever the names of files pass through.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    // by normalized name, sorted so the files of a directory are next to each other
+    files: RwLock<BTreeMap<String, MemoryFile>>,
+}
+
+#[derive(Debug)]
+struct MemoryFile {
+    // shared with the readers that opened it, changed on a copy while they hold it
+    data: Arc<Vec<u8>>,
+    modified: SystemTime,
+}
+
+impl MemoryFile {
+    fn new(data: &[u8]) -> MemoryFile {
+        return MemoryFile {
+            data: Arc::new(data.to_vec()),
+            modified: SystemTime::now(),
+        };
+    }
+
+    fn stat(&self) -> Stat {
+        return Stat {
+            len: self.data.len() as u64,
+            modified: Some(self.modified),
+            is_dir: false,
+        };
+    }
+}
+
+// the content of a file when it was opened, for reading it through a `Cursor`
+struct Snapshot(Arc<Vec<u8>>);
+
+impl AsRef<[u8]> for Snapshot {
+    fn as_ref(&self) -> &[u8] {
+        return &self.0;
+    }
+}
+
+impl MemoryStorage {
+    pub fn new() -> MemoryStorage {
+        return MemoryStorage::default();
+    }
+
+    fn is_dir(files: &BTreeMap<String, MemoryFile>, name: &str) -> bool {
+        if name.is_empty() {
+            return true;
+        }
+        let prefix = format!("{}/", name);
+        return files
+            .range(prefix.clone()..)
+            .next()
+            .is_some_and(|(key, _)| return key.starts_with(&prefix));
+    }
+
+    fn insert(
+        files: &mut BTreeMap<String, MemoryFile>,
+        name: String,
+        file: MemoryFile,
+    ) -> Result<Written, PathError> {
+        if name.is_empty() || MemoryStorage::is_dir(files, &name) {
+            return Err(PathError::Io(io::Error::other("is a directory")));
+        }
+        // a file cannot also be a directory on the way to another one
+        let mut parent = name.as_str();
+        while let Some((dir, _)) = parent.rsplit_once('/') {
+            if files.contains_key(dir) {
+                return Err(PathError::Io(io::Error::other("not a directory")));
+            }
+            parent = dir;
+        }
+        match files.insert(name, file) {
+            Some(_) => return Ok(Written::Replaced),
+            None => return Ok(Written::Created),
+        }
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn stat(&self, name: &str) -> Result<Option<Stat>, PathError> {
+        let name = normalize(name)?;
+        let files = self.files.read().unwrap_or_else(|e| return e.into_inner());
+        if let Some(file) = files.get(&name) {
+            return Ok(Some(file.stat()));
+        }
+        if MemoryStorage::is_dir(&files, &name) {
+            return Ok(Some(directory()));
+        }
+        return Ok(None);
+    }
+
+    fn read_range(&self, name: &str, start: u64, len: u64) -> Result<Vec<u8>, PathError> {
+        let name = normalize(name)?;
+        let files = self.files.read().unwrap_or_else(|e| return e.into_inner());
+        let Some(file) = files.get(&name) else {
+            return Err(PathError::Io(io::ErrorKind::NotFound.into()));
+        };
+        let start = (start as usize).min(file.data.len());
+        let end = start.saturating_add(len as usize).min(file.data.len());
+        return Ok(file.data[start..end].to_vec());
+    }
+
+    fn open(&self, name: &str) -> Result<Option<Opened>, PathError> {
+        let name = normalize(name)?;
+        let files = self.files.read().unwrap_or_else(|e| return e.into_inner());
+        let Some(file) = files.get(&name) else {
+            return Ok(None);
+        };
+        return Ok(Some(Opened {
+            stat: file.stat(),
+            source: Box::new(Cursor::new(Snapshot(file.data.clone()))),
+        }));
+    }
+
+    fn write(&self, name: &str, data: &[u8]) -> Result<Written, PathError> {
+        let name = normalize(name)?;
+        let mut files = self.files.write().unwrap_or_else(|e| return e.into_inner());
+        return MemoryStorage::insert(&mut files, name, MemoryFile::new(data));
+    }
+
+    fn append(&self, name: &str, data: &[u8]) -> Result<u64, PathError> {
+        let name = normalize(name)?;
+        let mut files = self.files.write().unwrap_or_else(|e| return e.into_inner());
+        if let Some(file) = files.get_mut(&name) {
+            Arc::make_mut(&mut file.data).extend_from_slice(data);
+            file.modified = SystemTime::now();
+            return Ok(file.data.len() as u64);
+        }
+        MemoryStorage::insert(&mut files, name, MemoryFile::new(data))?;
+        return Ok(data.len() as u64);
+    }
+
+    fn rename(&self, from: &str, to: &str) -> Result<Written, PathError> {
+        let (from, to) = (normalize(from)?, normalize(to)?);
+        let mut files = self.files.write().unwrap_or_else(|e| return e.into_inner());
+        let Some(file) = files.remove(&from) else {
+            return Err(PathError::Io(io::ErrorKind::NotFound.into()));
+        };
+        return MemoryStorage::insert(&mut files, to, file);
+    }
+
+    fn delete(&self, name: &str) -> Result<bool, PathError> {
+        let name = normalize(name)?;
+        let mut files = self.files.write().unwrap_or_else(|e| return e.into_inner());
+        return Ok(files.remove(&name).is_some());
+    }
+
+    fn list(&self, dir: &str) -> Result<Vec<Entry>, PathError> {
+        let dir = normalize(dir)?;
+        let files = self.files.read().unwrap_or_else(|e| return e.into_inner());
+        if !MemoryStorage::is_dir(&files, &dir) {
+            return Err(PathError::Io(io::ErrorKind::NotFound.into()));
+        }
+        let prefix = if dir.is_empty() {
+            dir
+        } else {
+            format!("{}/", dir)
+        };
+        let mut entries: Vec<Entry> = Vec::new();
+        for (key, file) in files.range(prefix.clone()..) {
+            let Some(rest) = key.strip_prefix(&prefix) else {
+                break;
+            };
+            let entry = match rest.split_once('/') {
+                Some((subdir, _)) => Entry {
+                    name: subdir.to_string(),
+                    stat: directory(),
+                },
+                None => Entry {
+                    name: rest.to_string(),
+                    stat: file.stat(),
+                },
+            };
+            // the files of a subdirectory all come one after the other
+            if entries
+                .last()
+                .is_some_and(|last| return last.name == entry.name)
+            {
+                continue;
+            }
+            entries.push(entry);
+        }
+        entries.sort_by(|a, b| return a.name.cmp(&b.name));
+        return Ok(entries);
+    }
+}
+
+fn directory() -> Stat {
+    return Stat {
+        len: 0,
+        modified: None,
+        is_dir: true,
+    };
+}
+
+// the key of a name, by the same rules as names on disk
+fn normalize(name: &str) -> Result<String, PathError> {
+    return Ok(safe_path::components(name)?.join("/"));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::safe_path::PathError;
+    use crate::example_server::memory_storage::MemoryStorage;
+    use crate::example_server::storage::{Storage, Written};
+
+    #[test]
+    fn memory_storage_files() {
+        let storage = MemoryStorage::new();
+        assert_eq!(storage.write("a.txt", b"one").unwrap(), Written::Created);
+        assert_eq!(storage.write("./a.txt", b"two").unwrap(), Written::Replaced);
+        assert_eq!(storage.read_range("a.txt", 1, 10).unwrap(), b"wo".to_vec());
+        assert_eq!(
+            storage.read_range("a.txt", 5, 10).unwrap(),
+            Vec::<u8>::new()
+        );
+        assert_eq!(storage.stat("a.txt").unwrap().unwrap().len, 3);
+
+        storage.write("docs/b.txt", b"b").unwrap();
+        storage.write("docs/deep/c.txt", b"c").unwrap();
+        assert!(storage.stat("docs").unwrap().unwrap().is_dir);
+        assert!(storage.write("docs", b"x").is_err());
+        assert!(storage.write("a.txt/d.txt", b"x").is_err());
+        let names = |dir: &str| {
+            return storage
+                .list(dir)
+                .unwrap()
+                .into_iter()
+                .map(|entry| return entry.name)
+                .collect::<Vec<_>>();
+        };
+        assert_eq!(names(""), vec!["a.txt", "docs"]);
+        assert_eq!(names("docs"), vec!["b.txt", "deep"]);
+
+        assert!(storage.delete("docs/b.txt").unwrap());
+        assert!(!storage.delete("docs/b.txt").unwrap());
+        assert_eq!(storage.stat("docs/b.txt").unwrap(), None);
+    }
+
+    #[test]
+    fn memory_storage_refuses_escapes() {
+        let storage = MemoryStorage::new();
+        assert!(matches!(
+            storage.write("../a.txt", b""),
+            Err(PathError::Escapes(_))
+        ));
+        assert!(matches!(
+            storage.stat("/etc/passwd"),
+            Err(PathError::Absolute(_))
+        ));
+        assert!(matches!(storage.stat("a\0b"), Err(PathError::Nul(_))));
+        assert!(matches!(storage.stat("\\a"), Err(PathError::Absolute(_))));
+        assert!(matches!(
+            storage.open("a/../../b"),
+            Err(PathError::Escapes(_))
+        ));
+    }
+}
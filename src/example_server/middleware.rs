@@ -0,0 +1,18 @@
+use crate::core::middleware::Next;
+use crate::core::request::Request;
+use crate::core::response::{Response, StatusCode};
+use crate::core::routing::Identifiers;
+
+use crate::example_server::context::ServerContext;
+
+pub fn get_only(
+    req: &Request,
+    path_vals: &Identifiers,
+    ctx: &ServerContext,
+    next: Next<ServerContext>,
+) -> Response {
+    if req.method.is_get() {
+        return next.run(req, path_vals, ctx);
+    }
+    return Response::new(StatusCode::METHOD_NOT_ALLOWED);
+}
@@ -0,0 +1,32 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// A lock per name, so work on one file or upload only waits for work on the same one.
+#[derive(Debug, Default)]
+pub struct NameLocks {
+    locks: Mutex<HashMap<String, Arc<Mutex<()>>>>,
+}
+
+impl NameLocks {
+    pub fn new() -> NameLocks {
+        return NameLocks::default();
+    }
+
+    /// The lock of `name`, to hold while it is looked at or changed.
+    pub fn lock(&self, name: &str) -> Arc<Mutex<()>> {
+        let mut locks = self.locks.lock().unwrap_or_else(|e| return e.into_inner());
+        // the locks of names nobody is working on any more
+        locks.retain(|_, lock| return Arc::strong_count(lock) > 1);
+        return locks.entry(name.to_string()).or_default().clone();
+    }
+
+    // how many names have a lock kept for them
+    #[cfg(test)]
+    pub fn kept(&self) -> usize {
+        return self
+            .locks
+            .lock()
+            .unwrap_or_else(|e| return e.into_inner())
+            .len();
+    }
+}
@@ -5,4 +5,6 @@ pub use echo::echo;
 mod user_agent;
 pub use user_agent::user_agent;
 mod files;
-pub use files::files;
+pub use files::{files, form_files};
+mod uploads;
+pub use uploads::{create_upload, upload};
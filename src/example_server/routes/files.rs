@@ -1,51 +1,621 @@
+use crate::core::json;
+use crate::core::mime;
+use crate::core::multipart::{self, Limits, Multipart, Part};
 use crate::core::request::HttpMethod;
 use crate::core::request::Request;
-use crate::core::response;
+use crate::core::response::{IntoResponse, Response, StatusCode};
 use crate::core::routing::Identifiers;
-use anyhow::{anyhow, Result};
+use crate::core::safe_path;
+use crate::core::urlencoded::percent_decode;
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex, MutexGuard};
 
 use crate::example_server::context::ServerContext;
+use crate::example_server::storage::{self, Storage, Written};
+use crate::example_server::uploads;
 
-pub fn files(req: &Request, path_vals: &Identifiers, ctx: &ServerContext) -> Result<Vec<u8>> {
+// the files of a form are written here first, dot files are neither listed nor served
+const STAGING_PREFIX: &str = ".form-";
+
+pub fn files(req: &Request, path_vals: &Identifiers, ctx: &ServerContext) -> Response {
     match req.method {
-        HttpMethod::Get => {return get_files(req, path_vals, ctx); },
-        HttpMethod::Post => {return post_files(req, path_vals, ctx)}
+        // the body of a `HEAD` answer is left out when it is written
+        HttpMethod::Get | HttpMethod::Head => {
+            return get_files(req, path_vals, ctx).into_response();
+        }
+        HttpMethod::Post => return post_files(req, path_vals, ctx).into_response(),
+        HttpMethod::Put => return put_files(req, path_vals, ctx).into_response(),
+        HttpMethod::Delete => return delete_files(req, path_vals, ctx).into_response(),
+        // partial writes go through `/uploads`
+        HttpMethod::Patch => {
+            let mut response = Response::new(StatusCode::METHOD_NOT_ALLOWED);
+            response.set_header("Allow", "GET, HEAD, POST, PUT, DELETE");
+            return response;
+        }
+    }
+}
+
+/// `POST /files` from an HTML form, every file of the form is stored under its own name.
+pub fn form_files(req: &Request, _path_vals: &Identifiers, ctx: &ServerContext) -> Response {
+    return post_form(req, ctx).into_response();
+}
+
+fn post_form(req: &Request, ctx: &ServerContext) -> Result<Response, Response> {
+    if req.method != HttpMethod::Post {
+        let mut response = Response::new(StatusCode::METHOD_NOT_ALLOWED);
+        response.set_header("Allow", "POST");
+        return Err(response);
+    }
+    let Some(boundary) = req.header("Content-Type").and_then(multipart::boundary) else {
+        return Err(Response::new(StatusCode::UNSUPPORTED_MEDIA_TYPE));
+    };
+    let storage = ctx.storage.as_ref();
+    let mut staged = Vec::new();
+    let stored = stage_files(req.body.unwrap_or_default(), boundary, storage, &mut staged)
+        .and_then(|()| return move_staged(req, ctx, &staged));
+    // what is still staged belongs to a form that was refused
+    for (_, staging) in &staged {
+        let _ = storage.delete(staging);
+    }
+    stored?;
+    let mut locations = Vec::new();
+    for (name, _) in &staged {
+        let location = ctx.route_names.url_for("files", [("file", name)]);
+        locations.push(location.map_err(IntoResponse::into_response)?);
+    }
+    let mut response = Response::new(StatusCode::CREATED);
+    if let [location] = locations.as_slice() {
+        response.set_header("Location", location.clone());
+    }
+    let locations = locations.iter().map(|l| return json::string(l));
+    response.set_header("Content-Type", "application/json");
+    response.body = format!("[{}]", locations.collect::<Vec<_>>().join(",")).into_bytes();
+    return Ok(response);
+}
+
+// writes every file of a form to a name of its own as it is read, fields are left out. The
+// target and staging name of each is added to `staged` before it is written
+fn stage_files(
+    body: &[u8],
+    boundary: &str,
+    storage: &dyn Storage,
+    staged: &mut Vec<(String, String)>,
+) -> Result<(), Response> {
+    let mut form = Multipart::new(body, boundary, Limits::default());
+    while let Some(part) = form.next_part().map_err(IntoResponse::into_response)? {
+        if !part.is_file() {
+            continue;
+        }
+        let name = form_file_name(&part)?;
+        let staging = format!("{}{}", STAGING_PREFIX, uploads::new_id());
+        staged.push((name, staging.clone()));
+        let written = storage.write(&staging, &part.data);
+        written.map_err(IntoResponse::into_response)?;
+    }
+    if staged.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "No file in the form").into_response());
+    }
+    return Ok(());
+}
+
+// renames the staged files over their targets once the preconditions hold for every one of them,
+// so a form is stored whole or not at all. Only the storage failing between two renames leaves
+// part of it stored
+fn move_staged(
+    req: &Request,
+    ctx: &ServerContext,
+    staged: &[(String, String)],
+) -> Result<(), Response> {
+    let storage = ctx.storage.as_ref();
+    let locks = write_locks(ctx, staged.iter().map(|(name, _)| return name.as_str()));
+    let _writes = hold(&locks);
+    for (name, _) in staged {
+        let existing = storage.stat(name).map_err(IntoResponse::into_response)?;
+        if existing.is_some_and(|stat| return stat.is_dir) {
+            let message = format!("{} is a directory", name);
+            return Err((StatusCode::CONFLICT, message).into_response());
+        }
+        let validators = storage::validators(storage, name);
+        if let Some(failed) = validators
+            .map_err(IntoResponse::into_response)?
+            .evaluate(req)
+        {
+            return Err(failed);
+        }
+    }
+    for (name, staging) in staged {
+        let renamed = storage.rename(staging, name);
+        renamed.map_err(IntoResponse::into_response)?;
+    }
+    return Ok(());
+}
+
+// the data of the one file in a form, fields are left out
+fn form_file(body: &[u8], boundary: &str) -> Result<Vec<u8>, Response> {
+    let not_one = || {
+        let message = "The form has to hold exactly one file";
+        return (StatusCode::BAD_REQUEST, message).into_response();
+    };
+    let mut form = Multipart::new(body, boundary, Limits::default());
+    let mut file = None;
+    while let Some(part) = form.next_part().map_err(IntoResponse::into_response)? {
+        if part.is_file() && file.replace(part.data).is_some() {
+            return Err(not_one());
+        }
     }
+    return file.ok_or_else(not_one);
 }
 
-fn get_files(_req: &Request, path_vals: &Identifiers, ctx: &ServerContext) -> Result<Vec<u8>> { 
+// browsers send only the base name, others may send a whole path, of which only the last part
+// counts. Dot files are kept for the server itself, uploads in progress among them
+fn form_file_name(part: &Part) -> Result<String, Response> {
+    let filename = part.filename.as_deref().unwrap_or_default();
+    let name = filename.rsplit(['/', '\\']).next().unwrap_or_default();
+    if name.is_empty() || name.starts_with('.') {
+        let message = format!("Cannot store a file named {:?}", filename);
+        return Err((StatusCode::BAD_REQUEST, message).into_response());
+    }
+    return Ok(name.to_string());
+}
+
+// the decoded capture, `..%2F` has to be seen as `../` to be refused. Dot files are the server's
+// own and not found for a client
+fn requested_file(path_vals: &Identifiers) -> Result<String, Response> {
     let Some(file) = path_vals.path_values.get("file") else {
-        return Err(anyhow!("File not specified"));
+        return Err(Response::new(StatusCode::NOT_FOUND));
     };
+    let file = percent_decode(file).map_err(|_| return Response::new(StatusCode::BAD_REQUEST))?;
+    if storage::is_hidden(&file) {
+        return Err(Response::new(StatusCode::NOT_FOUND));
+    }
+    return Ok(file);
+}
 
-    let Some(content) = ctx.file_handler.search(file) else {
-        return Err(anyhow!(format!("Unable to get content from {}", file)));
+fn get_files(
+    req: &Request,
+    path_vals: &Identifiers,
+    ctx: &ServerContext,
+) -> Result<Response, Response> {
+    let file = requested_file(path_vals)?;
+    let found = ctx.storage.find(&file);
+    let Some(content) = found.map_err(IntoResponse::into_response)? else {
+        return Err(Response::new(StatusCode::NOT_FOUND));
     };
 
-    let data = ctx.file_handler.read(content);
-    let response = format!(
-        "{}\r\n{}\r\nContent-Length: {}\r\n\r\n",
-        "HTTP/1.1 200 OK",
-        "Content-Type: application/octet-stream",
-        data.len()
-    );
-    let mut response = response.into_bytes();
-    response.extend(data);
-    response.extend(b"\r\n".to_vec());
-    return Ok(response);
+    let storage = ctx.storage.as_ref();
+    return Ok(storage::serve(storage, req, &content, mime::OCTET_STREAM));
 }
 
-fn post_files(req: &Request, path_vals: &Identifiers, ctx: &ServerContext) -> Result<Vec<u8>> {
+fn post_files(
+    req: &Request,
+    path_vals: &Identifiers,
+    ctx: &ServerContext,
+) -> Result<Response, Response> {
+    let (file, _written) = store(req, path_vals, ctx)?;
+    return created(ctx, file);
+}
+
+// 201 for a new file and 204 for a replaced one, as the spec asks of `PUT`
+fn put_files(
+    req: &Request,
+    path_vals: &Identifiers,
+    ctx: &ServerContext,
+) -> Result<Response, Response> {
+    let (file, written) = store(req, path_vals, ctx)?;
+    match written {
+        Written::Created => return created(ctx, file),
+        Written::Replaced => return Ok(Response::new(StatusCode::NO_CONTENT)),
+    }
+}
+
+fn delete_files(
+    req: &Request,
+    path_vals: &Identifiers,
+    ctx: &ServerContext,
+) -> Result<Response, Response> {
+    let file = requested_file(path_vals)?;
+    let locks = write_locks(ctx, [file.as_str()]);
+    let _writes = hold(&locks);
+    let validators = storage::validators(ctx.storage.as_ref(), &file);
+    if let Some(failed) = validators
+        .map_err(IntoResponse::into_response)?
+        .evaluate(req)
+    {
+        return Err(failed);
+    }
+    let deleted = ctx.storage.delete(&file);
+    if !deleted.map_err(IntoResponse::into_response)? {
+        return Err(Response::new(StatusCode::NOT_FOUND));
+    }
+    return Ok(Response::new(StatusCode::NO_CONTENT));
+}
+
+// writes the body to the requested file once its preconditions hold, of a form only the one file
+// in it is written
+fn store(
+    req: &Request,
+    path_vals: &Identifiers,
+    ctx: &ServerContext,
+) -> Result<(String, Written), Response> {
+    // request parsing only takes a body that comes with its length, and truncates it to that
     let Some(body) = req.body else {
-        return Err(anyhow!("No body found with request"))
+        let message = "A Content-Length is required";
+        return Err((StatusCode::LENGTH_REQUIRED, message).into_response());
     };
-    // request parsing already ensures that content length exists and truncates body accordingly
-    let Some(file) = path_vals.path_values.get("file") else {
-        return Err(anyhow!("File not specified"));
+    let file = requested_file(path_vals)?;
+    let data = match req.header("Content-Type").and_then(multipart::boundary) {
+        Some(boundary) => Cow::Owned(form_file(body, boundary)?),
+        None => Cow::Borrowed(body),
     };
-    let path = ctx.file_handler.get_path(file);
-    let _written =  ctx
-        .file_handler
-        .write(path, &body.as_bytes())?;
-    return Ok(response::CREATED.to_vec());
-}
\ No newline at end of file
+    // `If-Match` and friends against the file being replaced, a lost update is a 412
+    let locks = write_locks(ctx, [file.as_str()]);
+    let _writes = hold(&locks);
+    let validators = storage::validators(ctx.storage.as_ref(), &file);
+    if let Some(failed) = validators
+        .map_err(IntoResponse::into_response)?
+        .evaluate(req)
+    {
+        return Err(failed);
+    }
+    let written = ctx.storage.write(&file, &data);
+    let written = written.map_err(IntoResponse::into_response)?;
+    return Ok((file, written));
+}
+
+// the write locks of the files `names`, in one order so two writes of the same files never each
+// hold one the other waits for. A name is locked as the storage reads it, `a//b` as `a/b`
+fn write_locks<'a>(
+    ctx: &ServerContext,
+    names: impl IntoIterator<Item = &'a str>,
+) -> Vec<Arc<Mutex<()>>> {
+    let keys = names
+        .into_iter()
+        .map(|name| match safe_path::components(name) {
+            Ok(parts) => return parts.join("/"),
+            Err(_) => return name.to_string(),
+        })
+        .collect::<BTreeSet<_>>();
+    return keys.iter().map(|key| return ctx.writes.lock(key)).collect();
+}
+
+fn hold(locks: &[Arc<Mutex<()>>]) -> Vec<MutexGuard<'_, ()>> {
+    return locks
+        .iter()
+        .map(|lock| return lock.lock().unwrap_or_else(|e| return e.into_inner()))
+        .collect();
+}
+
+fn created(ctx: &ServerContext, file: String) -> Result<Response, Response> {
+    let location = ctx.route_names.url_for("files", [("file", file)]);
+    let location = location.map_err(IntoResponse::into_response)?;
+    let mut response = Response::new(StatusCode::CREATED);
+    response.set_header("Location", location);
+    return Ok(response);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::request::Request;
+    use crate::core::response::{Response, StatusCode};
+    use crate::core::routing::{Captures, Identifiers};
+    use crate::core::safe_path::PathError;
+    use crate::example_server::context::ServerContext;
+    use crate::example_server::file_handler::FileHandler;
+    use crate::example_server::memory_storage::MemoryStorage;
+    use crate::example_server::routes::{files, form_files};
+    use crate::example_server::storage::{Entry, Opened, Stat, Storage, Written};
+    use crate::example_server::test_support::{self, context};
+    use crate::example_server::uploads::DEFAULT_EXPIRY;
+    use std::fs;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    fn context_with(storage: Box<dyn Storage>) -> ServerContext {
+        return test_support::context_with(storage, DEFAULT_EXPIRY);
+    }
+
+    fn call(ctx: &ServerContext, method: &str, file: &str, headers: &str, body: &str) -> Response {
+        let path = format!("/files/{}", file);
+        return test_support::call(ctx, files, method, &path, &[("file", file)], headers, body);
+    }
+
+    #[test]
+    fn files_in_memory() {
+        let ctx = context();
+        let created = call(&ctx, "PUT", "a.txt", "", "one");
+        assert_eq!(created.status, StatusCode::CREATED);
+        assert_eq!(created.header("Location"), Some("/files/a.txt"));
+        let replaced = call(&ctx, "PUT", "a.txt", "", "two");
+        assert_eq!(replaced.status, StatusCode::NO_CONTENT);
+
+        let read = call(&ctx, "GET", "a.txt", "", "");
+        assert_eq!(read.status, StatusCode::OK);
+        assert_eq!(read.body, b"two".to_vec());
+        let etag = read.header("ETag").unwrap().to_string();
+        let cached = call(
+            &ctx,
+            "GET",
+            "a.txt",
+            &format!("If-None-Match: {}\r\n", etag),
+            "",
+        );
+        assert_eq!(cached.status, StatusCode::NOT_MODIFIED);
+        let partial = call(&ctx, "GET", "a.txt", "Range: bytes=1-\r\n", "");
+        assert_eq!(partial.body, b"wo".to_vec());
+
+        let stale = call(&ctx, "PUT", "a.txt", "If-Match: \"old\"\r\n", "three");
+        assert_eq!(stale.status, StatusCode::PRECONDITION_FAILED);
+        let deleted = call(&ctx, "DELETE", "a.txt", "", "");
+        assert_eq!(deleted.status, StatusCode::NO_CONTENT);
+        assert_eq!(
+            call(&ctx, "GET", "a.txt", "", "").status,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            call(&ctx, "DELETE", "a.txt", "", "").status,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    fn form(files: &[(&str, &str)]) -> (String, String) {
+        let mut body = String::from(
+            "--b0undary\r\nContent-Disposition: form-data; name=\"note\"\r\n\r\nnot a file\r\n",
+        );
+        for (name, data) in files {
+            body.push_str(&format!(
+                "--b0undary\r\nContent-Disposition: form-data; name=\"f\"; filename=\"{}\"\r\n\r\n{}\r\n",
+                name, data
+            ));
+        }
+        body.push_str("--b0undary--\r\n");
+        let headers = "Content-Type: multipart/form-data; boundary=b0undary\r\n".to_string();
+        return (headers, body);
+    }
+
+    fn post_form(ctx: &ServerContext, headers: &str, body: &str) -> Response {
+        return test_support::call(ctx, form_files, "POST", "/files", &[], headers, body);
+    }
+
+    #[test]
+    fn files_from_forms() {
+        let ctx = context();
+        let (headers, body) = form(&[("a.txt", "one\r\n--b0und")]);
+        let created = call(&ctx, "POST", "b.txt", &headers, &body);
+        assert_eq!(created.status, StatusCode::CREATED);
+        let stored = ctx.storage.read_range("b.txt", 0, 100).unwrap();
+        assert_eq!(stored, b"one\r\n--b0und".to_vec());
+        let (headers, body) = form(&[("a.txt", "one"), ("c.txt", "two")]);
+        let refused = call(&ctx, "POST", "b.txt", &headers, &body);
+        assert_eq!(refused.status, StatusCode::BAD_REQUEST);
+
+        let created = post_form(&ctx, &headers, &body);
+        assert_eq!(created.status, StatusCode::CREATED);
+        assert_eq!(created.header("Location"), None);
+        assert_eq!(
+            created.body,
+            b"[\"/files/a.txt\",\"/files/c.txt\"]".to_vec()
+        );
+        assert_eq!(ctx.storage.read_range("c.txt", 0, 100).unwrap(), b"two");
+        let (headers, body) = form(&[("C:\\Users\\me\\d.txt", "three")]);
+        let created = post_form(&ctx, &headers, &body);
+        assert_eq!(created.header("Location"), Some("/files/d.txt"));
+
+        for name in ["", "../.hidden", "e.txt/"] {
+            let (headers, body) = form(&[("ok.txt", "x"), (name, "x")]);
+            let refused = post_form(&ctx, &headers, &body);
+            assert_eq!(refused.status, StatusCode::BAD_REQUEST);
+            assert_eq!(ctx.storage.stat("ok.txt").unwrap(), None);
+        }
+        let refused = post_form(&ctx, "Content-Type: text/plain\r\n", "x");
+        assert_eq!(refused.status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        // the preconditions hold for every file or none is stored
+        let (headers, body) = form(&[("e.txt", "new"), ("a.txt", "changed")]);
+        let headers = format!("{}If-None-Match: *\r\n", headers);
+        let refused = post_form(&ctx, &headers, &body);
+        assert_eq!(refused.status, StatusCode::PRECONDITION_FAILED);
+        ctx.storage.write("dir/f.txt", b"f").unwrap();
+        let (headers, body) = form(&[("e.txt", "new"), ("dir", "a file")]);
+        let refused = post_form(&ctx, &headers, &body);
+        assert_eq!(refused.status, StatusCode::CONFLICT);
+        assert_eq!(ctx.storage.stat("e.txt").unwrap(), None);
+        assert_eq!(ctx.storage.read_range("a.txt", 0, 100).unwrap(), b"one");
+        // nothing staged is left behind
+        let names = ctx.storage.list("").unwrap();
+        assert!(names
+            .iter()
+            .all(|entry| return !entry.name.starts_with('.')));
+    }
+
+    // answers `stat` late, so writers that do not wait for each other all see the same version
+    #[derive(Debug)]
+    struct SlowStat(MemoryStorage);
+
+    impl Storage for SlowStat {
+        fn stat(&self, name: &str) -> Result<Option<Stat>, PathError> {
+            let stat = self.0.stat(name);
+            std::thread::sleep(Duration::from_millis(20));
+            return stat;
+        }
+
+        fn read_range(&self, name: &str, start: u64, len: u64) -> Result<Vec<u8>, PathError> {
+            return self.0.read_range(name, start, len);
+        }
+
+        fn open(&self, name: &str) -> Result<Option<Opened>, PathError> {
+            return self.0.open(name);
+        }
+
+        fn write(&self, name: &str, data: &[u8]) -> Result<Written, PathError> {
+            return self.0.write(name, data);
+        }
+
+        fn append(&self, name: &str, data: &[u8]) -> Result<u64, PathError> {
+            return self.0.append(name, data);
+        }
+
+        fn rename(&self, from: &str, to: &str) -> Result<Written, PathError> {
+            return self.0.rename(from, to);
+        }
+
+        fn delete(&self, name: &str) -> Result<bool, PathError> {
+            return self.0.delete(name);
+        }
+
+        fn list(&self, dir: &str) -> Result<Vec<Entry>, PathError> {
+            return self.0.list(dir);
+        }
+    }
+
+    #[test]
+    fn files_conditional_writes_one_at_a_time() {
+        let ctx = context();
+        call(&ctx, "PUT", "a.txt", "", "one");
+        let etag = call(&ctx, "GET", "a.txt", "", "")
+            .header("ETag")
+            .unwrap()
+            .to_string();
+        let if_match = format!("If-Match: {}\r\n", etag);
+        assert_eq!(
+            call(&ctx, "PUT", "a.txt", &if_match, "two!").status,
+            StatusCode::NO_CONTENT
+        );
+        assert_eq!(
+            call(&ctx, "PUT", "a.txt", &if_match, "three").status,
+            StatusCode::PRECONDITION_FAILED
+        );
+
+        // of writers racing with the same tag only one gets through, however long the check takes
+        let ctx = context_with(Box::new(SlowStat(MemoryStorage::new())));
+        call(&ctx, "PUT", "a.txt", "", "one");
+        let etag = call(&ctx, "GET", "a.txt", "", "")
+            .header("ETag")
+            .unwrap()
+            .to_string();
+        let if_match = format!("If-Match: {}\r\n", etag);
+        let statuses = std::thread::scope(|scope| {
+            let writers = (1..=4)
+                .map(|n| {
+                    let (ctx, if_match) = (&ctx, &if_match);
+                    return scope.spawn(move || {
+                        return call(ctx, "PUT", "a.txt", if_match, &"x".repeat(n)).status;
+                    });
+                })
+                .collect::<Vec<_>>();
+            return writers
+                .into_iter()
+                .map(|writer| return writer.join().unwrap())
+                .collect::<Vec<_>>();
+        });
+        let passed = statuses
+            .iter()
+            .filter(|s| return **s == StatusCode::NO_CONTENT);
+        assert_eq!(passed.count(), 1);
+        let deleted = call(&ctx, "DELETE", "a.txt", &if_match, "");
+        assert_eq!(deleted.status, StatusCode::PRECONDITION_FAILED);
+
+        // a write of another file does not wait, one of the same file does, by any spelling
+        let lock = ctx.writes.lock("a.txt");
+        let held = lock.lock().unwrap();
+        let (done, finished) = mpsc::channel();
+        std::thread::scope(|scope| {
+            for file in ["b.txt", "./a.txt"] {
+                let (ctx, done) = (&ctx, done.clone());
+                scope.spawn(move || {
+                    call(ctx, "PUT", file, "", "x");
+                    return done.send(file).unwrap();
+                });
+            }
+            let first = finished.recv_timeout(Duration::from_secs(5));
+            assert_eq!(first, Ok("b.txt"));
+            let waiting = finished.recv_timeout(Duration::from_millis(100));
+            assert!(waiting.is_err());
+            drop(held);
+        });
+        assert_eq!(finished.recv(), Ok("./a.txt"));
+    }
+
+    #[test]
+    fn files_on_disk() {
+        let root = std::env::temp_dir().join(format!("files_on_disk_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let ctx = context_with(Box::new(FileHandler::new(root.clone()).unwrap()));
+        let created = call(&ctx, "PUT", "a.txt", "", "one");
+        assert_eq!(created.status, StatusCode::CREATED);
+        assert_eq!(created.header("Location"), Some("/files/a.txt"));
+        let replaced = call(&ctx, "PUT", "a.txt", "", "three");
+        assert_eq!(replaced.status, StatusCode::NO_CONTENT);
+        assert_eq!(fs::read(root.join("a.txt")).unwrap(), b"three");
+
+        // answered like `GET`, the body is only left out when the response is written
+        let head = call(&ctx, "HEAD", "a.txt", "", "").into_head_bytes();
+        let head = String::from_utf8(head).unwrap();
+        assert!(head.contains("Content-Length: 5\r\n"));
+        assert!(head.ends_with("\r\n\r\n"));
+
+        let deleted = call(&ctx, "DELETE", "a.txt", "", "");
+        assert_eq!(deleted.status, StatusCode::NO_CONTENT);
+        assert!(!root.join("a.txt").exists());
+        assert_eq!(
+            call(&ctx, "GET", "a.txt", "", "").status,
+            StatusCode::NOT_FOUND
+        );
+
+        // a write in progress, as it sits next to its target
+        fs::write(root.join(".a.txt.1-1.tmp"), "partial").unwrap();
+        for method in ["GET", "PUT", "DELETE"] {
+            let hidden = call(&ctx, method, ".a.txt.1-1.tmp", "", "x");
+            assert_eq!(hidden.status, StatusCode::NOT_FOUND);
+        }
+        let partial = fs::read(root.join(".a.txt.1-1.tmp")).unwrap();
+        assert_eq!(partial, b"partial");
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn files_hide_dot_files() {
+        let ctx = context();
+        ctx.storage.write(".form-0123", b"staged").unwrap();
+        ctx.storage.write(".git/config", b"config").unwrap();
+        for file in [
+            ".form-0123",
+            ".anything",
+            "%2Eanything",
+            ".git/config",
+            "a/.b/c",
+        ] {
+            for method in ["GET", "HEAD", "PUT", "POST", "DELETE"] {
+                let hidden = call(&ctx, method, file, "", "x");
+                assert_eq!(hidden.status, StatusCode::NOT_FOUND, "{} {}", method, file);
+            }
+        }
+        assert_eq!(
+            ctx.storage.read_range(".form-0123", 0, 10).unwrap(),
+            b"staged"
+        );
+        assert_eq!(ctx.storage.stat(".anything").unwrap(), None);
+    }
+
+    #[test]
+    fn files_refuse_escapes() {
+        let ctx = context();
+        let escaped = call(&ctx, "PUT", "..%2Fa.txt", "", "x");
+        assert_eq!(escaped.status, StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn files_need_a_length() {
+        let ctx = context();
+        for method in ["PUT", "POST"] {
+            let raw = format!("{} /files/a.txt HTTP/1.1\r\n\r\n", method);
+            let request = Request::from(raw.as_bytes()).unwrap();
+            let mut path_values = Captures::new();
+            path_values.insert("file", "a.txt");
+            let refused = files(&request, &Identifiers::new(path_values), &ctx);
+            assert_eq!(refused.status, StatusCode::LENGTH_REQUIRED);
+        }
+        assert_eq!(ctx.storage.stat("a.txt").unwrap(), None);
+    }
+}
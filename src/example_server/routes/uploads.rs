@@ -0,0 +1,347 @@
+use crate::core::http_date;
+use crate::core::request::{HttpMethod, Request};
+use crate::core::response::{IntoResponse, Response, StatusCode};
+use crate::core::routing::Identifiers;
+use crate::example_server::context::ServerContext;
+use crate::example_server::uploads::Upload;
+
+/*
+ * Resumable uploads after the tus protocol 1.0.0, https://tus.io/protocols/resumable-upload
+ * With the creation and termination extensions and without `OPTIONS` discovery.
+ */
+
+const TUS_VERSION: &str = "1.0.0";
+const OFFSET_CONTENT_TYPE: &str = "application/offset+octet-stream";
+
+/// `POST /uploads` with `Upload-Length` and the target file name as `filename` in
+/// `Upload-Metadata`.
+pub fn create_upload(req: &Request, _path_vals: &Identifiers, ctx: &ServerContext) -> Response {
+    return tus(req, || {
+        if req.method != HttpMethod::Post {
+            return Err(not_allowed("POST"));
+        }
+        let length = req.header("Upload-Length");
+        let Some(length) = length.and_then(|l| return l.trim().parse::<u64>().ok()) else {
+            return Err(Response::new(StatusCode::BAD_REQUEST));
+        };
+        let metadata = req.header("Upload-Metadata").unwrap_or("");
+        let Some(target) = metadata_value(metadata, "filename") else {
+            return Err(Response::new(StatusCode::BAD_REQUEST));
+        };
+        let upload = ctx.uploads.create(ctx.storage.as_ref(), &target, length);
+        let upload = upload.map_err(IntoResponse::into_response)?;
+        let location = ctx.route_names.url_for("upload", [("id", &upload.id)]);
+        let location = location.map_err(IntoResponse::into_response)?;
+        let mut response = progress(StatusCode::CREATED, &upload);
+        response.set_header("Location", location);
+        return Ok(response);
+    });
+}
+
+/// `HEAD` for how far an upload got, `PATCH` to add to it and `DELETE` to give up on it.
+pub fn upload(req: &Request, path_vals: &Identifiers, ctx: &ServerContext) -> Response {
+    return tus(req, || {
+        let Some(id) = path_vals.path_values.get("id") else {
+            return Err(Response::new(StatusCode::NOT_FOUND));
+        };
+        let storage = ctx.storage.as_ref();
+        match req.method {
+            HttpMethod::Head => {
+                let upload = ctx.uploads.get(storage, id);
+                let upload = upload.map_err(IntoResponse::into_response)?;
+                let mut response = progress(StatusCode::OK, &upload);
+                // a stale offset would make the client send the wrong part
+                response.set_header("Cache-Control", "no-store");
+                return Ok(response);
+            }
+            HttpMethod::Patch => {
+                if req.header("Content-Type") != Some(OFFSET_CONTENT_TYPE) {
+                    return Err(Response::new(StatusCode::UNSUPPORTED_MEDIA_TYPE));
+                }
+                let offset = req.header("Upload-Offset");
+                let Some(offset) = offset.and_then(|o| return o.trim().parse::<u64>().ok()) else {
+                    return Err(Response::new(StatusCode::BAD_REQUEST));
+                };
+                let data = req.body.unwrap_or_default();
+                let appended = ctx.uploads.append(storage, id, offset, data);
+                let (upload, completed) = appended.map_err(IntoResponse::into_response)?;
+                let mut response = progress(StatusCode::NO_CONTENT, &upload);
+                if completed.is_some() {
+                    let location = ctx.route_names.url_for("files", [("file", &upload.target)]);
+                    if let Ok(location) = location {
+                        response.set_header("Content-Location", location);
+                    }
+                }
+                return Ok(response);
+            }
+            HttpMethod::Delete => {
+                let cancelled = ctx.uploads.cancel(storage, id);
+                cancelled.map_err(IntoResponse::into_response)?;
+                return Ok(Response::new(StatusCode::NO_CONTENT));
+            }
+            _ => return Err(not_allowed("HEAD, PATCH, DELETE")),
+        }
+    });
+}
+
+// every answer names the protocol version, requests for another one are refused
+fn tus(req: &Request, handle: impl FnOnce() -> Result<Response, Response>) -> Response {
+    let mut response = match req.header("Tus-Resumable") {
+        Some(version) if version.trim() != TUS_VERSION => {
+            let mut response = Response::new(StatusCode::PRECONDITION_FAILED);
+            response.set_header("Tus-Version", TUS_VERSION);
+            response
+        }
+        _ => handle().into_response(),
+    };
+    response.set_header("Tus-Resumable", TUS_VERSION);
+    return response;
+}
+
+fn progress(status: StatusCode, upload: &Upload) -> Response {
+    let mut response = Response::new(status);
+    response.set_header("Upload-Offset", upload.offset.to_string());
+    response.set_header("Upload-Length", upload.length.to_string());
+    if !upload.is_complete() {
+        response.set_header("Upload-Expires", http_date::format(upload.expires));
+    }
+    return response;
+}
+
+fn not_allowed(allow: &str) -> Response {
+    let mut response = Response::new(StatusCode::METHOD_NOT_ALLOWED);
+    response.set_header("Allow", allow);
+    return response;
+}
+
+// `key base64value` pairs separated by commas, the value may be left out
+fn metadata_value(metadata: &str, key: &str) -> Option<String> {
+    let value = metadata.split(',').find_map(|pair| {
+        let mut parts = pair.trim().splitn(2, ' ');
+        if parts.next()? != key {
+            return None;
+        }
+        return Some(parts.next().unwrap_or("").trim());
+    })?;
+    return String::from_utf8(base64_decode(value)?).ok();
+}
+
+fn base64_decode(encoded: &str) -> Option<Vec<u8>> {
+    let sextet = |c: u8| -> Option<u32> {
+        match c {
+            b'A'..=b'Z' => return Some((c - b'A') as u32),
+            b'a'..=b'z' => return Some((c - b'a') as u32 + 26),
+            b'0'..=b'9' => return Some((c - b'0') as u32 + 52),
+            b'+' => return Some(62),
+            b'/' => return Some(63),
+            _ => return None,
+        }
+    };
+    let encoded = encoded.trim_end_matches('=').as_bytes();
+    let mut decoded = Vec::with_capacity(encoded.len() * 3 / 4);
+    for chunk in encoded.chunks(4) {
+        if chunk.len() == 1 {
+            return None;
+        }
+        let mut bits = 0u32;
+        for (i, c) in chunk.iter().enumerate() {
+            bits |= sextet(*c)? << (18 - 6 * i);
+        }
+        let bytes = bits.to_be_bytes();
+        decoded.extend_from_slice(&bytes[1..chunk.len()]);
+    }
+    return Some(decoded);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::response::{Response, StatusCode};
+    use crate::example_server::context::ServerContext;
+    use crate::example_server::memory_storage::MemoryStorage;
+    use crate::example_server::routes::uploads::base64_decode;
+    use crate::example_server::routes::{create_upload, files, upload};
+    use crate::example_server::test_support;
+    use crate::example_server::uploads::DEFAULT_EXPIRY;
+    use std::time::Duration;
+
+    fn context(expiry: Duration) -> ServerContext {
+        return test_support::context_with(Box::new(MemoryStorage::new()), expiry);
+    }
+
+    fn call(ctx: &ServerContext, method: &str, id: &str, headers: &str, body: &str) -> Response {
+        if id.is_empty() {
+            return test_support::call(ctx, create_upload, method, "/uploads", &[], headers, body);
+        }
+        let path = format!("/uploads/{}", id);
+        return test_support::call(ctx, upload, method, &path, &[("id", id)], headers, body);
+    }
+
+    fn patch(ctx: &ServerContext, id: &str, offset: u64, body: &str) -> Response {
+        let headers = format!(
+            "Content-Type: application/offset+octet-stream\r\nUpload-Offset: {}\r\n",
+            offset
+        );
+        return call(ctx, "PATCH", id, &headers, body);
+    }
+
+    #[test]
+    fn uploads_resume() {
+        let ctx = context(DEFAULT_EXPIRY);
+        // "a.txt"
+        let headers = "Upload-Length: 11\r\nUpload-Metadata: filename YS50eHQ=\r\n";
+        let created = call(&ctx, "POST", "", headers, "");
+        assert_eq!(created.status, StatusCode::CREATED);
+        assert_eq!(created.header("Tus-Resumable"), Some("1.0.0"));
+        let location = created.header("Location").unwrap();
+        let id = location.strip_prefix("/uploads/").unwrap().to_string();
+
+        let first = patch(&ctx, &id, 0, "hello");
+        assert_eq!(first.status, StatusCode::NO_CONTENT);
+        assert_eq!(first.header("Upload-Offset"), Some("5"));
+        // the same chunk again, as after a lost answer
+        assert_eq!(patch(&ctx, &id, 0, "hello").status, StatusCode::CONFLICT);
+        let head = call(&ctx, "HEAD", &id, "", "");
+        assert_eq!(head.header("Upload-Offset"), Some("5"));
+        assert_eq!(head.header("Upload-Length"), Some("11"));
+        assert_eq!(ctx.storage.stat("a.txt").unwrap(), None);
+
+        assert_eq!(
+            patch(&ctx, &id, 5, " world!").status,
+            StatusCode::BAD_REQUEST
+        );
+        let last = patch(&ctx, &id, 5, " world");
+        assert_eq!(last.status, StatusCode::NO_CONTENT);
+        assert_eq!(last.header("Content-Location"), Some("/files/a.txt"));
+        let stored = ctx.storage.read_range("a.txt", 0, 100).unwrap();
+        assert_eq!(stored, b"hello world".to_vec());
+        assert_eq!(
+            call(&ctx, "HEAD", &id, "", "").status,
+            StatusCode::NOT_FOUND
+        );
+        // nothing of the upload is left next to the file
+        assert_eq!(ctx.storage.list("").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn uploads_binary_chunks() {
+        let ctx = context(DEFAULT_EXPIRY);
+        // larger than one read from the connection and not UTF-8
+        let data = (0..20_000)
+            .map(|i| return (i % 251) as u8 | 0x80)
+            .collect::<Vec<_>>();
+        // "a.bin"
+        let headers = format!(
+            "Upload-Length: {}\r\nUpload-Metadata: filename YS5iaW4=\r\n",
+            data.len()
+        );
+        let created = call(&ctx, "POST", "", &headers, "");
+        let id = created.header("Location").unwrap()[9..].to_string();
+        let path = format!("/uploads/{}", id);
+        let headers = "Content-Type: application/offset+octet-stream\r\nUpload-Offset: 0\r\n";
+        let patched =
+            test_support::call(&ctx, upload, "PATCH", &path, &[("id", &id)], headers, &data);
+        assert_eq!(patched.status, StatusCode::NO_CONTENT);
+        let stored = ctx
+            .storage
+            .read_range("a.bin", 0, data.len() as u64)
+            .unwrap();
+        assert_eq!(stored, data);
+    }
+
+    #[test]
+    fn uploads_expire_and_cancel() {
+        let ctx = context(Duration::ZERO);
+        let headers = "Upload-Length: 3\r\nUpload-Metadata: filename YS50eHQ=\r\n";
+        let created = call(&ctx, "POST", "", headers, "");
+        let id = created.header("Location").unwrap()[9..].to_string();
+        assert_eq!(patch(&ctx, &id, 0, "abc").status, StatusCode::NOT_FOUND);
+        assert_eq!(ctx.uploads.sweep(ctx.storage.as_ref()), 1);
+        assert!(ctx.storage.list("").unwrap().is_empty());
+
+        let ctx = context(DEFAULT_EXPIRY);
+        let created = call(&ctx, "POST", "", headers, "");
+        let id = created.header("Location").unwrap()[9..].to_string();
+        assert_eq!(
+            call(&ctx, "DELETE", &id, "", "").status,
+            StatusCode::NO_CONTENT
+        );
+        assert!(ctx.storage.list("").unwrap().is_empty());
+
+        let escape = "Upload-Length: 3\r\nUpload-Metadata: filename Li4vYQ==\r\n";
+        let refused = call(&ctx, "POST", "", escape, "");
+        assert_eq!(refused.status, StatusCode::FORBIDDEN);
+        // ".upload-0123456789abcdef0123456789abcdef.info", "docs/.hidden" and ""
+        for filename in [
+            "LnVwbG9hZC0wMTIzNDU2Nzg5YWJjZGVmMDEyMzQ1Njc4OWFiY2RlZi5pbmZv",
+            "ZG9jcy8uaGlkZGVu",
+            "",
+        ] {
+            let hidden = format!(
+                "Upload-Length: 3\r\nUpload-Metadata: filename {}\r\n",
+                filename
+            );
+            let refused = call(&ctx, "POST", "", &hidden, "");
+            assert_eq!(refused.status, StatusCode::BAD_REQUEST, "{}", filename);
+        }
+        assert!(ctx.storage.list("").unwrap().is_empty());
+        let version = format!("Tus-Resumable: 0.2.2\r\n{}", headers);
+        let refused = call(&ctx, "POST", "", &version, "");
+        assert_eq!(refused.status, StatusCode::PRECONDITION_FAILED);
+    }
+
+    #[test]
+    fn uploads_keep_their_state_to_themselves() {
+        let ctx = context(DEFAULT_EXPIRY);
+        let headers = "Upload-Length: 3\r\nUpload-Metadata: filename YS50eHQ=\r\n";
+        let created = call(&ctx, "POST", "", headers, "");
+        let id = created.header("Location").unwrap()[9..].to_string();
+        for name in [
+            format!(".upload-{}.info", id),
+            format!(".upload-{}.part", id),
+        ] {
+            let path = format!("/files/{}", name);
+            for method in ["GET", "PUT", "DELETE"] {
+                let captures = [("file", name.as_str())];
+                let answer = test_support::call(&ctx, files, method, &path, &captures, "", "x");
+                assert_eq!(answer.status, StatusCode::NOT_FOUND, "{} {}", method, path);
+            }
+        }
+        assert_eq!(
+            call(&ctx, "HEAD", &id, "", "").header("Upload-Offset"),
+            Some("0")
+        );
+
+        // an info rewritten behind the server's back does not send the upload elsewhere
+        let info = format!(".upload-{}.info", id);
+        ctx.storage.write(&info, b"3\n.hidden").unwrap();
+        assert_eq!(patch(&ctx, &id, 0, "abc").status, StatusCode::NOT_FOUND);
+        assert_eq!(ctx.storage.stat(".hidden").unwrap(), None);
+    }
+
+    #[test]
+    fn uploads_need_a_directory_to_end_up_in() {
+        let ctx = context(DEFAULT_EXPIRY);
+        let create = |filename: &str| {
+            let headers = format!(
+                "Upload-Length: 1\r\nUpload-Metadata: filename {}\r\n",
+                filename
+            );
+            return call(&ctx, "POST", "", &headers, "").status;
+        };
+        // "missing/a.txt"
+        assert_eq!(create("bWlzc2luZy9hLnR4dA=="), StatusCode::CONFLICT);
+        ctx.storage.write("docs/b.txt", b"b").unwrap();
+        // "docs" is a directory, "docs/a.txt" can be created in it
+        assert_eq!(create("ZG9jcw=="), StatusCode::BAD_REQUEST);
+        assert_eq!(create("ZG9jcy9hLnR4dA=="), StatusCode::CREATED);
+    }
+
+    #[test]
+    fn uploads_base64() {
+        assert_eq!(base64_decode("YS50eHQ="), Some(b"a.txt".to_vec()));
+        assert_eq!(base64_decode("aGk"), Some(b"hi".to_vec()));
+        assert_eq!(base64_decode(""), Some(Vec::new()));
+        assert_eq!(base64_decode("a"), None);
+        assert_eq!(base64_decode("a*=="), None);
+    }
+}
@@ -0,0 +1,387 @@
+use crate::core::json;
+use crate::core::mime;
+use crate::core::request::Request;
+use crate::core::response::{IntoResponse, Response, StatusCode};
+use crate::core::route_group::{MountPrefix, RouteGroup};
+use crate::core::routing::{Handler, Identifiers};
+use crate::core::urlencoded::{percent_decode, percent_encode};
+use crate::example_server::storage::{self, Entry, Storage};
+use std::path::Path;
+
+const SNIFF_LEN: u64 = 16;
+
+/// Serves a directory as is: files with a `Content-Type` from their extension, directories by
+/// their index file or a generated listing.
+///
+/// It does not depend on the server context, so it can be mounted any number of times, each with
+/// its own storage, see `group`.
+pub struct StaticFiles {
+    files: Box<dyn Storage>,
+    // served for a directory instead of a listing
+    index_file: Option<String>,
+    autoindex: bool,
+    // look at the first bytes of files whose extension says nothing
+    sniff: bool,
+    // every file is a download, for directories clients write to
+    attachments: bool,
+}
+
+impl StaticFiles {
+    pub fn new(files: impl Storage + 'static) -> StaticFiles {
+        return StaticFiles {
+            files: Box::new(files),
+            index_file: Some(String::from("index.html")),
+            autoindex: false,
+            sniff: false,
+            attachments: false,
+        };
+    }
+
+    pub fn index_file(mut self, name: Option<&str>) -> Self {
+        self.index_file = name.map(String::from);
+        return self;
+    }
+
+    /// Lists directories without an index file, as HTML or as JSON when the client accepts it.
+    pub fn autoindex(mut self, enabled: bool) -> Self {
+        self.autoindex = enabled;
+        return self;
+    }
+
+    pub fn sniff(mut self, enabled: bool) -> Self {
+        self.sniff = enabled;
+        return self;
+    }
+
+    /// Serves every file as an `application/octet-stream` download the browser does not sniff, so
+    /// files anyone could have uploaded are never shown as a page of the server.
+    pub fn attachments(mut self, enabled: bool) -> Self {
+        self.attachments = enabled;
+        return self;
+    }
+
+    /// A group answering every path below the prefix it gets mounted at.
+    pub fn group<Context: 'static>(self) -> RouteGroup<Context> {
+        let mut group = RouteGroup::new();
+        group.strip_prefix(true);
+        group.fallback(self);
+        return group;
+    }
+
+    fn serve(&self, req: &Request) -> Result<Response, Response> {
+        if !req.method.is_get() {
+            let mut response = Response::new(StatusCode::METHOD_NOT_ALLOWED);
+            response.set_header("Allow", "GET, HEAD");
+            return Err(response);
+        }
+        let Ok(relative) = percent_decode(req.path.trim_start_matches('/')) else {
+            return Err(Response::new(StatusCode::BAD_REQUEST));
+        };
+        // hidden files, and the partial ones of uploads and writes in progress
+        if storage::is_hidden(&relative) {
+            return Err(Response::new(StatusCode::NOT_FOUND));
+        }
+        let stat = self
+            .files
+            .stat(&relative)
+            .map_err(IntoResponse::into_response)?;
+        let Some(stat) = stat else {
+            return Err(Response::new(StatusCode::NOT_FOUND));
+        };
+        if !stat.is_dir {
+            return Ok(self.file(req, &relative));
+        }
+        // the links of an index file or a listing are relative to the directory
+        if !req.path.ends_with('/') {
+            return Ok(redirect_to_dir(req));
+        }
+
+        if let Some(index_file) = &self.index_file {
+            let index = child(&relative, index_file);
+            if let Ok(Some(found)) = self.files.stat(&index) {
+                if !found.is_dir {
+                    return Ok(self.file(req, &index));
+                }
+            }
+        }
+        if !self.autoindex {
+            return Err(Response::new(StatusCode::NOT_FOUND));
+        }
+        let mut entries = self.files.list(&relative).unwrap_or_default();
+        entries.retain(|entry| return !storage::is_hidden(&entry.name));
+        if req
+            .header("Accept")
+            .is_some_and(|accept| return accept.contains("application/json"))
+        {
+            return Ok(json_listing(&entries));
+        }
+        let prefix = req.extensions.get::<MountPrefix>();
+        let prefix = prefix.map(|MountPrefix(p)| return p.as_str()).unwrap_or("");
+        return Ok(html_listing(&format!("{}{}", prefix, req.path), &entries));
+    }
+
+    fn file(&self, req: &Request, name: &str) -> Response {
+        if self.attachments {
+            let storage = self.files.as_ref();
+            let mut response = storage::serve(storage, req, name, mime::OCTET_STREAM);
+            response.set_header("X-Content-Type-Options", "nosniff");
+            response.set_header("Content-Disposition", "attachment");
+            return response;
+        }
+        let sniffed = || {
+            if !self.sniff {
+                return None;
+            }
+            // signatures are short, the whole file is not needed to tell
+            let head = self.files.read_range(name, 0, SNIFF_LEN).ok()?;
+            return mime::sniff(&head);
+        };
+        let media_type = mime::from_path(Path::new(name))
+            .or_else(sniffed)
+            .unwrap_or(mime::OCTET_STREAM);
+        return storage::serve(self.files.as_ref(), req, name, media_type);
+    }
+}
+
+impl<Context> Handler<Context> for StaticFiles {
+    fn call(&self, req: &Request, _path_vals: &Identifiers, _ctx: &Context) -> Response {
+        return self.serve(req).into_response();
+    }
+}
+
+fn redirect_to_dir(req: &Request) -> Response {
+    let prefix = req.extensions.get::<MountPrefix>();
+    let prefix = prefix.map(|MountPrefix(p)| return p.as_str()).unwrap_or("");
+    let mut location = format!("{}{}/", prefix, req.path);
+    if let Some(query) = req.query {
+        location = format!("{}?{}", location, query);
+    }
+    let mut response = Response::new(StatusCode::MOVED_PERMANENTLY);
+    response.set_header("Location", location);
+    return response;
+}
+
+// relative paths of the base directory itself are empty, a leading slash would make them absolute
+fn child(relative: &str, name: &str) -> String {
+    if relative.is_empty() {
+        return name.to_string();
+    }
+    return format!("{}/{}", relative.trim_end_matches('/'), name);
+}
+
+fn json_listing(entries: &[Entry]) -> Response {
+    let items = entries
+        .iter()
+        .map(|entry| {
+            if entry.stat.is_dir {
+                return format!(
+                    "{{\"name\":{},\"type\":\"directory\"}}",
+                    json::string(&entry.name)
+                );
+            }
+            return format!(
+                "{{\"name\":{},\"type\":\"file\",\"size\":{}}}",
+                json::string(&entry.name),
+                entry.stat.len
+            );
+        })
+        .collect::<Vec<_>>();
+    let mut response = Response::new(StatusCode::OK);
+    response.set_header("Content-Type", "application/json");
+    response.body = format!("[{}]", items.join(",")).into_bytes();
+    return response;
+}
+
+// links are absolute, whatever the page is mounted under
+fn html_listing(path: &str, entries: &[Entry]) -> Response {
+    let base = path.trim_end_matches('/');
+    let title = html_escape(if path.is_empty() { "/" } else { path });
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Index of {title}</title></head>\n<body>\n<h1>Index of {title}</h1>\n<ul>\n"
+    );
+    for entry in entries {
+        let slash = if entry.stat.is_dir { "/" } else { "" };
+        html.push_str(&format!(
+            "<li><a href=\"{}/{}{}\">{}{}</a></li>\n",
+            html_escape(base),
+            percent_encode(&entry.name),
+            slash,
+            html_escape(&entry.name),
+            slash
+        ));
+    }
+    html.push_str("</ul>\n</body>\n</html>\n");
+    let mut response = Response::new(StatusCode::OK);
+    response.set_header("Content-Type", "text/html; charset=utf-8");
+    response.body = html.into_bytes();
+    return response;
+}
+
+fn html_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    return escaped;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::request::Request;
+    use crate::core::response::{Response, StatusCode};
+    use crate::core::route_group::MountPrefix;
+    use crate::core::routing::{Captures, Handler, Identifiers};
+    use crate::example_server::memory_storage::MemoryStorage;
+    use crate::example_server::static_files::{html_escape, StaticFiles};
+    use crate::example_server::storage::Storage;
+
+    // docs/{index.html, guide.txt}, raw/{<b>&.txt, image, sub/x} and a hidden raw/.partial
+    fn files() -> MemoryStorage {
+        let files = MemoryStorage::new();
+        files.write("docs/index.html", b"<h1>docs</h1>").unwrap();
+        files.write("docs/guide.txt", b"guide").unwrap();
+        files.write("raw/<b>&.txt", b"b").unwrap();
+        files.write("raw/image", b"\x89PNG\r\n\x1a\n....").unwrap();
+        files.write("raw/sub/x", b"x").unwrap();
+        files.write("raw/.partial", b"p").unwrap();
+        return files;
+    }
+
+    fn call(handler: &StaticFiles, raw: &str) -> Response {
+        let request = Request::from(raw.as_bytes()).unwrap();
+        return handler.call(&request, &Identifiers::new(Captures::new()), &());
+    }
+
+    fn get(handler: &StaticFiles, path: &str) -> Response {
+        return call(handler, &format!("GET {} HTTP/1.1\r\n\r\n", path));
+    }
+
+    fn body(response: &Response) -> &str {
+        return std::str::from_utf8(&response.body).unwrap();
+    }
+
+    #[test]
+    fn static_files_index_file() {
+        let handler = StaticFiles::new(files());
+        let response = get(&handler, "/docs/");
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(body(&response), "<h1>docs</h1>");
+        assert_eq!(
+            response.header("Content-Type"),
+            Some("text/html; charset=utf-8")
+        );
+        assert_eq!(body(&get(&handler, "/docs/guide.txt")), "guide");
+        // no index file and no listing
+        assert_eq!(get(&handler, "/raw/").status, StatusCode::NOT_FOUND);
+        assert_eq!(get(&handler, "/missing").status, StatusCode::NOT_FOUND);
+        // the server's own files are not served, whatever the spelling
+        for hidden in ["/raw/.partial", "/raw/%2Epartial", "/raw/sub/../.partial"] {
+            assert_eq!(get(&handler, hidden).status, StatusCode::NOT_FOUND);
+        }
+
+        let handler = StaticFiles::new(files()).index_file(Some("guide.txt"));
+        assert_eq!(body(&get(&handler, "/docs/")), "guide");
+        let handler = StaticFiles::new(files()).index_file(None);
+        assert_eq!(get(&handler, "/docs/").status, StatusCode::NOT_FOUND);
+
+        // a directory is only served under a path ending in a slash, links in it are relative
+        let moved = get(&handler, "/docs?lang=en");
+        assert_eq!(moved.status, StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(moved.header("Location"), Some("/docs/?lang=en"));
+        let mut request = Request::from(b"GET /raw/sub HTTP/1.1\r\n\r\n").unwrap();
+        request
+            .extensions
+            .insert(MountPrefix(String::from("/static")));
+        let moved = handler.call(&request, &Identifiers::new(Captures::new()), &());
+        assert_eq!(moved.header("Location"), Some("/static/raw/sub/"));
+    }
+
+    #[test]
+    fn static_files_autoindex() {
+        let handler = StaticFiles::new(files()).autoindex(true);
+        // an index file still wins over the listing
+        assert_eq!(body(&get(&handler, "/docs/")), "<h1>docs</h1>");
+
+        let html = get(&handler, "/raw/");
+        assert_eq!(html.status, StatusCode::OK);
+        assert!(body(&html).contains("<title>Index of /raw/</title>"));
+        let links = body(&html)
+            .lines()
+            .filter(|line| return line.starts_with("<li>"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            links,
+            vec![
+                "<li><a href=\"/raw/%3Cb%3E%26.txt\">&lt;b&gt;&amp;.txt</a></li>",
+                "<li><a href=\"/raw/image\">image</a></li>",
+                "<li><a href=\"/raw/sub/\">sub/</a></li>",
+            ]
+        );
+
+        let json = call(
+            &handler,
+            "GET /raw/ HTTP/1.1\r\nAccept: application/json\r\n\r\n",
+        );
+        assert_eq!(json.header("Content-Type"), Some("application/json"));
+        assert_eq!(
+            body(&json),
+            "[{\"name\":\"<b>&.txt\",\"type\":\"file\",\"size\":1},\
+             {\"name\":\"image\",\"type\":\"file\",\"size\":12},\
+             {\"name\":\"sub\",\"type\":\"directory\"}]"
+        );
+    }
+
+    #[test]
+    fn static_files_methods_and_sniffing() {
+        let handler = StaticFiles::new(files());
+        let response = call(&handler, "DELETE /docs/guide.txt HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status, StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.header("Allow"), Some("GET, HEAD"));
+        let head = call(&handler, "HEAD /docs/guide.txt HTTP/1.1\r\n\r\n");
+        assert_eq!(head.status, StatusCode::OK);
+
+        let plain = get(&handler, "/raw/image");
+        assert_eq!(
+            plain.header("Content-Type"),
+            Some("application/octet-stream")
+        );
+        let handler = StaticFiles::new(files()).sniff(true);
+        let sniffed = get(&handler, "/raw/image");
+        assert_eq!(sniffed.header("Content-Type"), Some("image/png"));
+        // the extension still comes first
+        let text = get(&handler, "/docs/guide.txt");
+        assert_eq!(
+            text.header("Content-Type"),
+            Some("text/plain; charset=utf-8")
+        );
+
+        // uploaded files are downloads, whatever they claim to be
+        let handler = StaticFiles::new(files()).sniff(true).attachments(true);
+        for path in ["/docs/index.html", "/raw/image"] {
+            let download = get(&handler, path);
+            assert_eq!(download.status, StatusCode::OK);
+            assert_eq!(
+                download.header("Content-Type"),
+                Some("application/octet-stream")
+            );
+            assert_eq!(download.header("X-Content-Type-Options"), Some("nosniff"));
+            assert_eq!(download.header("Content-Disposition"), Some("attachment"));
+        }
+    }
+
+    #[test]
+    fn static_files_html_escape() {
+        assert_eq!(
+            html_escape("<a href=\"x\">Tom & Jerry's</a>"),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;"
+        );
+        assert_eq!(html_escape("plain é"), "plain é");
+    }
+}
@@ -0,0 +1,205 @@
+use crate::core::conditional::{ETag, Validators};
+use crate::core::range;
+use crate::core::request::Request;
+use crate::core::response::{IntoResponse, Response, StatusCode};
+use crate::core::safe_path::PathError;
+use std::fmt;
+use std::io::{Read, Seek};
+use std::time::SystemTime;
+
+/// What is known about a stored file or directory without reading it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stat {
+    pub len: u64,
+    pub modified: Option<SystemTime>,
+    pub is_dir: bool,
+}
+
+impl Stat {
+    /// Validators of a file with this size and modification time.
+    pub fn validators(&self) -> Validators {
+        return Validators {
+            etag: Some(ETag::from_metadata(self.len, self.modified)),
+            last_modified: self.modified,
+            exists: true,
+        };
+    }
+}
+
+/// A file or directory directly inside a listed directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub name: String,
+    pub stat: Stat,
+}
+
+/// Something a file can be read from in any order.
+pub trait Source: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> Source for T {}
+
+/// A file opened with `Storage::open`, the stat is of the content the source reads.
+pub struct Opened {
+    pub stat: Stat,
+    pub source: Box<dyn Source>,
+}
+
+/// What `Storage::write` did to the file it was given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Written {
+    Created,
+    Replaced,
+}
+
+/// Where the served files are kept.
+///
+/// Names are relative paths with `/` between components, straight from requests. Names that
+/// would get out of the storage are refused with the errors of `SafeRoot::resolve`, whether or
+/// not the storage has a directory on disk.
+pub trait Storage: Send + Sync + fmt::Debug {
+    /// `None` when there is nothing under `name`.
+    fn stat(&self, name: &str) -> Result<Option<Stat>, PathError>;
+
+    /// Up to `len` bytes of the file from `start`, fewer where the file ends.
+    fn read_range(&self, name: &str, start: u64, len: u64) -> Result<Vec<u8>, PathError>;
+
+    /// The file for reading as it is now, a write while it is read does not show through. `None`
+    /// when there is no file under `name`, a directory is not one.
+    fn open(&self, name: &str) -> Result<Option<Opened>, PathError>;
+
+    /// Replaces the whole file at once, readers never see part of `data`.
+    fn write(&self, name: &str, data: &[u8]) -> Result<Written, PathError>;
+
+    /// Adds `data` to the end of the file, creating it if needed, and answers its new length.
+    /// Once this returns the data is kept, it is how an upload knows what it can resume from.
+    fn append(&self, name: &str, data: &[u8]) -> Result<u64, PathError>;
+
+    /// Moves the file `from` over `to` at once, like `write` with the content of `from`.
+    fn rename(&self, from: &str, to: &str) -> Result<Written, PathError>;
+
+    /// `false` when there was no such file.
+    fn delete(&self, name: &str) -> Result<bool, PathError>;
+
+    /// Entries of the directory `dir` sorted by name, `""` being the top.
+    fn list(&self, dir: &str) -> Result<Vec<Entry>, PathError>;
+
+    /// The name of the file a request for `name` means, only `name` itself unless the storage
+    /// knows better.
+    fn find(&self, name: &str) -> Result<Option<String>, PathError> {
+        match self.stat(name)? {
+            Some(stat) if !stat.is_dir => return Ok(Some(name.to_string())),
+            _ => return Ok(None),
+        }
+    }
+}
+
+/// Whether `name` goes through a dot file or directory. Those are kept for the server itself,
+/// such as the state of uploads, and are neither served nor written for a client.
+pub fn is_hidden(name: &str) -> bool {
+    // `..` is no name of its own, the path rules refuse it as an escape
+    return name
+        .split(std::path::is_separator)
+        .any(|part| return part.starts_with('.') && part != "." && part != "..");
+}
+
+/// Validators of the file as it is now, for preconditions on reads and writes of it.
+pub fn validators(storage: &dyn Storage, name: &str) -> Result<Validators, PathError> {
+    match storage.stat(name)? {
+        Some(stat) if !stat.is_dir => return Ok(stat.validators()),
+        _ => return Ok(Validators::missing()),
+    }
+}
+
+/// The response to `req` for the file `name`, honoring `Range` so only the requested parts are
+/// read. `name` is expected to come from `Storage::find` or to name a file.
+///
+/// Responses carry `ETag` and `Last-Modified`, and a client that already has the file gets a
+/// 304 without it being read. The file is opened once, a response never mixes two versions.
+pub fn serve(storage: &dyn Storage, req: &Request, name: &str, content_type: &str) -> Response {
+    let Opened { stat, mut source } = match storage.open(name) {
+        Ok(Some(opened)) => opened,
+        Ok(None) => return Response::new(StatusCode::NOT_FOUND),
+        Err(e) => return e.into_response(),
+    };
+    let validators = stat.validators();
+    if let Some(response) = validators.evaluate(req) {
+        return response;
+    }
+    let etag = validators.etag.as_ref().map(ToString::to_string);
+    let response = range::serve(
+        req,
+        &mut source,
+        stat.len,
+        content_type,
+        etag.as_deref(),
+        validators.last_modified,
+    );
+    let Ok(mut response) = response else {
+        return Response::new(StatusCode::INTERNAL_SERVER_ERROR);
+    };
+    validators.apply(&mut response);
+    return response;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::request::Request;
+    use crate::core::response::{Response, StatusCode};
+    use crate::example_server::memory_storage::MemoryStorage;
+    use crate::example_server::storage::{serve, Storage};
+    use std::io::{Read, Seek, SeekFrom};
+
+    fn get(storage: &dyn Storage, name: &str, headers: &str) -> Response {
+        let raw = format!("GET /{} HTTP/1.1\r\n{}\r\n", name, headers);
+        let request = Request::from(raw.as_bytes()).unwrap();
+        return serve(storage, &request, name, "text/plain");
+    }
+
+    #[test]
+    fn storage_serve() {
+        let storage = MemoryStorage::new();
+        storage.write("docs/a.txt", b"0123456789").unwrap();
+        let full = get(&storage, "docs/a.txt", "");
+        assert_eq!(full.status, StatusCode::OK);
+        assert_eq!(full.body, b"0123456789".to_vec());
+        let etag = full.header("ETag").unwrap().to_string();
+        assert!(full.header("Last-Modified").is_some());
+
+        let partial = get(&storage, "docs/a.txt", "Range: bytes=2-4, 8-\r\n");
+        assert_eq!(partial.status, StatusCode::PARTIAL_CONTENT);
+        let body = String::from_utf8(partial.body).unwrap();
+        assert!(body.contains("Content-Range: bytes 2-4/10\r\n\r\n234\r\n"));
+        assert!(body.contains("Content-Range: bytes 8-9/10\r\n\r\n89\r\n"));
+
+        let cached = get(
+            &storage,
+            "docs/a.txt",
+            &format!("If-None-Match: {}\r\n", etag),
+        );
+        assert_eq!(cached.status, StatusCode::NOT_MODIFIED);
+        assert_eq!(get(&storage, "docs", "").status, StatusCode::NOT_FOUND);
+        assert_eq!(get(&storage, "b.txt", "").status, StatusCode::NOT_FOUND);
+        assert_eq!(get(&storage, "../b.txt", "").status, StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn storage_open_keeps_its_version() {
+        let storage = MemoryStorage::new();
+        storage.write("a.txt", b"first").unwrap();
+        let mut opened = storage.open("a.txt").unwrap().unwrap();
+        assert_eq!(opened.stat.len, 5);
+        storage.write("a.txt", b"second").unwrap();
+        storage.append("a.txt", b"!").unwrap();
+
+        let mut read = String::new();
+        opened.source.seek(SeekFrom::Start(1)).unwrap();
+        opened.source.read_to_string(&mut read).unwrap();
+        assert_eq!(read, "irst");
+        let mut now = String::new();
+        let mut reopened = storage.open("a.txt").unwrap().unwrap().source;
+        reopened.read_to_string(&mut now).unwrap();
+        assert_eq!(now, "second!");
+        storage.write("dir/b.txt", b"b").unwrap();
+        assert!(storage.open("dir").unwrap().is_none());
+    }
+}
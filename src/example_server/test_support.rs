@@ -0,0 +1,61 @@
+use crate::core::request::Request;
+use crate::core::response::Response;
+use crate::core::route_names::RouteNames;
+use crate::core::routing::{Captures, Handler, Identifiers};
+use crate::example_server::context::ServerContext;
+use crate::example_server::memory_storage::MemoryStorage;
+use crate::example_server::name_locks::NameLocks;
+use crate::example_server::storage::Storage;
+use crate::example_server::uploads::{Uploads, DEFAULT_EXPIRY};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A context on memory, with the route names the example server links to.
+pub fn context() -> ServerContext {
+    return context_with(Box::new(MemoryStorage::new()), DEFAULT_EXPIRY);
+}
+
+pub fn context_with(storage: Box<dyn Storage>, expiry: Duration) -> ServerContext {
+    let route_names = RouteNames::new();
+    route_names
+        .insert("files", &"/files/{file}".into())
+        .unwrap();
+    route_names
+        .insert("upload", &"/uploads/{id}".into())
+        .unwrap();
+    return ServerContext {
+        storage,
+        writes: NameLocks::new(),
+        uploads: Uploads::new(expiry),
+        route_names: Arc::new(route_names),
+    };
+}
+
+/// Calls `handler` as the router would for a request to `path`, with the values its route
+/// captured from it. The body may be any bytes.
+pub fn call(
+    ctx: &ServerContext,
+    handler: impl Handler<ServerContext>,
+    method: &str,
+    path: &str,
+    captures: &[(&str, &str)],
+    headers: &str,
+    body: impl AsRef<[u8]>,
+) -> Response {
+    let body = body.as_ref();
+    let mut raw = format!(
+        "{} {} HTTP/1.1\r\n{}Content-Length: {}\r\n\r\n",
+        method,
+        path,
+        headers,
+        body.len()
+    )
+    .into_bytes();
+    raw.extend_from_slice(body);
+    let request = Request::from(&raw).unwrap();
+    let mut path_values = Captures::new();
+    for (key, value) in captures {
+        path_values.insert(key, value);
+    }
+    return handler.call(&request, &Identifiers::new(path_values), ctx);
+}
@@ -0,0 +1,331 @@
+use crate::core::response::{IntoResponse, Response, StatusCode};
+use crate::core::safe_path::{self, PathError};
+use crate::example_server::name_locks::NameLocks;
+use crate::example_server::storage::{self, Storage, Written};
+use std::collections::hash_map::RandomState;
+use std::fs::File;
+use std::hash::{BuildHasher, Hasher};
+use std::io::Read;
+use std::sync::TryLockError;
+use std::time::{Duration, SystemTime};
+
+/// How long an upload nobody adds to is kept.
+pub const DEFAULT_EXPIRY: Duration = Duration::from_secs(24 * 60 * 60);
+
+// next to the files, names starting with a dot are neither listed nor served, see `is_hidden`
+const PREFIX: &str = ".upload-";
+
+#[derive(Debug, thiserror::Error)]
+pub enum UploadError {
+    #[error("No upload {0}")]
+    NotFound(String),
+    #[error("Upload is at offset {expected}, not {given}")]
+    Offset { expected: u64, given: u64 },
+    #[error("Upload would grow past its length of {0}")]
+    TooLong(u64),
+    #[error("Cannot upload to {0}")]
+    Target(String),
+    #[error("No directory {0} to upload into")]
+    Parent(String),
+    #[error(transparent)]
+    Storage(#[from] PathError),
+}
+
+impl UploadError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            UploadError::NotFound(_) => return StatusCode::NOT_FOUND,
+            UploadError::Offset { .. } | UploadError::Parent(_) => return StatusCode::CONFLICT,
+            UploadError::TooLong(_) | UploadError::Target(_) => return StatusCode::BAD_REQUEST,
+            UploadError::Storage(e) => return e.status(),
+        }
+    }
+}
+
+// the offset a client has to resume from is told by a `HEAD`, not by the error
+impl IntoResponse for UploadError {
+    fn into_response(self) -> Response {
+        return Response::new(self.status());
+    }
+}
+
+/// An upload in progress, as far as it got.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Upload {
+    pub id: String,
+    // the file it becomes once complete
+    pub target: String,
+    pub length: u64,
+    pub offset: u64,
+    pub expires: SystemTime,
+}
+
+impl Upload {
+    pub fn is_complete(&self) -> bool {
+        return self.offset == self.length;
+    }
+}
+
+/// Uploads that can be resumed after the connection drops, kept as partial files in the storage
+/// until they are complete and moved over their target in one go.
+///
+/// What an upload is for is stored next to its data, so uploads survive a restart of the server
+/// as long as the storage does.
+#[derive(Debug)]
+pub struct Uploads {
+    expiry: Duration,
+    // one per upload id, its offset is checked and appended to without anyone else appending in
+    // between while other uploads go on
+    locks: NameLocks,
+}
+
+impl Uploads {
+    pub fn new(expiry: Duration) -> Uploads {
+        return Uploads {
+            expiry,
+            locks: NameLocks::new(),
+        };
+    }
+
+    /// Starts an upload of `length` bytes to become the file `target`. Expired uploads are
+    /// removed first, so abandoned ones do not pile up.
+    pub fn create(
+        &self,
+        storage: &dyn Storage,
+        target: &str,
+        length: u64,
+    ) -> Result<Upload, UploadError> {
+        self.sweep(storage);
+        // a target the storage would refuse is refused before any data is sent, as is one the
+        // finished upload could not be moved to
+        let existing = storage.stat(target)?;
+        let parts = target_parts(target)?;
+        if existing.is_some_and(|stat| return stat.is_dir) {
+            return Err(UploadError::Target(target.to_string()));
+        }
+        let parent = parts[..parts.len() - 1].join("/");
+        if !parent.is_empty()
+            && !storage
+                .stat(&parent)?
+                .is_some_and(|stat| return stat.is_dir)
+        {
+            return Err(UploadError::Parent(parent));
+        }
+        let id = new_id();
+        // a sweep in between the two writes would take the upload for an interrupted one
+        let lock = self.locks.lock(&id);
+        let _lock = lock.lock().unwrap_or_else(|e| return e.into_inner());
+        let info = format!("{}\n{}", length, target);
+        storage.write(&info_name(&id), info.as_bytes())?;
+        storage.write(&part_name(&id), b"")?;
+        let upload = Upload {
+            id,
+            target: target.to_string(),
+            length,
+            offset: 0,
+            expires: SystemTime::now() + self.expiry,
+        };
+        if upload.is_complete() {
+            self.complete(storage, &upload)?;
+        }
+        return Ok(upload);
+    }
+
+    pub fn get(&self, storage: &dyn Storage, id: &str) -> Result<Upload, UploadError> {
+        let lock = self.locks.lock(id);
+        let _lock = lock.lock().unwrap_or_else(|e| return e.into_inner());
+        return self.get_locked(storage, id);
+    }
+
+    /// Adds `data` at `offset`, which has to be where the upload is so a chunk sent twice is not
+    /// stored twice. The upload that `data` completes is moved over its target at once, and
+    /// what that did to the target is returned with it.
+    pub fn append(
+        &self,
+        storage: &dyn Storage,
+        id: &str,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<(Upload, Option<Written>), UploadError> {
+        let lock = self.locks.lock(id);
+        let _lock = lock.lock().unwrap_or_else(|e| return e.into_inner());
+        let mut upload = self.get_locked(storage, id)?;
+        if offset != upload.offset {
+            return Err(UploadError::Offset {
+                expected: upload.offset,
+                given: offset,
+            });
+        }
+        if upload.length - upload.offset < data.len() as u64 {
+            return Err(UploadError::TooLong(upload.length));
+        }
+        upload.offset = storage.append(&part_name(id), data)?;
+        upload.expires = SystemTime::now() + self.expiry;
+        if !upload.is_complete() {
+            return Ok((upload, None));
+        }
+        let written = self.complete(storage, &upload)?;
+        return Ok((upload, Some(written)));
+    }
+
+    /// Stops an upload and drops what was sent of it.
+    pub fn cancel(&self, storage: &dyn Storage, id: &str) -> Result<(), UploadError> {
+        let lock = self.locks.lock(id);
+        let _lock = lock.lock().unwrap_or_else(|e| return e.into_inner());
+        self.get_locked(storage, id)?;
+        storage.delete(&part_name(id))?;
+        storage.delete(&info_name(id))?;
+        return Ok(());
+    }
+
+    /// Removes expired uploads and the leftovers of ones that were interrupted while completing,
+    /// answers how many were removed.
+    pub fn sweep(&self, storage: &dyn Storage) -> usize {
+        let Ok(entries) = storage.list("") else {
+            return 0;
+        };
+        let mut removed = 0;
+        for entry in entries {
+            let Some(id) = entry
+                .name
+                .strip_prefix(PREFIX)
+                .and_then(|name| return name.strip_suffix(".info"))
+            else {
+                continue;
+            };
+            // an upload someone is working on is in use, whatever its age
+            let lock = self.locks.lock(id);
+            let _lock = match lock.try_lock() {
+                Ok(guard) => guard,
+                Err(TryLockError::Poisoned(e)) => e.into_inner(),
+                Err(TryLockError::WouldBlock) => continue,
+            };
+            if let Err(UploadError::NotFound(_)) = self.get_locked(storage, id) {
+                let _ = storage.delete(&part_name(id));
+                let _ = storage.delete(&info_name(id));
+                removed += 1;
+            }
+        }
+        return removed;
+    }
+
+    // expired uploads are not found, even before they are swept
+    fn get_locked(&self, storage: &dyn Storage, id: &str) -> Result<Upload, UploadError> {
+        let not_found = || return UploadError::NotFound(id.to_string());
+        if !is_id(id) {
+            return Err(not_found());
+        }
+        let (Some(info), Some(part)) =
+            (storage.stat(&info_name(id))?, storage.stat(&part_name(id))?)
+        else {
+            return Err(not_found());
+        };
+        let info = storage.read_range(&info_name(id), 0, info.len)?;
+        let info = String::from_utf8(info).map_err(|_| return not_found())?;
+        let Some((length, target)) = info.split_once('\n') else {
+            return Err(not_found());
+        };
+        let length = length.parse::<u64>().map_err(|_| return not_found())?;
+        // the info is only trusted to name a target `create` would have taken
+        if target_parts(target).is_err() {
+            return Err(not_found());
+        }
+        let expires = part.modified.unwrap_or(SystemTime::UNIX_EPOCH) + self.expiry;
+        if SystemTime::now() >= expires {
+            return Err(not_found());
+        }
+        return Ok(Upload {
+            id: id.to_string(),
+            target: target.to_string(),
+            length,
+            offset: part.len,
+            expires,
+        });
+    }
+
+    fn complete(&self, storage: &dyn Storage, upload: &Upload) -> Result<Written, UploadError> {
+        let written = storage.rename(&part_name(&upload.id), &upload.target)?;
+        // a leftover info without its part is swept later
+        let _ = storage.delete(&info_name(&upload.id));
+        return Ok(written);
+    }
+}
+
+// the components of a name an upload may end up at, dot files are kept for the server itself
+fn target_parts(target: &str) -> Result<Vec<&str>, UploadError> {
+    let parts = safe_path::components(target)?;
+    if parts.is_empty() || storage::is_hidden(target) {
+        return Err(UploadError::Target(target.to_string()));
+    }
+    return Ok(parts);
+}
+
+fn part_name(id: &str) -> String {
+    return format!("{}{}.part", PREFIX, id);
+}
+
+fn info_name(id: &str) -> String {
+    return format!("{}{}.info", PREFIX, id);
+}
+
+// ids end up in file names, anything else is not an id
+fn is_id(id: &str) -> bool {
+    return id.len() == 32 && id.bytes().all(|b| return b.is_ascii_hexdigit());
+}
+
+/// A fresh id of 32 hex digits, also used for other files that must not collide.
+// uploads are only as private as their ids, so they come from the system's random source.
+// Without one the keys of `RandomState` are used, random per process but not made for secrets
+pub fn new_id() -> String {
+    let mut bytes = [0u8; 16];
+    let read = File::open("/dev/urandom").and_then(|mut random| {
+        return random.read_exact(&mut bytes);
+    });
+    if read.is_err() {
+        let random = || return RandomState::new().build_hasher().finish().to_be_bytes();
+        bytes[..8].copy_from_slice(&random());
+        bytes[8..].copy_from_slice(&random());
+    }
+    return bytes.iter().map(|b| return format!("{:02x}", b)).collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::example_server::memory_storage::MemoryStorage;
+    use crate::example_server::uploads::{is_id, new_id, Uploads, DEFAULT_EXPIRY};
+    use std::time::Duration;
+
+    #[test]
+    fn uploads_ids() {
+        let (a, b) = (new_id(), new_id());
+        assert!(is_id(&a) && is_id(&b));
+        assert_ne!(a, b);
+        assert!(!is_id("../0123456789abcdef0123456789ab"));
+    }
+
+    #[test]
+    fn uploads_lock_one_upload_at_a_time() {
+        let storage = MemoryStorage::new();
+        let uploads = Uploads::new(DEFAULT_EXPIRY);
+        let first = uploads.create(&storage, "a.txt", 2).unwrap();
+        let second = uploads.create(&storage, "b.txt", 2).unwrap();
+        let lock = uploads.locks.lock(&first.id);
+        let held = lock.lock().unwrap();
+        // the other upload goes on meanwhile
+        let (appended, _) = uploads.append(&storage, &second.id, 0, b"b").unwrap();
+        assert_eq!(appended.offset, 1);
+        drop(held);
+        drop(lock);
+        // locks nobody holds are dropped when the next one is taken
+        uploads.get(&storage, &second.id).unwrap();
+        assert_eq!(uploads.locks.kept(), 1);
+
+        // an expired upload is only swept once nobody holds it
+        let uploads = Uploads::new(Duration::ZERO);
+        let lock = uploads.locks.lock(&first.id);
+        let held = lock.lock().unwrap();
+        assert_eq!(uploads.sweep(&storage), 1);
+        drop(held);
+        assert_eq!(uploads.sweep(&storage), 1);
+    }
+}
@@ -0,0 +1,5 @@
+#![deny(clippy::implicit_return)]
+#![allow(clippy::needless_return)]
+
+// the routing and HTTP pieces, the binary is an example server built on them
+pub mod core;
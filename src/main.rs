@@ -1,28 +1,92 @@
 #![deny(clippy::implicit_return)]
 #![allow(clippy::needless_return)]
 
-mod core;
+use http_server_starter_rust::core;
 mod example_server;
 
 //use core::route_table::RouteTable; // alternative implementation
+//use core::route_radix::RouteRadix; // alternative implementation for large route sets
 use core::route_trie::RouteTrie;
 
+use core::conditional::conditional_get;
+use core::extract::extract;
+use core::introspect::describe;
+use core::request::HttpMethod;
+use core::route_group::RouteGroup;
 use core::router::Router;
 
-use example_server::context::get_context;
+use example_server::context::{
+    autoindex, base_directory, get_context, index_file, static_directory,
+};
+use example_server::file_handler::FileHandler;
+use example_server::middleware;
 use example_server::routes;
+use example_server::static_files::StaticFiles;
 
 fn main() {
     let addr = "127.0.0.1:4221";
     let context = get_context();
+    let route_names = context.route_names.clone();
     // panic-ing here is fine since an invalid router should not be recoverable
     let mut app = Router::<_, RouteTrie<_>>::new(addr, context).unwrap();
-    app.handle("/", routes::index).unwrap();
-    app.handle("/echo/{msg}", routes::echo).unwrap();
-    app.handle("/user-agent", routes::user_agent).unwrap();
-    app.handle("/files/{file}", routes::files).unwrap();
+    app.share_route_names(route_names).unwrap();
+    let mut pages = RouteGroup::new();
+    pages.layer(middleware::get_only);
+    pages.layer(conditional_get);
+    pages.handle("/", describe(routes::index).method(HttpMethod::Get));
+    let echo = describe(extract(routes::echo))
+        .summary("Answers with the message from the path")
+        .method(HttpMethod::Get)
+        .response_content_type("text/plain");
+    pages.handle("/echo/{msg}", echo);
+    let user_agent = describe(extract(routes::user_agent))
+        .summary("Answers with the User-Agent header of the request")
+        .method(HttpMethod::Get)
+        .response_content_type("text/plain");
+    pages.handle("/user-agent", user_agent);
+    app.group(pages).unwrap();
+    let files = describe(routes::files)
+        .summary("Reads, stores or deletes a file of the served directory")
+        .method(HttpMethod::Get)
+        .method(HttpMethod::Head)
+        .method(HttpMethod::Post)
+        .method(HttpMethod::Put)
+        .method(HttpMethod::Delete)
+        .request_content_type("application/octet-stream")
+        .response_content_type("application/octet-stream");
+    app.handle_named("files", "/files/{file}", files).unwrap();
+    let form_files = describe(routes::form_files)
+        .summary("Stores the files of an HTML form under their own names")
+        .method(HttpMethod::Post)
+        .request_content_type("multipart/form-data")
+        .response_content_type("application/json");
+    app.handle("/files", form_files).unwrap();
+    let create_upload = describe(routes::create_upload)
+        .summary("Starts a resumable upload of a file")
+        .method(HttpMethod::Post);
+    app.handle("/uploads", create_upload).unwrap();
+    let upload = describe(routes::upload)
+        .summary("Reports, continues or cancels a resumable upload")
+        .method(HttpMethod::Head)
+        .method(HttpMethod::Patch)
+        .method(HttpMethod::Delete)
+        .request_content_type("application/offset+octet-stream");
+    app.handle_named("upload", "/uploads/{id}", upload).unwrap();
+    // pages only come from a directory of their own, what clients upload is never one
+    let public = match static_directory() {
+        Some(pages) => StaticFiles::new(FileHandler::new(pages).unwrap())
+            .index_file(Some(&index_file()))
+            .sniff(true),
+        None => StaticFiles::new(FileHandler::new(base_directory()).unwrap())
+            .index_file(None)
+            .attachments(true),
+    };
+    let public = public.autoindex(autoindex());
+    app.mount("/static", public.group()).unwrap();
+    app.openapi("/openapi.json", "http-server-starter-rust", "0.1.0")
+        .unwrap();
 
-    app.run();
+    app.run().unwrap();
 }
 
 // #[allow(dead_code)]